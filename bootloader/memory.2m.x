@@ -3,9 +3,29 @@ MEMORY
   /* NOTE 1 K = 1 KiBi = 1024 bytes */
   BOOT2                             : ORIGIN = 0x10000000, LENGTH = 0x100
   FLASH                             : ORIGIN = 0x10000100, LENGTH = 24K - LENGTH(BOOT2)
-  APPLICATION                       : ORIGIN = 0x10007000, LENGTH = 4096K - LENGTH(BOOT2)
+  APPLICATION                       : ORIGIN = 0x10007000, LENGTH = 1024K - 0x7000
   RAM                               : ORIGIN = 0x20000000, LENGTH = 256K
+  CRASH_REPORT                      : ORIGIN = 0x20040000, LENGTH = 4K
+  /* must match the firmware's memory layout */
+  STAGING                           : ORIGIN = 0x10140000, LENGTH = 768K - 8K
+  SCRATCH                           : ORIGIN = ORIGIN(STAGING) + LENGTH(STAGING), LENGTH = 4K
+  BOOT_STATE                        : ORIGIN = ORIGIN(SCRATCH) + LENGTH(SCRATCH), LENGTH = 4K
 }
 
 __bootloader_application_start = ORIGIN(APPLICATION) - ORIGIN(BOOT2);
 __bootloader_application_end = ORIGIN(APPLICATION) + LENGTH(APPLICATION) - ORIGIN(BOOT2);
+/* the version string in the image header, see shared/src/image.rs */
+__bootloader_application_version = ORIGIN(APPLICATION) + 0xc0 + 16;
+
+__bootloader_staging_start = ORIGIN(STAGING) - ORIGIN(BOOT2);
+__bootloader_staging_end = ORIGIN(STAGING) + LENGTH(STAGING) - ORIGIN(BOOT2);
+__bootloader_scratch_start = ORIGIN(SCRATCH) - ORIGIN(BOOT2);
+__bootloader_state_start = ORIGIN(BOOT_STATE) - ORIGIN(BOOT2);
+
+SECTIONS {
+  /* must match the firmware, which reports crashes left here */
+  .crash_report (NOLOAD) : ALIGN(4)
+  {
+    KEEP(*(.crash_report .crash_report.*));
+  } > CRASH_REPORT
+}
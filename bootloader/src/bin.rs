@@ -1,14 +1,17 @@
 #![no_std]
 #![no_main]
 
+use core::mem::MaybeUninit;
+use core::ptr::addr_of_mut;
 use core::sync::atomic::AtomicU32;
 
 use cortex_m_rt::{exception};
 use embedded_hal::digital::OutputPin;
-use rp2040_hal::{pac, rom_data::reset_to_usb_boot, Sio};
+use rp2040_hal::{pac, rom_data::reset_to_usb_boot, Sio, Watchdog};
 
 #[cfg(feature = "binaryinfo")]
 pub mod binary_info;
+mod slots;
 
 extern "C" {
     static __bootloader_application_start: u32;
@@ -38,6 +41,22 @@ unsafe fn check_bootloader() {
     reset_to_usb_boot(1 << 17, 0);
 }
 
+/// Three short blinks and a pause, a few times over, so that a corrupt image
+/// can be told apart from asking for the usb bootloader
+fn signal_corrupt_image(led: &mut impl OutputPin) {
+    const BLINK_CYCLES: u32 = 1_000_000;
+
+    for _ in 0..3 {
+        for _ in 0..3 {
+            led.set_high().unwrap();
+            cortex_m::asm::delay(BLINK_CYCLES);
+            led.set_low().unwrap();
+            cortex_m::asm::delay(BLINK_CYCLES);
+        }
+        cortex_m::asm::delay(BLINK_CYCLES * 4);
+    }
+}
+
 pub const FLASH_BASE: *const u32 = 0x10000000 as _;
 
 #[rp2040_hal::entry]
@@ -57,6 +76,30 @@ fn main() -> ! {
         check_bootloader();
     }
 
+    let mut on_trial = unsafe { slots::prepare() };
+
+    if on_trial && !unsafe { slots::application_is_valid() } {
+        // no point letting a new image that doesn't check out use its boots
+        unsafe { slots::roll_back() };
+        on_trial = false;
+    }
+
+    if !unsafe { slots::application_is_valid() } {
+        signal_corrupt_image(&mut s);
+        reset_to_usb_boot(1 << 17, 0);
+        unreachable!();
+    }
+
+    if on_trial {
+        // a new image that hangs rather than crashing still needs resetting
+        // to be rolled back, the firmware stops this once it has confirmed
+        // itself. The tick is only roughly right until the firmware sets the
+        // clocks up
+        let mut watchdog = Watchdog::new(pac.WATCHDOG);
+        watchdog.enable_tick_generation(12);
+        watchdog.start(fugit::MicrosDurationU32::secs(8));
+    }
+
     unsafe {
         let p = cortex_m::Peripherals::steal();
         let start = FLASH_BASE as u32 + &__bootloader_application_start as *const u32 as u32;
@@ -66,9 +109,30 @@ fn main() -> ! {
     }
 }
 
-#[no_mangle]
-#[cfg_attr(target_os = "none", link_section = ".HardFault.user")]
-unsafe extern "C" fn HardFault() {
+/// Left for the firmware to report once it boots, see
+/// `firmware/src/crash.rs` for the rest of the layout
+#[link_section = ".crash_report"]
+static mut CRASH_REPORT: MaybeUninit<[u32; 8]> = MaybeUninit::uninit();
+
+// Must match the firmware
+const CRASH_MAGIC: u32 = 0x4352_5348;
+const CRASH_KIND_BOOTLOADER_FAULT: u32 = 3;
+
+#[exception]
+unsafe fn HardFault(frame: &cortex_m_rt::ExceptionFrame) -> ! {
+    // magic, kind, pc, lr, xpsr, then no panic location or message
+    let report = [
+        CRASH_MAGIC,
+        CRASH_KIND_BOOTLOADER_FAULT,
+        frame.pc(),
+        frame.lr(),
+        frame.xpsr(),
+        0,
+        0,
+        0,
+    ];
+    core::ptr::write_volatile(addr_of_mut!(CRASH_REPORT).cast::<[u32; 8]>(), report);
+
     cortex_m::peripheral::SCB::sys_reset();
 }
 
@@ -12,6 +12,8 @@ extern "C" {
     static __sdata: u32;
     static __edata: u32;
     static __sidata: u32;
+    /// The version string in the application's image header
+    static __bootloader_application_version: [u8; 16];
 }
 
 #[link_section = ".bi_header"]
@@ -38,7 +40,7 @@ static MAPPING_TABLE: [MappingTableEntry; 2] = [
 // This is a list of references to our table entries
 #[link_section = ".bi_entries"]
 #[used]
-pub static PICOTOOL_ENTRIES: [Addr; 8] = [
+pub static PICOTOOL_ENTRIES: [Addr; 9] = [
     PROGRAM_NAME.addr(),
     PROGRAM_VERSION.addr(),
     PROGRAM_BUILD_DATE.addr(),
@@ -47,6 +49,7 @@ pub static PICOTOOL_ENTRIES: [Addr; 8] = [
     PROGRAM_FEATURE.addr(),
     PROGRAM_BUILD_ATTRIBUTE.addr(),
     PICO_BOARD.addr(),
+    APPLICATION_VERSION.addr(),
 ];
 
 static PROGRAM_NAME: IdAndString = program_name(concat!(env!("CARGO_PKG_NAME"), "\0"));
@@ -86,3 +89,11 @@ static PICO_BOARD: IdAndString = custom_string(
     ID_RP_PICO_BOARD,
     concat!("dilemma-v2", "\0"),
 );
+
+// Points into the application rather than the bootloader, so picotool shows
+// the version of whatever firmware is installed
+static APPLICATION_VERSION: IdAndString = custom_string(
+    TAG_RASPBERRY_PI,
+    ID_RP_PROGRAM_BUILD_ATTRIBUTE,
+    unsafe { core::str::from_utf8_unchecked(&__bootloader_application_version) },
+);
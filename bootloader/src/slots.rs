@@ -0,0 +1,335 @@
+//! Application slots, firmware updates and rollback
+//!
+//! Images always run from the application slot (slot A), the staging area
+//! (slot B) holds the other one. The firmware stages an update in slot B after
+//! its first sector, then writes a header of three little endian words (magic,
+//! length and crc32 of the image) to that first sector. The bootloader
+//! installs it by swapping the two slots a sector at a time, which leaves the
+//! previous image in slot B.
+//!
+//! The new image is then on trial, it gets [`MAX_ATTEMPTS`] boots to confirm
+//! itself in the boot state record, after which the slots are swapped back.
+//! Swaps go through a scratch sector and record each step in the boot state,
+//! so a swap interrupted by a reset carries on where it left off.
+
+use rp2040_hal::rom_data;
+
+use crate::FLASH_BASE;
+
+extern "C" {
+    static __bootloader_application_start: u32;
+    static __bootloader_application_end: u32;
+    static __bootloader_staging_start: u32;
+    static __bootloader_staging_end: u32;
+    static __bootloader_scratch_start: u32;
+    static __bootloader_state_start: u32;
+}
+
+/// Must match `UPDATE_MAGIC` in the firmware
+const UPDATE_MAGIC: u32 = 0x5550_4454;
+
+const SECTOR_SIZE: usize = 4096;
+const PAGE_SIZE: usize = 256;
+const SECTOR_ERASE_CMD: u8 = 0x20;
+
+// Must match `shared::image`
+const IMAGE_HEADER_MAGIC: u32 = u32::from_le_bytes(*b"RDim");
+const IMAGE_HEADER_OFFSET: u32 = 0xc0;
+const IMAGE_HEADER_LEN: usize = 32;
+
+/// Boots an image gets to confirm itself before it's rolled back
+const MAX_ATTEMPTS: u32 = 3;
+
+// Layout of the boot state record, these must match the firmware. Flags are
+// words which are set by programming them to zero, and progress is recorded
+// by clearing one bit per step, so the record is only erased when an install
+// starts.
+const STATE_MAGIC: u32 = 0x5354_4154;
+const STATE_MAGIC_OFFSET: u32 = 0;
+/// Number of sectors being swapped
+const STATE_SECTORS: u32 = 4;
+const STATE_INSTALLED: u32 = 256;
+const STATE_ATTEMPTS: u32 = 260;
+const STATE_CONFIRMED: u32 = 264;
+const STATE_ROLLED_BACK: u32 = 268;
+const STATE_INSTALL_PROGRESS: u32 = 512;
+const STATE_ROLLBACK_PROGRESS: u32 = 1024;
+
+/// The rom flash functions and a copy of boot2, looked up before xip is turned
+/// off as the lookup itself reads from flash
+struct FlashFns {
+    connect_internal_flash: extern "C" fn(),
+    flash_exit_xip: extern "C" fn(),
+    flash_range_erase: extern "C" fn(u32, usize, u32, u8),
+    flash_range_program: extern "C" fn(u32, *const u8, usize),
+    flash_flush_cache: extern "C" fn(),
+    boot2: [u32; 64],
+}
+
+impl FlashFns {
+    unsafe fn lookup() -> Self {
+        let mut boot2 = [0; 64];
+        core::ptr::copy_nonoverlapping(FLASH_BASE, boot2.as_mut_ptr(), boot2.len());
+
+        Self {
+            connect_internal_flash: core::mem::transmute(rom_data::connect_internal_flash::ptr()),
+            flash_exit_xip: core::mem::transmute(rom_data::flash_exit_xip::ptr()),
+            flash_range_erase: core::mem::transmute(rom_data::flash_range_erase::ptr()),
+            flash_range_program: core::mem::transmute(rom_data::flash_range_program::ptr()),
+            flash_flush_cache: core::mem::transmute(rom_data::flash_flush_cache::ptr()),
+            boot2,
+        }
+    }
+}
+
+/// Erase a sector and/or program some pages, runs from ram as flash can't be
+/// read while this happens
+///
+/// Xip is set back up by running boot2 again, so that the application gets
+/// the same fast reads it would after a reset.
+#[link_section = ".data.ram_func"]
+#[inline(never)]
+unsafe fn flash_op(fns: &FlashFns, erase: Option<u32>, program: Option<(u32, &[u8])>) {
+    (fns.connect_internal_flash)();
+    (fns.flash_exit_xip)();
+    if let Some(offset) = erase {
+        (fns.flash_range_erase)(offset, SECTOR_SIZE, SECTOR_SIZE as u32, SECTOR_ERASE_CMD);
+    }
+    if let Some((offset, data)) = program {
+        (fns.flash_range_program)(offset, data.as_ptr(), data.len());
+    }
+    (fns.flash_flush_cache)();
+
+    let boot2: extern "C" fn() = core::mem::transmute(fns.boot2.as_ptr() as usize + 1);
+    boot2();
+}
+
+unsafe fn flash_slice(offset: u32, len: usize) -> &'static [u8] {
+    core::slice::from_raw_parts((FLASH_BASE as *const u8).add(offset as usize), len)
+}
+
+unsafe fn read_word(offset: u32) -> u32 {
+    core::ptr::read_volatile(FLASH_BASE.add(offset as usize / 4))
+}
+
+/// Program a single word, only bits that are still set can be changed
+unsafe fn program_word(fns: &FlashFns, offset: u32, value: u32) {
+    let mut page = [0xff; PAGE_SIZE];
+    let at = offset as usize % PAGE_SIZE;
+    page[at..at + 4].copy_from_slice(&value.to_le_bytes());
+
+    flash_op(fns, None, Some((offset - at as u32, &page)));
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+/// Same crc32 as the firmware and host use, over the parts one after another
+fn crc32(parts: &[&[u8]]) -> u32 {
+    !parts.iter().flat_map(|p| p.iter()).fold(!0u32, |crc, &b| {
+        CRC_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+struct Layout {
+    app: u32,
+    app_len: usize,
+    staged_header: u32,
+    staged_image: u32,
+    staged_capacity: usize,
+    scratch: u32,
+    state: u32,
+}
+
+impl Layout {
+    unsafe fn get() -> Self {
+        let staging = &__bootloader_staging_start as *const u32 as u32;
+        let staging_end = &__bootloader_staging_end as *const u32 as u32;
+
+        let app = &__bootloader_application_start as *const u32 as u32;
+        let app_end = &__bootloader_application_end as *const u32 as u32;
+
+        Self {
+            app,
+            app_len: (app_end - app) as usize,
+            staged_header: staging,
+            staged_image: staging + SECTOR_SIZE as u32,
+            staged_capacity: (staging_end - staging) as usize - SECTOR_SIZE,
+            scratch: &__bootloader_scratch_start as *const u32 as u32,
+            state: &__bootloader_state_start as *const u32 as u32,
+        }
+    }
+
+    unsafe fn state_word(&self, field: u32) -> u32 {
+        read_word(self.state + field)
+    }
+
+    unsafe fn state_flag(&self, field: u32) -> bool {
+        self.state_word(field) != 0xffff_ffff
+    }
+
+    unsafe fn set_state_flag(&self, fns: &FlashFns, field: u32) {
+        program_word(fns, self.state + field, 0);
+    }
+
+    /// Number of bits cleared from the start of a progress bitmap
+    unsafe fn progress(&self, field: u32) -> u32 {
+        let mut done = 0;
+        loop {
+            let word = self.state_word(field + done / 32 * 4);
+            let cleared = word.trailing_zeros();
+            done += cleared;
+            if cleared < 32 {
+                return done;
+            }
+        }
+    }
+
+    unsafe fn record_progress(&self, fns: &FlashFns, field: u32, step: u32) {
+        program_word(fns, self.state + field + step / 32 * 4, !(1 << (step % 32)));
+    }
+}
+
+/// Get the application slot ready to boot, returning whether the image in it
+/// is on trial and needs the watchdog watching it
+pub unsafe fn prepare() -> bool {
+    let layout = Layout::get();
+    let fns = FlashFns::lookup();
+
+    let in_progress = layout.state_word(STATE_MAGIC_OFFSET) == STATE_MAGIC
+        && !layout.state_flag(STATE_CONFIRMED)
+        && !layout.state_flag(STATE_ROLLED_BACK);
+
+    if !in_progress && !begin_install(&layout, &fns) {
+        return false;
+    }
+
+    let sectors = layout.state_word(STATE_SECTORS);
+
+    if !layout.state_flag(STATE_INSTALLED) {
+        swap(&layout, &fns, sectors, STATE_INSTALL_PROGRESS);
+        layout.set_state_flag(&fns, STATE_INSTALLED);
+    }
+
+    let attempts = layout.state_word(STATE_ATTEMPTS).trailing_zeros();
+    if attempts >= MAX_ATTEMPTS {
+        roll_back_trial(&layout, &fns);
+        return false;
+    }
+
+    layout.record_progress(&fns, STATE_ATTEMPTS, attempts);
+    true
+}
+
+/// Give up on the image on trial without waiting for it to use up its boots,
+/// for one that doesn't pass [`application_is_valid`]
+pub unsafe fn roll_back() {
+    roll_back_trial(&Layout::get(), &FlashFns::lookup());
+}
+
+unsafe fn roll_back_trial(layout: &Layout, fns: &FlashFns) {
+    let sectors = layout.state_word(STATE_SECTORS);
+    swap(layout, fns, sectors, STATE_ROLLBACK_PROGRESS);
+    layout.set_state_flag(fns, STATE_ROLLED_BACK);
+}
+
+/// Check that the application slot holds an intact image, going by the header
+/// the firmware build puts after the vector table
+pub unsafe fn application_is_valid() -> bool {
+    let layout = Layout::get();
+    let header = layout.app + IMAGE_HEADER_OFFSET;
+    let crc_at = header + 8;
+
+    let magic = read_word(header);
+    let len = read_word(header + 4) as usize;
+    let crc = read_word(crc_at);
+
+    if magic != IMAGE_HEADER_MAGIC
+        || len < IMAGE_HEADER_OFFSET as usize + IMAGE_HEADER_LEN
+        || len > layout.app_len
+    {
+        return false;
+    }
+
+    // the crc was taken with its own field erased
+    let before = flash_slice(layout.app, (crc_at - layout.app) as usize);
+    let after = flash_slice(crc_at + 4, len - before.len() - 4);
+
+    crc32(&[before, &[0xff; 4], after]) == crc
+}
+
+/// Start installing a staged update if there is one
+unsafe fn begin_install(layout: &Layout, fns: &FlashFns) -> bool {
+    let header = flash_slice(layout.staged_header, 12);
+    let [magic, len, crc] =
+        [0, 1, 2].map(|i| u32::from_le_bytes(header[i * 4..i * 4 + 4].try_into().unwrap()));
+
+    if magic != UPDATE_MAGIC {
+        return false;
+    }
+
+    let len = len as usize;
+
+    // the firmware checked this before staging it, so the staging area has
+    // been damaged since
+    if len > layout.staged_capacity.min(layout.app_len)
+        || crc32(&[flash_slice(layout.staged_image, len)]) != crc
+    {
+        flash_op(fns, Some(layout.staged_header), None);
+        return false;
+    }
+
+    // sectors past the end of the new image are left alone, so the tail of
+    // the previous image is still in place if it's rolled back to
+    let sectors = len.div_ceil(SECTOR_SIZE) as u32;
+
+    let mut page = [0xff; PAGE_SIZE];
+    for (i, word) in [STATE_MAGIC, sectors, len as u32, crc]
+        .into_iter()
+        .enumerate()
+    {
+        page[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    flash_op(fns, Some(layout.state), Some((layout.state, &page)));
+
+    // the boot state takes over from here
+    flash_op(fns, Some(layout.staged_header), None);
+
+    true
+}
+
+/// Swap the first `sectors` sectors of the two slots, carrying on from the
+/// progress recorded in `progress`
+unsafe fn swap(layout: &Layout, fns: &FlashFns, sectors: u32, progress: u32) {
+    let mut buf = [0u8; SECTOR_SIZE];
+
+    for step in layout.progress(progress)..sectors * 3 {
+        let offset = step / 3 * SECTOR_SIZE as u32;
+        let (from, to) = match step % 3 {
+            0 => (layout.app + offset, layout.scratch),
+            1 => (layout.staged_image + offset, layout.app + offset),
+            _ => (layout.scratch, layout.staged_image + offset),
+        };
+
+        buf.copy_from_slice(flash_slice(from, SECTOR_SIZE));
+        flash_op(fns, Some(to), Some((to, &buf)));
+        layout.record_progress(fns, progress, step);
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read, Write};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{bail, Context, Result};
+use postcard::accumulator::{CobsAccumulator, FeedResult};
+use serialport::SerialPort;
+use shared::cmd::{CmdOrAck, Command};
+use shared::crash::CrashReport;
+use shared::device_to_host::{DeviceToHost, DeviceToHostMsg, Reply, RpcError};
+use shared::handshake::{DeviceInfo, PROTOCOL_VERSION};
+use shared::host_to_device::{HostToDevice, HostToDeviceMsg, RequestId};
+use shared::side::KeyboardSide;
+
+/// Must match the accumulator size used by the firmware's eventer
+const BUF_SIZE: usize = 128;
+
+const ACK_TIMEOUT: Duration = Duration::from_millis(20);
+const MAX_RETRIES: usize = 16;
+const HELLO_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The response of one side to a request, `None` if it didn't respond in time
+pub type SideResponse = (KeyboardSide, Option<Result<Reply, RpcError>>);
+
+pub const USB_VID: u16 = 0x2e8a;
+pub const USB_PID: u16 = 0x000a;
+
+/// Host side of the CDC framing spoken by `messages::transmissions::eventer`
+pub struct Link {
+    port: Box<dyn SerialPort>,
+    accumulator: CobsAccumulator<BUF_SIZE>,
+    next_id: u8,
+    next_request_id: RequestId,
+    last_seen_id: Option<u8>,
+    acks: VecDeque<bool>,
+    received: VecDeque<DeviceToHost>,
+}
+
+impl Link {
+    pub fn open(path: &str) -> Result<Self> {
+        let port = serialport::new(path, 115200)
+            .timeout(Duration::from_millis(5))
+            .open()
+            .with_context(|| format!("Couldn't open serial port {path}"))?;
+
+        // the device deduplicates on the last id it saw, so don't start from
+        // zero each time or the first message of a session might get eaten
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+
+        Ok(Self {
+            port,
+            accumulator: CobsAccumulator::new(),
+            next_id: nanos as u8 & 0b1111111,
+            next_request_id: (nanos >> 8) as RequestId,
+            last_seen_id: None,
+            acks: VecDeque::new(),
+            received: VecDeque::new(),
+        })
+    }
+
+    /// Say hello to both halves and check that they speak our protocol,
+    /// returning what each half told us about itself
+    ///
+    /// The device ignores everything else until this is done
+    pub fn handshake(&mut self) -> Result<Vec<(KeyboardSide, DeviceInfo)>> {
+        let id = self.next_request_id;
+        self.next_request_id = self.next_request_id.wrapping_add(1);
+
+        self.send(HostToDevice {
+            id,
+            target_side: None,
+            msg: HostToDeviceMsg::Hello {
+                protocol_version: PROTOCOL_VERSION,
+            },
+        })?;
+
+        let mut infos: Vec<(KeyboardSide, DeviceInfo)> = Vec::new();
+        let mut unrelated = VecDeque::new();
+        let deadline = Instant::now() + HELLO_TIMEOUT;
+
+        while infos.len() < 2 {
+            let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+                break;
+            };
+            let Some(msg) = self.recv(remaining)? else {
+                break;
+            };
+
+            match msg.msg {
+                DeviceToHostMsg::Hello(info) if infos.iter().all(|(s, _)| *s != msg.from_side) => {
+                    infos.push((msg.from_side, info));
+                }
+                _ => unrelated.push_back(msg),
+            }
+        }
+
+        unrelated.append(&mut self.received);
+        self.received = unrelated;
+
+        if infos.is_empty() {
+            bail!("The keyboard didn't respond to the handshake, is the firmware too old?");
+        }
+
+        for (side, info) in &infos {
+            if !info.is_compatible() {
+                bail!(
+                    "The {side:?} half runs firmware {} speaking protocol v{}, \
+                     but this tool speaks v{PROTOCOL_VERSION}. Update whichever is older",
+                    info.firmware_version,
+                    info.protocol_version,
+                );
+            }
+        }
+
+        infos.sort_by_key(|(side, _)| side.is_right());
+
+        Ok(infos)
+    }
+
+    /// Take the crash reports that came in so far, each half sends one before
+    /// its hello if it was reset by a crash
+    pub fn take_crash_reports(&mut self) -> Vec<(KeyboardSide, CrashReport)> {
+        let mut reports = Vec::new();
+
+        self.received.retain(|msg| match &msg.msg {
+            DeviceToHostMsg::CrashReport(report) => {
+                reports.push((msg.from_side, report.clone()));
+                false
+            }
+            _ => true,
+        });
+
+        reports
+    }
+
+    /// Find the first serial port that looks like a dilemma
+    pub fn detect() -> Result<String> {
+        let ports = serialport::available_ports().context("Couldn't list serial ports")?;
+
+        ports
+            .into_iter()
+            .find(|p| {
+                matches!(&p.port_type, serialport::SerialPortType::UsbPort(info)
+                         if info.vid == USB_VID && info.pid == USB_PID)
+            })
+            .map(|p| p.port_name)
+            .context("Couldn't find a connected keyboard, pass --port explicitly")
+    }
+
+    /// Reliably send a message, retrying until the device acks it
+    pub fn send(&mut self, msg: HostToDevice) -> Result<()> {
+        let id = self.next_id;
+        self.next_id = (self.next_id + 1) & 0b1111111;
+
+        let frame = postcard::to_stdvec_cobs(&CmdOrAck::Cmd(Command::new_reliable(msg, id)))?;
+        let mut timeout = ACK_TIMEOUT;
+
+        for _ in 0..MAX_RETRIES {
+            self.acks.clear();
+            self.port.write_all(&frame)?;
+
+            let deadline = Instant::now() + timeout;
+            while Instant::now() < deadline {
+                match self.acks.pop_front() {
+                    Some(true) => return Ok(()),
+                    Some(false) => break,
+                    None => self.poll()?,
+                }
+            }
+
+            timeout += ACK_TIMEOUT / 2;
+        }
+
+        bail!("Device didn't acknowledge message after {MAX_RETRIES} attempts")
+    }
+
+    /// Send a request and wait for the response from each side it targets
+    pub fn request(
+        &mut self,
+        target_side: Option<KeyboardSide>,
+        msg: HostToDeviceMsg,
+        timeout: Duration,
+    ) -> Result<Vec<SideResponse>> {
+        let id = self.next_request_id;
+        self.next_request_id = self.next_request_id.wrapping_add(1);
+
+        self.send(HostToDevice {
+            id,
+            target_side,
+            msg,
+        })?;
+
+        let mut responses: Vec<_> = match target_side {
+            Some(side) => vec![(side, None)],
+            None => vec![(KeyboardSide::Left, None), (KeyboardSide::Right, None)],
+        };
+        let mut unrelated = VecDeque::new();
+        let deadline = Instant::now() + timeout;
+
+        while responses.iter().any(|(_, r)| r.is_none()) {
+            let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+                break;
+            };
+            let Some(msg) = self.recv(remaining)? else {
+                break;
+            };
+
+            let slot = responses
+                .iter_mut()
+                .find(|(side, r)| *side == msg.from_side && r.is_none());
+
+            match (msg.msg, slot) {
+                (
+                    DeviceToHostMsg::Response {
+                        id: resp_id,
+                        result,
+                    },
+                    Some((_, slot)),
+                ) if resp_id == id => {
+                    *slot = Some(result);
+                }
+                (other, _) => unrelated.push_back(DeviceToHost {
+                    from_side: msg.from_side,
+                    msg: other,
+                }),
+            }
+        }
+
+        // leave anything else (such as logs) to be picked up by `recv`
+        unrelated.append(&mut self.received);
+        self.received = unrelated;
+
+        if responses.iter().all(|(_, r)| r.is_none()) {
+            bail!("Timed out waiting for a response to request {id}");
+        }
+
+        Ok(responses)
+    }
+
+    /// Wait up to `timeout` for a message from the device
+    pub fn recv(&mut self, timeout: Duration) -> Result<Option<DeviceToHost>> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if let Some(msg) = self.received.pop_front() {
+                return Ok(Some(msg));
+            }
+
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+
+            self.poll()?;
+        }
+    }
+
+    fn poll(&mut self) -> Result<()> {
+        let mut buf = [0u8; BUF_SIZE];
+        let n = match self.port.read(&mut buf) {
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::TimedOut => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut window = &buf[..n];
+
+        while !window.is_empty() {
+            window = match self.accumulator.feed::<CmdOrAck<DeviceToHost>>(window) {
+                FeedResult::Consumed => break,
+                FeedResult::OverFull(remaining) => remaining,
+                FeedResult::DeserError(remaining) => {
+                    self.write_frame(&CmdOrAck::<HostToDevice>::Nack)?;
+                    remaining
+                }
+                FeedResult::Success { data, remaining } => {
+                    self.handle(data)?;
+                    remaining
+                }
+            };
+        }
+
+        Ok(())
+    }
+
+    fn handle(&mut self, data: CmdOrAck<DeviceToHost>) -> Result<()> {
+        match data {
+            CmdOrAck::Cmd(c) => {
+                if !c.validate() {
+                    return self.write_frame(&CmdOrAck::<HostToDevice>::Nack);
+                }
+
+                if c.command_seq.reliable() {
+                    self.write_frame(&CmdOrAck::<HostToDevice>::Ack)?;
+                }
+
+                // unreliable messages are never retransmitted, so only
+                // deduplicate the reliable ones
+                if !c.command_seq.reliable() || Some(c.command_seq.id()) != self.last_seen_id {
+                    if c.command_seq.reliable() {
+                        self.last_seen_id = Some(c.command_seq.id());
+                    }
+                    self.received.push_back(c.cmd);
+                }
+            }
+            CmdOrAck::Ack => self.acks.push_back(true),
+            CmdOrAck::Nack => self.acks.push_back(false),
+            // only sent between the halves
+            CmdOrAck::AckUpto { .. } | CmdOrAck::Sync { .. } => {}
+        }
+
+        Ok(())
+    }
+
+    fn write_frame(&mut self, frame: &CmdOrAck<HostToDevice>) -> Result<()> {
+        let buf = postcard::to_stdvec_cobs(frame)?;
+        self.port.write_all(&buf)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,756 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use shared::backup::{self, BackupHeader, BackupSource, ConfigStatus, BACKUP_CHUNK_LEN};
+use shared::crash::{CrashKind, CrashReport};
+use shared::device_to_host::{DeviceToHost, DeviceToHostMsg, Reply};
+use shared::handshake::DeviceInfo;
+use shared::host_to_device::{AnimationKind, HostToDeviceMsg, MAX_PING_LEN};
+use shared::image::{self, ImageHeader};
+use shared::keymap::{self, Keymap, KEYMAP_CHUNK_LEN, MAX_KEYMAP_LEN};
+use shared::link_stats::{rtt_bucket_limit, LinkKind, LinkStats};
+use shared::settings::Setting;
+use shared::side::KeyboardSide;
+use shared::update::{self, RelayState, UPDATE_CHUNK_LEN};
+
+use crate::link::Link;
+
+mod link;
+
+const REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Serial port of the keyboard, detected from the usb vid/pid if not given
+    #[arg(short, long, global = true)]
+    port: Option<String>,
+
+    /// Only send the command to one half, by default both halves get it
+    #[arg(short, long, global = true)]
+    side: Option<Side>,
+
+    #[command(subcommand)]
+    command: Option<Cmd>,
+}
+
+#[derive(Subcommand)]
+enum Cmd {
+    /// List serial ports that look like a keyboard
+    List,
+    /// Print logs and other messages from both halves
+    Logs,
+    /// Check that the halves are responding
+    Ping {
+        #[arg(default_value = "hello")]
+        payload: String,
+    },
+    /// Print the firmware version
+    Version,
+    /// Print the firmware version, build date and features of each half
+    Info,
+    /// Print how well the links to the host and between the halves are doing
+    Stats,
+    /// Reset the keyboard
+    Reboot,
+    /// Reset into the RP2040 usb bootloader, ready for flashing
+    Bootloader,
+    /// Switch to a different rgb animation
+    Animation { kind: Animation },
+    /// Change the keymap without reflashing
+    #[command(subcommand)]
+    Keymap(KeymapCmd),
+    /// Show or change settings, changes are stored on both halves
+    #[command(subcommand)]
+    Settings(SettingsCmd),
+    /// Back up, restore or wipe the config stored in flash
+    #[command(subcommand)]
+    Config(ConfigCmd),
+    /// Install new firmware without going through the usb bootloader, the
+    /// image must be a raw binary made with `objcopy -O binary`
+    Update { path: PathBuf },
+    /// Fill in the length and crc of the image header in a raw firmware
+    /// binary, which the bootloader checks before booting it. `update` does
+    /// this itself
+    Seal { path: PathBuf },
+}
+
+#[derive(Subcommand)]
+enum KeymapCmd {
+    /// Upload a keymap from a json file, it is stored on both halves
+    Upload { path: PathBuf },
+    /// Go back to the keymap built into the firmware
+    Reset,
+}
+
+#[derive(Subcommand)]
+enum SettingsCmd {
+    /// Print the current settings
+    Get,
+    /// Change a setting
+    Set {
+        #[arg(value_parser = clap::builder::PossibleValuesParser::new(Setting::NAMES))]
+        name: String,
+        value: u16,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCmd {
+    /// Check whether the config could be loaded at boot
+    Status,
+    /// Save the config of one half to a file
+    Backup {
+        path: PathBuf,
+        /// Save the copy kept when the config couldn't be loaded, rather than
+        /// the one in use
+        #[arg(long)]
+        rescued: bool,
+    },
+    /// Replace the config of one half with a backup, then reboot it
+    Restore { path: PathBuf },
+    /// Wipe the config, going back to the defaults, then reboot
+    Reset,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Side {
+    Left,
+    Right,
+}
+
+impl From<Side> for KeyboardSide {
+    fn from(value: Side) -> Self {
+        match value {
+            Side::Left => KeyboardSide::Left,
+            Side::Right => KeyboardSide::Right,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Animation {
+    Snow,
+    Perlin,
+    Rain,
+    Off,
+}
+
+impl From<Animation> for AnimationKind {
+    fn from(value: Animation) -> Self {
+        match value {
+            Animation::Snow => AnimationKind::Snow,
+            Animation::Perlin => AnimationKind::Perlin,
+            Animation::Rain => AnimationKind::Rain,
+            Animation::Off => AnimationKind::Off,
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let target_side = args.side.map(KeyboardSide::from);
+
+    let msg = match args.command.unwrap_or(Cmd::Logs) {
+        Cmd::List => return list(),
+        Cmd::Logs => return logs(&mut open(args.port)?.0),
+        Cmd::Info => {
+            let (mut link, infos) = open(args.port)?;
+            return info(&mut link, infos, target_side);
+        }
+        Cmd::Stats => return stats(&mut open(args.port)?.0, target_side),
+        Cmd::Ping { payload } => {
+            let payload = &payload.as_bytes()[..payload.len().min(MAX_PING_LEN)];
+            HostToDeviceMsg::Ping {
+                payload: heapless::Vec::from_slice(payload).unwrap(),
+            }
+        }
+        Cmd::Version => HostToDeviceMsg::QueryVersion,
+        Cmd::Reboot => HostToDeviceMsg::Reboot,
+        Cmd::Bootloader => HostToDeviceMsg::RebootToBootloader,
+        Cmd::Animation { kind } => HostToDeviceMsg::SetAnimation(kind.into()),
+        Cmd::Keymap(KeymapCmd::Upload { path }) => {
+            return upload_keymap(&mut open(args.port)?.0, target_side, &path)
+        }
+        Cmd::Keymap(KeymapCmd::Reset) => HostToDeviceMsg::ResetKeymap,
+        Cmd::Settings(SettingsCmd::Get) => HostToDeviceMsg::GetSettings,
+        Cmd::Settings(SettingsCmd::Set { name, value }) => {
+            HostToDeviceMsg::SetSetting(setting(&name, value)?)
+        }
+        Cmd::Config(ConfigCmd::Status) => HostToDeviceMsg::ConfigStatus,
+        Cmd::Config(ConfigCmd::Backup { path, rescued }) => {
+            let source = if rescued {
+                BackupSource::Rescued
+            } else {
+                BackupSource::Live
+            };
+            return save_backup(
+                &mut open(args.port)?.0,
+                one_side(target_side)?,
+                source,
+                &path,
+            );
+        }
+        Cmd::Config(ConfigCmd::Restore { path }) => {
+            return restore_backup(&mut open(args.port)?.0, one_side(target_side)?, &path)
+        }
+        Cmd::Config(ConfigCmd::Reset) => HostToDeviceMsg::FactoryReset,
+        Cmd::Update { path } => {
+            let (mut link, infos) = open(args.port)?;
+            return update_firmware(&mut link, infos, target_side, &path);
+        }
+        Cmd::Seal { path } => return seal_firmware(&path),
+    };
+
+    request(&mut open(args.port)?.0, target_side, msg)
+}
+
+/// Open the port and perform the handshake
+fn open(port: Option<String>) -> Result<(Link, Vec<(KeyboardSide, DeviceInfo)>)> {
+    let port = match port {
+        Some(p) => p,
+        None => Link::detect()?,
+    };
+
+    let mut link = Link::open(&port)?;
+    let infos = link.handshake()?;
+
+    for (side, report) in link.take_crash_reports() {
+        eprintln!(
+            "warning: the {} half was reset by a crash, {}",
+            side_name(side),
+            crash_report(&report)
+        );
+    }
+
+    Ok((link, infos))
+}
+
+fn crash_report(report: &CrashReport) -> String {
+    let registers = format!(
+        "pc {:08x}, lr {:08x}, xpsr {:08x}",
+        report.pc, report.lr, report.xpsr
+    );
+
+    match report.kind {
+        CrashKind::Panic => format!(
+            "it panicked at {}:{}: {}",
+            report.file, report.line, report.message
+        ),
+        CrashKind::HardFault => format!("it hit a hard fault ({registers})"),
+        CrashKind::BootloaderFault => format!("the bootloader hit a hard fault ({registers})"),
+        CrashKind::Hang => format!("the {} task stopped responding", report.message),
+    }
+}
+
+fn list() -> Result<()> {
+    for port in serialport::available_ports()? {
+        if let serialport::SerialPortType::UsbPort(info) = port.port_type {
+            if info.vid == link::USB_VID && info.pid == link::USB_PID {
+                println!("{}", port.port_name);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn side_name(side: KeyboardSide) -> &'static str {
+    match side {
+        KeyboardSide::Left => "left",
+        KeyboardSide::Right => "right",
+    }
+}
+
+fn info(
+    link: &mut Link,
+    infos: Vec<(KeyboardSide, DeviceInfo)>,
+    target_side: Option<KeyboardSide>,
+) -> Result<()> {
+    let statuses = link.request(target_side, HostToDeviceMsg::ConfigStatus, REPLY_TIMEOUT)?;
+    let headers = link.request(target_side, HostToDeviceMsg::GetImageHeader, REPLY_TIMEOUT)?;
+
+    for (side, info) in infos {
+        if target_side.is_some_and(|s| s != side) {
+            continue;
+        }
+
+        let caps = info.capabilities;
+        let mut features = Vec::new();
+        if caps.display() {
+            features.push("display");
+        }
+        if caps.trackpad() {
+            features.push("trackpad");
+        }
+        if caps.flash_db() {
+            features.push("flash db");
+        }
+
+        println!(
+            "[{}] version {} built {} (protocol v{}), features: {}",
+            side_name(side),
+            info.firmware_version,
+            info.build_date,
+            info.protocol_version,
+            if features.is_empty() {
+                "none".to_owned()
+            } else {
+                features.join(", ")
+            },
+        );
+
+        let header = headers.iter().find(|(s, _)| *s == side);
+        if let Some((_, Some(Ok(Reply::ImageHeader(header))))) = header {
+            println!("[{}] {}", side_name(side), image_header(header));
+        }
+
+        let status = statuses.iter().find(|(s, _)| *s == side);
+        if let Some((_, Some(Ok(Reply::ConfigStatus(status))))) = status {
+            if status.rescued_at_boot {
+                println!("[{}] {}", side_name(side), config_status(status));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn stats(link: &mut Link, target_side: Option<KeyboardSide>) -> Result<()> {
+    for (kind, name) in [(LinkKind::Usb, "usb"), (LinkKind::Interboard, "interboard")] {
+        let msg = HostToDeviceMsg::GetLinkStats(kind);
+
+        for (side, result) in link.request(target_side, msg, REPLY_TIMEOUT)? {
+            let side = side_name(side);
+
+            let stats = match result {
+                Some(Ok(Reply::LinkStats(stats))) => stats,
+                None => {
+                    println!("[{side}] {name}: no response");
+                    continue;
+                }
+                Some(Err(e)) => {
+                    println!("[{side}] {name}: error: {e}");
+                    continue;
+                }
+                Some(Ok(reply)) => bail!("Unexpected reply from the {side} half: {reply:?}"),
+            };
+
+            println!("[{side}] {name}: {}", link_stats(&stats));
+
+            let mut lower = 0;
+            for (bucket, &count) in stats.rtt.iter().enumerate() {
+                let range = match rtt_bucket_limit(bucket) {
+                    Some(limit) => format!("{lower}-{limit}us"),
+                    None => format!("{lower}us+"),
+                };
+                println!("[{side}] {name}: round trips of {range:>12}: {count}");
+                lower = rtt_bucket_limit(bucket).unwrap_or(lower);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn link_stats(stats: &LinkStats) -> String {
+    format!(
+        "{} frames sent, {} received, {} undecodable, {} with bad checksums, \
+         {} nacks received, {} retries, {} failures",
+        stats.frames_sent,
+        stats.frames_received,
+        stats.decode_failures,
+        stats.checksum_failures,
+        stats.nacks_received,
+        stats.retries,
+        stats.failures,
+    )
+}
+
+/// Send a request and print out the response from each side
+fn request(link: &mut Link, target_side: Option<KeyboardSide>, msg: HostToDeviceMsg) -> Result<()> {
+    for (side, result) in link.request(target_side, msg, REPLY_TIMEOUT)? {
+        let side = side_name(side);
+
+        match result {
+            None => println!("[{side}] no response"),
+            Some(Err(e)) => println!("[{side}] error: {e}"),
+            Some(Ok(Reply::Pong { payload })) => {
+                println!("[{side}] pong: {}", String::from_utf8_lossy(&payload));
+            }
+            Some(Ok(Reply::Rebooting)) => println!("[{side}] rebooting"),
+            Some(Ok(Reply::AnimationSet(kind))) => println!("[{side}] animation set to {kind:?}"),
+            Some(Ok(Reply::Version { version })) => println!("[{side}] version {version}"),
+            Some(Ok(Reply::KeymapReceived { len })) => println!("[{side}] received {len} bytes"),
+            Some(Ok(Reply::KeymapApplied)) => println!("[{side}] keymap applied"),
+            Some(Ok(Reply::ConfigStatus(status))) => {
+                println!("[{side}] {}", config_status(&status));
+            }
+            Some(Ok(Reply::Backup(header))) => {
+                println!("[{side}] backup of {} bytes", header.len())
+            }
+            Some(Ok(Reply::BackupChunk { data })) => println!("[{side}] read {} bytes", data.len()),
+            Some(Ok(Reply::RestoreReceived { len })) => println!("[{side}] received {len} bytes"),
+            Some(Ok(Reply::UpdateReceived { len })) => println!("[{side}] received {len} bytes"),
+            Some(Ok(Reply::UpdateRelay(status))) => {
+                println!("[{side}] relayed {} of {} bytes", status.sent, status.len)
+            }
+            Some(Ok(Reply::ImageHeader(header))) => {
+                println!("[{side}] {}", image_header(&header))
+            }
+            Some(Ok(Reply::Settings(settings))) => {
+                for setting in settings.iter() {
+                    println!("[{side}] {} = {}", setting.name(), setting.value());
+                }
+            }
+            Some(Ok(Reply::LinkStats(stats))) => println!("[{side}] {}", link_stats(&stats)),
+        }
+    }
+
+    Ok(())
+}
+
+fn image_header(header: &ImageHeader) -> String {
+    format!(
+        "image of {} bytes, crc {:08x}, built at unix time {}",
+        header.len, header.crc, header.build_time
+    )
+}
+
+fn config_status(status: &ConfigStatus) -> String {
+    let mut text = String::from(match (status.mounted, status.rescued_at_boot) {
+        (false, _) => "config couldn't be loaded or reformatted, nothing will be saved",
+        (true, true) => "config was corrupted and has been reset to the defaults",
+        (true, false) => "config loaded",
+    });
+
+    if status.has_rescued_copy {
+        text.push_str(
+            ", a copy of the corrupted config can be saved with `config backup --rescued`",
+        );
+    }
+
+    text
+}
+
+/// Backups and restores only make sense for one half at a time
+fn one_side(target_side: Option<KeyboardSide>) -> Result<KeyboardSide> {
+    target_side.context("Each half has its own config, pick one with --side")
+}
+
+/// Send a request to one side and return its reply, failing if it didn't
+/// succeed
+fn request_one(link: &mut Link, side: KeyboardSide, msg: HostToDeviceMsg) -> Result<Reply> {
+    let responses = link.request(Some(side), msg, REPLY_TIMEOUT)?;
+
+    match responses.into_iter().next() {
+        Some((_, Some(Ok(reply)))) => Ok(reply),
+        Some((_, Some(Err(e)))) => bail!("The {} half failed: {e}", side_name(side)),
+        _ => bail!("The {} half stopped responding", side_name(side)),
+    }
+}
+
+fn save_backup(
+    link: &mut Link,
+    side: KeyboardSide,
+    source: BackupSource,
+    path: &Path,
+) -> Result<()> {
+    let Reply::Backup(header) = request_one(link, side, HostToDeviceMsg::BeginBackup(source))?
+    else {
+        bail!("Unexpected reply to a backup request");
+    };
+
+    let mut data = Vec::with_capacity(header.len() as usize);
+    while data.len() < header.len() as usize {
+        let msg = HostToDeviceMsg::ReadBackup {
+            source,
+            offset: data.len() as u32,
+        };
+        match request_one(link, side, msg)? {
+            Reply::BackupChunk { data: chunk } if !chunk.is_empty() => {
+                data.extend_from_slice(&chunk)
+            }
+            _ => bail!("Unexpected reply while reading the backup"),
+        }
+    }
+
+    if backup::checksum(&data) != header.crc {
+        bail!("The config changed while it was being read, try again");
+    }
+
+    let mut file = postcard::to_stdvec(&header)?;
+    file.extend_from_slice(&data);
+    std::fs::write(path, file).with_context(|| format!("Couldn't write {}", path.display()))?;
+
+    println!(
+        "[{}] saved {} bytes of config to {}",
+        side_name(side),
+        data.len(),
+        path.display()
+    );
+
+    Ok(())
+}
+
+fn restore_backup(link: &mut Link, side: KeyboardSide, path: &Path) -> Result<()> {
+    let file = std::fs::read(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+    let (header, data) = postcard::take_from_bytes::<BackupHeader>(&file)
+        .ok()
+        .filter(|(h, _)| h.is_valid())
+        .with_context(|| format!("{} isn't a config backup", path.display()))?;
+
+    if data.len() != header.len() as usize || backup::checksum(data) != header.crc {
+        bail!("{} is corrupted", path.display());
+    }
+
+    request_one(link, side, HostToDeviceMsg::BeginRestore(header))?;
+
+    for (i, chunk) in data.chunks(BACKUP_CHUNK_LEN).enumerate() {
+        let msg = HostToDeviceMsg::RestoreChunk {
+            offset: (i * BACKUP_CHUNK_LEN) as u32,
+            data: heapless::Vec::from_slice(chunk).unwrap(),
+        };
+        request_one(link, side, msg)?;
+    }
+
+    request(link, Some(side), HostToDeviceMsg::CommitRestore)
+}
+
+fn setting(name: &str, value: u16) -> Result<Setting> {
+    let Some(setting) = Setting::from_name(name, value) else {
+        bail!("{value} is too large for {name}");
+    };
+
+    let range = setting.range();
+    if !range.contains(&value) {
+        bail!(
+            "{name} must be between {} and {}",
+            range.start(),
+            range.end()
+        );
+    }
+
+    Ok(setting)
+}
+
+fn upload_keymap(link: &mut Link, target_side: Option<KeyboardSide>, path: &Path) -> Result<()> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read {}", path.display()))?;
+    let keymap: Keymap = serde_json::from_str(&text)
+        .with_context(|| format!("Couldn't parse {}", path.display()))?;
+    keymap.validate()?;
+
+    let data = postcard::to_stdvec(&keymap)?;
+    if data.len() > MAX_KEYMAP_LEN {
+        bail!(
+            "Keymap is {} bytes encoded, the keyboard only has room for {MAX_KEYMAP_LEN}",
+            data.len()
+        );
+    }
+
+    let mut expect_ok = |msg| -> Result<()> {
+        for (side, result) in link.request(target_side, msg, REPLY_TIMEOUT)? {
+            match result {
+                Some(Ok(_)) => {}
+                Some(Err(e)) => bail!(
+                    "The {} half failed to take the keymap: {e}",
+                    side_name(side)
+                ),
+                None => bail!("The {} half stopped responding", side_name(side)),
+            }
+        }
+        Ok(())
+    };
+
+    expect_ok(HostToDeviceMsg::BeginKeymap {
+        len: data.len() as u16,
+    })?;
+
+    for (i, chunk) in data.chunks(KEYMAP_CHUNK_LEN).enumerate() {
+        expect_ok(HostToDeviceMsg::KeymapChunk {
+            offset: (i * KEYMAP_CHUNK_LEN) as u16,
+            data: heapless::Vec::from_slice(chunk).unwrap(),
+        })?;
+    }
+
+    request(
+        link,
+        target_side,
+        HostToDeviceMsg::CommitKeymap {
+            crc: keymap::checksum(&data),
+        },
+    )
+}
+
+fn update_firmware(
+    link: &mut Link,
+    infos: Vec<(KeyboardSide, DeviceInfo)>,
+    target_side: Option<KeyboardSide>,
+    path: &Path,
+) -> Result<()> {
+    let mut image = read_firmware(path)?;
+
+    match ImageHeader::of_image(&image) {
+        Some(header) if !header.is_sealed() => {
+            image::seal(&mut image);
+        }
+        Some(_) if image::verify(&image) => {}
+        Some(_) => bail!("{} is corrupted", path.display()),
+        None => bail!(
+            "{} has no image header, was it built with the bootloader feature?",
+            path.display()
+        ),
+    }
+
+    let mut sides = infos
+        .into_iter()
+        .filter(|(side, _)| target_side.is_none_or(|s| s == *side))
+        .collect::<Vec<_>>();
+
+    if let Some((side, _)) = sides.iter().find(|(_, i)| !i.capabilities.update()) {
+        bail!(
+            "The {} half has no bootloader to install updates, use the usb bootloader instead",
+            side_name(*side)
+        );
+    }
+
+    // with both halves to update only the one plugged into the host is sent
+    // the image, it passes it on to the other over the interboard link
+    let relay = target_side.is_none() && sides.len() > 1;
+    if relay {
+        sides.retain(|(_, info)| info.capabilities.usb());
+    }
+
+    // the half plugged into the host goes last, the link drops once it reboots
+    sides.sort_by_key(|(_, info)| info.capabilities.usb());
+
+    for &(side, _) in &sides {
+        let msg = HostToDeviceMsg::BeginUpdate {
+            len: image.len() as u32,
+        };
+        request_one(link, side, msg)?;
+
+        for (i, chunk) in image.chunks(UPDATE_CHUNK_LEN).enumerate() {
+            let offset = i * UPDATE_CHUNK_LEN;
+            let msg = HostToDeviceMsg::UpdateChunk {
+                offset: offset as u32,
+                data: heapless::Vec::from_slice(chunk).unwrap(),
+            };
+            request_one(link, side, msg)?;
+
+            print!(
+                "\r[{}] sent {} of {} bytes",
+                side_name(side),
+                offset + chunk.len(),
+                image.len()
+            );
+            std::io::stdout().flush()?;
+        }
+        println!();
+    }
+
+    let crc = update::checksum(&image);
+    if let (true, Some(&(side, _))) = (relay, sides.first()) {
+        relay_update(link, side, crc)?;
+    }
+
+    for (side, _) in sides {
+        request(link, Some(side), HostToDeviceMsg::CommitUpdate { crc })?;
+    }
+
+    Ok(())
+}
+
+/// Have `side` pass the image it was sent on to the other half, which
+/// installs it once it has all arrived
+fn relay_update(link: &mut Link, side: KeyboardSide, crc: u32) -> Result<()> {
+    request_one(link, side, HostToDeviceMsg::RelayUpdate { crc })?;
+
+    loop {
+        let Reply::UpdateRelay(status) =
+            request_one(link, side, HostToDeviceMsg::UpdateRelayStatus)?
+        else {
+            bail!("Unexpected reply to an update relay request");
+        };
+
+        print!(
+            "\r[{}] relayed {} of {} bytes",
+            side_name(side.other()),
+            status.sent,
+            status.len
+        );
+        std::io::stdout().flush()?;
+
+        match status.state {
+            RelayState::Sending => std::thread::sleep(Duration::from_millis(200)),
+            RelayState::Done => break,
+            RelayState::Failed(e) => {
+                println!();
+                bail!("Couldn't pass the update on to the other half: {e}");
+            }
+            RelayState::Idle => bail!("The update relay stopped unexpectedly"),
+        }
+    }
+    println!();
+
+    Ok(())
+}
+
+/// Read a raw firmware binary
+fn read_firmware(path: &Path) -> Result<Vec<u8>> {
+    let image = std::fs::read(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+
+    if image.starts_with(b"\x7fELF") {
+        bail!(
+            "{} is an elf file, convert it with `objcopy -O binary` first",
+            path.display()
+        );
+    }
+
+    Ok(image)
+}
+
+fn seal_firmware(path: &Path) -> Result<()> {
+    let mut image = read_firmware(path)?;
+
+    let header = image::seal(&mut image).with_context(|| {
+        format!(
+            "{} has no image header, was it built with the bootloader feature?",
+            path.display()
+        )
+    })?;
+    std::fs::write(path, &image).with_context(|| format!("Couldn't write {}", path.display()))?;
+
+    println!("sealed {}: {}", path.display(), image_header(&header));
+
+    Ok(())
+}
+
+fn logs(link: &mut Link) -> Result<()> {
+    // log lines arrive in small chunks, interleaved between the two halves
+    let mut lines: HashMap<KeyboardSide, Vec<u8>> = HashMap::new();
+    let mut stdout = std::io::stdout().lock();
+
+    loop {
+        let Some(DeviceToHost { from_side, msg }) = link.recv(Duration::from_millis(100))? else {
+            continue;
+        };
+
+        if let DeviceToHostMsg::Log { msg } = msg {
+            let line = lines.entry(from_side).or_default();
+            line.extend_from_slice(&msg);
+
+            while let Some(idx) = line.iter().position(|&c| c == b'\n') {
+                let rest = line.split_off(idx + 1);
+                let text = String::from_utf8_lossy(line);
+                writeln!(stdout, "[{}] {}", side_name(from_side), text.trim_end())?;
+                *line = rest;
+            }
+        }
+    }
+}
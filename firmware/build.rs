@@ -44,6 +44,10 @@ fn main() {
         .unwrap()
         .write_all(Local::now().date_naive().to_string().as_bytes())
         .unwrap();
+    File::create(out.join("build_time.rs"))
+        .unwrap()
+        .write_all(Local::now().timestamp().to_string().as_bytes())
+        .unwrap();
     File::create(out.join("build_attribute.txt"))
         .unwrap()
         .write_all(env::var("PROFILE").unwrap().as_bytes())
@@ -3,9 +3,11 @@ MEMORY {
     FLASH  : ORIGIN = 0x10000100, LENGTH = 4096K - 0x100
     CONFIG : ORIGIN = ORIGIN(FLASH) + LENGTH(FLASH), LENGTH = 8192K
     RAM    : ORIGIN = 0x20000000, LENGTH = 256K
+    CRASH_REPORT : ORIGIN = 0x20040000, LENGTH = 4K
 }
 
 __config_start = ORIGIN(CONFIG) - ORIGIN(BOOT2);
+__config_end = ORIGIN(CONFIG) + LENGTH(CONFIG) - ORIGIN(BOOT2);
 
 SECTIONS {
   .config (NOLOAD) : ALIGN(4)
@@ -15,3 +17,12 @@ SECTIONS {
     . = ALIGN(4);
   } > CONFIG
 }
+
+SECTIONS {
+  /* crash reports are kept here across resets, this bank of ram is left alone
+   * by everything else including the bootrom, see src/crash.rs */
+  .crash_report (NOLOAD) : ALIGN(4)
+  {
+    KEEP(*(.crash_report .crash_report.*));
+  } > CRASH_REPORT
+}
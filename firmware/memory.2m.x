@@ -2,10 +2,26 @@ MEMORY {
     BOOT2  : ORIGIN = 0x10000000, LENGTH = 0x100
     FLASH  : ORIGIN = 0x10007000, LENGTH = 1024K - 0x7000
     CONFIG : ORIGIN = ORIGIN(FLASH) + LENGTH(FLASH), LENGTH = 256K
+    STAGING : ORIGIN = ORIGIN(CONFIG) + LENGTH(CONFIG), LENGTH = 768K - 8K
+    SCRATCH : ORIGIN = ORIGIN(STAGING) + LENGTH(STAGING), LENGTH = 4K
+    BOOT_STATE : ORIGIN = ORIGIN(SCRATCH) + LENGTH(SCRATCH), LENGTH = 4K
     RAM    : ORIGIN = 0x20000000, LENGTH = 256K
+    CRASH_REPORT : ORIGIN = 0x20040000, LENGTH = 4K
 }
 
 __config_start = ORIGIN(CONFIG) - ORIGIN(BOOT2);
+__config_end = ORIGIN(CONFIG) + LENGTH(CONFIG) - ORIGIN(BOOT2);
+
+/* the config database is made of whole 4K flash pages */
+ASSERT(ORIGIN(CONFIG) % 4K == 0 && LENGTH(CONFIG) % 4K == 0, "the config region must be whole flash pages");
+
+/* firmware updates are staged here for the bootloader to install, which
+ * leaves the previous firmware here in case it needs rolling back to */
+__staging_start = ORIGIN(STAGING) - ORIGIN(BOOT2);
+__staging_end = ORIGIN(STAGING) + LENGTH(STAGING) - ORIGIN(BOOT2);
+
+/* which firmware the bootloader is running and whether it has been confirmed */
+__boot_state_start = ORIGIN(BOOT_STATE) - ORIGIN(BOOT2);
 
 SECTIONS {
   .config (NOLOAD) : ALIGN(4)
@@ -15,3 +31,27 @@ SECTIONS {
     . = ALIGN(4);
   } > CONFIG
 }
+
+SECTIONS {
+  /* the bootloader looks for the image header straight after the vector
+   * table, see shared/src/image.rs */
+  .image_header : ALIGN(4)
+  {
+    KEEP(*(.image_header));
+    . = ALIGN(4);
+  } > FLASH
+} INSERT BEFORE .text;
+
+/* Move _stext, to make room for the header */
+_stext = ADDR(.image_header) + SIZEOF(.image_header);
+
+ASSERT(ADDR(.image_header) == ORIGIN(FLASH) + 0xc0, "the image header must follow the vector table");
+
+SECTIONS {
+  /* crash reports are kept here across resets, this bank of ram is left alone
+   * by everything else including the bootrom, see src/crash.rs */
+  .crash_report (NOLOAD) : ALIGN(4)
+  {
+    KEEP(*(.crash_report .crash_report.*));
+  } > CRASH_REPORT
+}
@@ -0,0 +1,201 @@
+//! Crash reports that survive the reset
+//!
+//! Panics and hard faults are written to the `.crash_report` section before
+//! resetting. It sits in a bank of RAM that neither the firmware nor the
+//! bootloader otherwise use (see memory.x), so it isn't cleared at boot and
+//! the bootloader can leave reports of its own faults there too. The next boot
+//! picks the report up, counts it in the metrics and sends it to the host once
+//! it says hello.
+
+use core::cell::RefCell;
+use core::fmt::Write;
+use core::mem::MaybeUninit;
+use core::ptr::addr_of_mut;
+
+use embassy_sync::blocking_mutex::{raw::ThreadModeRawMutex, Mutex};
+use portable_atomic::AtomicBool;
+use shared::crash::{CrashKind, CrashReport, MAX_CRASH_FILE_LEN, MAX_CRASH_MESSAGE_LEN};
+
+use crate::utils::log;
+
+// Must match the bootloader
+const CRASH_MAGIC: u32 = 0x4352_5348;
+const KIND_PANIC: u32 = 1;
+const KIND_HARD_FAULT: u32 = 2;
+const KIND_BOOTLOADER_FAULT: u32 = 3;
+const KIND_HANG: u32 = 4;
+
+#[repr(C)]
+struct RawCrash {
+    magic: u32,
+    kind: u32,
+    pc: u32,
+    lr: u32,
+    xpsr: u32,
+    line: u32,
+    file_len: u32,
+    message_len: u32,
+    file: [u8; MAX_CRASH_FILE_LEN],
+    message: [u8; MAX_CRASH_MESSAGE_LEN],
+}
+
+#[link_section = ".crash_report"]
+static mut CRASH_REPORT: MaybeUninit<RawCrash> = MaybeUninit::uninit();
+
+static LAST_CRASH: Mutex<ThreadModeRawMutex, RefCell<Option<CrashReport>>> =
+    Mutex::new(RefCell::new(None));
+static CRASHED: AtomicBool = AtomicBool::new(false);
+
+/// Fills a fixed buffer, dropping whatever doesn't fit
+struct Truncating<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for Truncating<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let space = self.buf.len() - self.len;
+        let mut n = s.len().min(space);
+        while !s.is_char_boundary(n) {
+            n -= 1;
+        }
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+/// Pick up the report left by the last crash, if any, and clear it
+pub fn init() {
+    let raw = unsafe {
+        let slot = addr_of_mut!(CRASH_REPORT).cast::<RawCrash>();
+        let raw = core::ptr::read_volatile(slot);
+        core::ptr::write_volatile(addr_of_mut!((*slot).magic), 0);
+        raw
+    };
+
+    if raw.magic != CRASH_MAGIC {
+        // a clean reset, or power on with whatever the ram held
+        return;
+    }
+
+    let kind = match raw.kind {
+        KIND_PANIC => CrashKind::Panic,
+        KIND_HARD_FAULT => CrashKind::HardFault,
+        KIND_BOOTLOADER_FAULT => CrashKind::BootloaderFault,
+        KIND_HANG => CrashKind::Hang,
+        _ => return,
+    };
+
+    fn text(bytes: &[u8], len: u32) -> &str {
+        let bytes = &bytes[..(len as usize).min(bytes.len())];
+        core::str::from_utf8(bytes).unwrap_or("?")
+    }
+
+    let report = CrashReport {
+        kind,
+        file: heapless::String::try_from(text(&raw.file, raw.file_len)).unwrap_or_default(),
+        line: raw.line,
+        message: heapless::String::try_from(text(&raw.message, raw.message_len))
+            .unwrap_or_default(),
+        pc: raw.pc,
+        lr: raw.lr,
+        xpsr: raw.xpsr,
+    };
+
+    log::warn!("Reset by a crash: {:?}", report);
+
+    CRASHED.store(true, portable_atomic::Ordering::Relaxed);
+    LAST_CRASH.lock(|c| *c.borrow_mut() = Some(report));
+}
+
+/// Whether this boot followed a crash
+pub fn crashed_before_boot() -> bool {
+    CRASHED.load(portable_atomic::Ordering::Relaxed)
+}
+
+/// Take the report of the crash before this boot, it's only handed out once
+pub fn take() -> Option<CrashReport> {
+    LAST_CRASH.lock(|c| c.borrow_mut().take())
+}
+
+/// Leave the name of a task that stopped responding for the next boot, the
+/// watchdog resets the chip soon after
+pub fn record_hang(task: &str) {
+    record(KIND_HANG, 0, 0, 0, Message::Task(task));
+}
+
+enum Message<'a> {
+    None,
+    Panic(&'a core::panic::PanicInfo<'a>),
+    Task(&'a str),
+}
+
+fn record(kind: u32, pc: u32, lr: u32, xpsr: u32, message: Message) {
+    let mut raw = RawCrash {
+        magic: CRASH_MAGIC,
+        kind,
+        pc,
+        lr,
+        xpsr,
+        line: 0,
+        file_len: 0,
+        message_len: 0,
+        file: [0; MAX_CRASH_FILE_LEN],
+        message: [0; MAX_CRASH_MESSAGE_LEN],
+    };
+
+    let mut text = Truncating {
+        buf: &mut raw.message,
+        len: 0,
+    };
+
+    match message {
+        Message::None => {}
+        Message::Task(task) => {
+            let _ = text.write_str(task);
+        }
+        Message::Panic(info) => {
+            let _ = write!(text, "{}", info.message());
+
+            if let Some(location) = info.location() {
+                // the end of the path says more than the start
+                let file = location.file();
+                let mut start = file.len().saturating_sub(MAX_CRASH_FILE_LEN);
+                while !file.is_char_boundary(start) {
+                    start += 1;
+                }
+                let file = &file[start..];
+
+                raw.file[..file.len()].copy_from_slice(file.as_bytes());
+                raw.file_len = file.len() as u32;
+                raw.line = location.line();
+            }
+        }
+    }
+    raw.message_len = text.len as u32;
+
+    unsafe { core::ptr::write_volatile(addr_of_mut!(CRASH_REPORT).cast::<RawCrash>(), raw) };
+}
+
+#[cfg(not(feature = "probe"))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    cortex_m::interrupt::disable();
+    record(KIND_PANIC, 0, 0, 0, Message::Panic(info));
+    cortex_m::peripheral::SCB::sys_reset();
+}
+
+// with a probe attached it's more use to stop at the fault
+#[cfg(not(feature = "probe"))]
+#[cortex_m_rt::exception]
+unsafe fn HardFault(frame: &cortex_m_rt::ExceptionFrame) -> ! {
+    record(
+        KIND_HARD_FAULT,
+        frame.pc(),
+        frame.lr(),
+        frame.xpsr(),
+        Message::None,
+    );
+    cortex_m::peripheral::SCB::sys_reset();
+}
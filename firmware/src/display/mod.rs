@@ -9,9 +9,12 @@ use embassy_rp::{
 };
 use embassy_time::{Duration, Timer};
 use embedded_hal_bus::spi::ExclusiveDevice;
+use shared::link_stats::{rtt_bucket_limit, LinkStats, RTT_BUCKETS};
 use slint::platform::software_renderer::Rgb565Pixel;
+use slint::{ModelRc, SharedString, VecModel};
 
 use crate::metrics::{self, Metrics, METRIC_UPDATES};
+use crate::settings::{self, SETTING_UPDATES};
 
 use self::{backend::PicoBackend, draw_buffer::DrawBuffer};
 
@@ -77,9 +80,25 @@ fn run(spi: SPI0, clk: PIN_22, mosi: PIN_23, cs: PIN_12, dc: PIN_11) -> ! {
                 let percentage_awake = 100 - ((100 * sleep) / (sleep + awake + 1));
 
                 window.set_cpu_util(percentage_awake as i32);
+            }
+        },
+    );
+
+    let diagnostics_timer = slint::Timer::default();
+    diagnostics_timer.start(
+        slint::TimerMode::Repeated,
+        core::time::Duration::from_secs(1),
+        {
+            let window = Rc::clone(&window);
+            let mut secs = 0;
+            move || {
+                secs = (secs + 1) % (2 * PAGE_SECS);
+                let show = secs >= PAGE_SECS;
 
-                // window.set_failed_decodes(crate::messages::transmissions::FAILED_DECODES.load(core::sync::atomic::Ordering::Relaxed) as i32);
-                // window.set_nacks_received(crate::messages::transmissions::NACKS_RECEIVED.load(core::sync::atomic::Ordering::Relaxed) as i32);
+                window.set_show_diagnostics(show);
+                if show {
+                    window.set_link_stats(link_stat_rows());
+                }
             }
         },
     );
@@ -90,6 +109,66 @@ fn run(spi: SPI0, clk: PIN_22, mosi: PIN_23, cs: PIN_12, dc: PIN_11) -> ! {
     loop {}
 }
 
+/// How long the values and the link diagnostics are each shown for
+const PAGE_SECS: u32 = 5;
+
+fn link_stat_rows() -> ModelRc<LinkStatRow> {
+    let usb = crate::usb::channel::COUNTERS.snapshot();
+    let interboard = crate::interboard::channel::COUNTERS.snapshot();
+
+    let row = |title: &str, f: fn(&LinkStats) -> SharedString| LinkStatRow {
+        title: title.into(),
+        usb: f(&usb),
+        interboard: f(&interboard),
+    };
+
+    let rows = alloc::vec![
+        row("Sent", |s| slint::format!("{}", s.frames_sent)),
+        row("Received", |s| slint::format!("{}", s.frames_received)),
+        row("Bad", |s| {
+            slint::format!("{}", s.decode_failures + s.checksum_failures)
+        }),
+        row("Nacks", |s| slint::format!("{}", s.nacks_received)),
+        row("Retries", |s| slint::format!("{}", s.retries)),
+        row("Failed", |s| slint::format!("{}", s.failures)),
+        row("RTT", median_rtt),
+    ];
+
+    ModelRc::new(VecModel::from(rows))
+}
+
+/// The bucket of the round trip time histogram that the median falls in
+fn median_rtt(stats: &LinkStats) -> SharedString {
+    let total: u32 = stats.rtt.iter().sum();
+    if total == 0 {
+        return "-".into();
+    }
+
+    let mut seen = 0;
+    let bucket = stats
+        .rtt
+        .iter()
+        .position(|&n| {
+            seen += n;
+            seen * 2 >= total
+        })
+        .unwrap_or(RTT_BUCKETS - 1);
+
+    let us = |us: u32| {
+        if us >= 1000 {
+            slint::format!("{}ms", us / 1000)
+        } else {
+            slint::format!("{}us", us)
+        }
+    };
+
+    match rtt_bucket_limit(bucket) {
+        Some(limit) => slint::format!("<{}", us(limit)),
+        // the last bucket has everything longer than the one before it
+        None => slint::format!(">{}", us(rtt_bucket_limit(bucket - 1).unwrap())),
+    }
+}
+
 static KEYS_PRESSED: AtomicUsize = AtomicUsize::new(0);
 
 #[embassy_executor::task]
@@ -100,11 +179,18 @@ async fn metrics_updater(bl: PIN_13, pwm: PWM_SLICE6) {
     pwm_cfg.compare_b = 256;
     let mut bl = embassy_rp::pwm::Pwm::new_output_b(pwm, bl, pwm_cfg.clone());
 
+    let mut setting_updates = SETTING_UPDATES.subscriber().unwrap();
+    let mut timeout = settings::current().display_timeout_secs;
+
     metrics::request_sync().await;
 
     loop {
-        let Metrics { keys_pressed } = match embassy_time::with_timeout(
-            Duration::from_secs(30),
+        if let Some(s) = setting_updates.try_next_message_pure() {
+            timeout = s.display_timeout_secs;
+        }
+
+        let Metrics { keys_pressed, .. } = match embassy_time::with_timeout(
+            Duration::from_secs(timeout as u64),
             sub.next_message_pure(),
         )
         .await
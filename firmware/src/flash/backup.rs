@@ -0,0 +1,317 @@
+//! Backups, restores and resets of the whole config database
+//!
+//! The config region has room past the end of the database for one more copy
+//! of it, the spare area, if it's big enough (see [`has_spare_area`]). A
+//! database that can't be mounted at boot is copied there before being
+//! reformatted, and restores are staged there before replacing the database. The first page of the spare area holds a
+//! [`BackupHeader`], which is only written once a copy is complete.
+
+use ekv::config::PAGE_SIZE;
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex};
+use portable_atomic::AtomicBool;
+use shared::backup::{BackupHeader, BackupSource, ConfigStatus, BACKUP_CHUNK_LEN};
+use shared::device_to_host::{Reply, RpcError};
+
+use crate::utils::log;
+
+use super::{AlignedBuf, ConfigFlash, DB};
+
+fn db_size(flash: &ConfigFlash) -> usize {
+    flash.db_pages() * PAGE_SIZE
+}
+
+fn spare_header(flash: &ConfigFlash) -> usize {
+    db_size(flash)
+}
+
+fn spare_data(flash: &ConfigFlash) -> usize {
+    spare_header(flash) + PAGE_SIZE
+}
+
+static RESCUED_AT_BOOT: AtomicBool = AtomicBool::new(false);
+
+struct Restore {
+    header: Option<BackupHeader>,
+    received: u32,
+}
+
+static RESTORE: Mutex<ThreadModeRawMutex, Restore> = Mutex::new(Restore {
+    header: None,
+    received: 0,
+});
+
+fn header(flash: &ConfigFlash, crc: u32) -> BackupHeader {
+    BackupHeader::new(PAGE_SIZE as u32, flash.db_pages() as u32, crc)
+}
+
+pub(super) fn has_spare_area(flash: &ConfigFlash) -> bool {
+    flash.len() >= spare_data(flash) + db_size(flash)
+}
+
+/// Copy a database that failed to mount into the spare area, so that it
+/// isn't lost when it gets reformatted
+pub(super) async fn rescue(flash: &mut ConfigFlash) {
+    if !has_spare_area(flash) {
+        log::warn!("No room to keep a copy of the config database");
+        return;
+    }
+
+    if copy_to_spare(flash).await.is_some() {
+        log::info!("Kept a copy of the config database for the host");
+        RESCUED_AT_BOOT.store(true, portable_atomic::Ordering::Relaxed);
+    } else {
+        log::error!("Failed to keep a copy of the config database");
+    }
+}
+
+async fn copy_to_spare(flash: &mut ConfigFlash) -> Option<()> {
+    flash.erase_raw(spare_header(flash), PAGE_SIZE).await.ok()?;
+
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buf = AlignedBuf([0; PAGE_SIZE]);
+    let spare_data = spare_data(flash);
+
+    for offset in (0..db_size(flash)).step_by(PAGE_SIZE) {
+        flash.read_raw(offset, &mut buf.0).await.ok()?;
+        hasher.update(&buf.0);
+
+        flash.erase_raw(spare_data + offset, PAGE_SIZE).await.ok()?;
+        flash.write_raw(spare_data + offset, &buf.0).await.ok()?;
+    }
+
+    let header = header(flash, hasher.finalize());
+    write_spare_header(flash, &header).await
+}
+
+async fn write_spare_header(flash: &mut ConfigFlash, header: &BackupHeader) -> Option<()> {
+    let mut buf = AlignedBuf([0; 32]);
+    postcard::to_slice(header, &mut buf.0).ok()?;
+
+    flash.write_raw(spare_header(flash), &buf.0).await.ok()
+}
+
+async fn read_spare_header(flash: &mut ConfigFlash) -> Option<BackupHeader> {
+    if !has_spare_area(flash) {
+        return None;
+    }
+
+    let mut buf = AlignedBuf([0; 32]);
+    flash.read_raw(spare_header(flash), &mut buf.0).await.ok()?;
+
+    let (header, _) = postcard::take_from_bytes::<BackupHeader>(&buf.0).ok()?;
+    (header.is_valid() && header == self::header(flash, header.crc)).then_some(header)
+}
+
+async fn checksum(flash: &mut ConfigFlash, start: usize) -> Option<u32> {
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buf = AlignedBuf([0; PAGE_SIZE]);
+
+    for offset in (0..db_size(flash)).step_by(PAGE_SIZE) {
+        flash.read_raw(start + offset, &mut buf.0).await.ok()?;
+        hasher.update(&buf.0);
+    }
+
+    Some(hasher.finalize())
+}
+
+pub async fn status() -> ConfigStatus {
+    let has_rescued_copy = match DB.get() {
+        Some(db) => read_spare_header(&mut db.lock_flash().await)
+            .await
+            .is_some(),
+        None => false,
+    };
+
+    ConfigStatus {
+        mounted: super::is_mounted(),
+        rescued_at_boot: RESCUED_AT_BOOT.load(portable_atomic::Ordering::Relaxed),
+        has_rescued_copy,
+    }
+}
+
+pub async fn begin_backup(source: BackupSource) -> Result<Reply, RpcError> {
+    let db = DB.get().ok_or(RpcError::FlashFailure)?;
+    let mut flash = db.lock_flash().await;
+
+    let header = match source {
+        BackupSource::Live => {
+            let crc = checksum(&mut flash, 0)
+                .await
+                .ok_or(RpcError::FlashFailure)?;
+            header(&flash, crc)
+        }
+        BackupSource::Rescued => read_spare_header(&mut flash)
+            .await
+            .ok_or(RpcError::InvalidBackup)?,
+    };
+
+    Ok(Reply::Backup(header))
+}
+
+pub async fn read_backup(source: BackupSource, offset: u32) -> Result<Reply, RpcError> {
+    let db = DB.get().ok_or(RpcError::FlashFailure)?;
+    let mut flash = db.lock_flash().await;
+
+    let offset = offset as usize;
+    if offset >= db_size(&flash) {
+        return Err(RpcError::InvalidBackup);
+    }
+
+    let start = match source {
+        BackupSource::Live => 0,
+        BackupSource::Rescued if has_spare_area(&flash) => spare_data(&flash),
+        BackupSource::Rescued => return Err(RpcError::InvalidBackup),
+    };
+
+    let len = BACKUP_CHUNK_LEN.min(db_size(&flash) - offset);
+    let mut buf = AlignedBuf([0; BACKUP_CHUNK_LEN]);
+    flash
+        .read_raw(start + offset, &mut buf.0[..len])
+        .await
+        .map_err(|_| RpcError::FlashFailure)?;
+
+    Ok(Reply::BackupChunk {
+        data: heapless::Vec::from_slice(&buf.0[..len]).unwrap(),
+    })
+}
+
+pub async fn begin_restore(backup: BackupHeader) -> Result<Reply, RpcError> {
+    let db = DB.get().ok_or(RpcError::FlashFailure)?;
+    let mut flash = db.lock_flash().await;
+
+    // the crc is checked once everything has arrived
+    if !backup.is_valid() || backup != header(&flash, backup.crc) {
+        return Err(RpcError::InvalidBackup);
+    }
+
+    // this layout has nowhere to stage it, so no backup fits
+    if !has_spare_area(&flash) {
+        return Err(RpcError::InvalidBackup);
+    }
+
+    // the spare area is about to be overwritten, so it no longer holds a
+    // rescued copy
+    flash
+        .erase_raw(spare_header(&flash), PAGE_SIZE)
+        .await
+        .map_err(|_| RpcError::FlashFailure)?;
+
+    let mut restore = RESTORE.lock().await;
+    restore.header = Some(backup);
+    restore.received = 0;
+
+    Ok(Reply::RestoreReceived { len: 0 })
+}
+
+pub async fn receive_restore_chunk(offset: u32, chunk: &[u8]) -> Result<Reply, RpcError> {
+    let mut restore = RESTORE.lock().await;
+    let header = restore.header.ok_or(RpcError::InvalidBackup)?;
+
+    // a repeat of the last chunk, the host didn't hear our reply
+    if offset < restore.received && offset + chunk.len() as u32 == restore.received {
+        return Ok(Reply::RestoreReceived {
+            len: restore.received,
+        });
+    }
+
+    // chunks are sent one at a time, so anything out of order means we missed
+    // one and the host should start again
+    if offset != restore.received || offset + chunk.len() as u32 > header.len() {
+        return Err(RpcError::InvalidBackup);
+    }
+
+    let db = DB.get().ok_or(RpcError::FlashFailure)?;
+    let mut flash = db.lock_flash().await;
+    let offset = offset as usize;
+    let spare_data = spare_data(&flash);
+
+    // pages are erased as they're reached, erasing everything up front takes
+    // longer than the host waits for a reply
+    let first_page = offset.next_multiple_of(PAGE_SIZE);
+    if first_page < offset + chunk.len() {
+        flash
+            .erase_raw(spare_data + first_page, PAGE_SIZE)
+            .await
+            .map_err(|_| RpcError::FlashFailure)?;
+    }
+
+    let mut buf = AlignedBuf([0; BACKUP_CHUNK_LEN]);
+    buf.0[..chunk.len()].copy_from_slice(chunk);
+    flash
+        .write_raw(spare_data + offset, &buf.0[..chunk.len()])
+        .await
+        .map_err(|_| RpcError::FlashFailure)?;
+
+    restore.received += chunk.len() as u32;
+
+    Ok(Reply::RestoreReceived {
+        len: restore.received,
+    })
+}
+
+/// Check that the staged backup arrived intact, [`apply_restore`] then puts it
+/// in place
+pub async fn check_restore() -> Result<(), RpcError> {
+    let restore = RESTORE.lock().await;
+    let header = restore.header.ok_or(RpcError::InvalidBackup)?;
+
+    if restore.received != header.len() {
+        return Err(RpcError::InvalidBackup);
+    }
+
+    let db = DB.get().ok_or(RpcError::FlashFailure)?;
+    let mut flash = db.lock_flash().await;
+
+    let spare_data = spare_data(&flash);
+    match checksum(&mut flash, spare_data).await {
+        Some(crc) if crc == header.crc => Ok(()),
+        Some(_) => Err(RpcError::InvalidBackup),
+        None => Err(RpcError::FlashFailure),
+    }
+}
+
+/// Replace the database with the staged backup and reboot, the mounted
+/// database no longer matches the flash afterwards
+pub async fn apply_restore() -> ! {
+    if let Some(db) = DB.get() {
+        // held until the reboot, so nothing else touches the database
+        let mut flash = db.lock_flash().await;
+        copy_from_spare(&mut flash).await;
+    }
+
+    cortex_m::peripheral::SCB::sys_reset();
+}
+
+async fn copy_from_spare(flash: &mut ConfigFlash) {
+    let mut buf = AlignedBuf([0; PAGE_SIZE]);
+    let spare_data = spare_data(flash);
+
+    for offset in (0..db_size(flash)).step_by(PAGE_SIZE) {
+        if flash
+            .read_raw(spare_data + offset, &mut buf.0)
+            .await
+            .is_err()
+            || flash.erase_raw(offset, PAGE_SIZE).await.is_err()
+            || flash.write_raw(offset, &buf.0).await.is_err()
+        {
+            // whatever is left fails to mount next boot and gets rescued
+            log::error!("Failed to restore the config database");
+            return;
+        }
+    }
+
+    let _ = flash.erase_raw(spare_header(flash), PAGE_SIZE).await;
+
+    log::info!("Restored the config database");
+}
+
+/// Wipe the database and reboot, so that everything goes back to its
+/// defaults
+pub async fn factory_reset() -> ! {
+    match DB.get() {
+        Some(db) if db.format().await.is_ok() => log::info!("Formatted the config database"),
+        _ => log::error!("Failed to format the config database"),
+    }
+
+    cortex_m::peripheral::SCB::sys_reset();
+}
@@ -0,0 +1,94 @@
+//! The bootloader's record of the image it installed
+//!
+//! A newly installed image is on trial until it confirms itself here, if it
+//! resets a few times without doing so the bootloader goes back to the
+//! previous image. See `bootloader/src/slots.rs` for the layout.
+
+use embedded_storage_async::nor_flash::NorFlash;
+
+use crate::utils::log;
+
+use super::{AlignedBuf, DB};
+
+extern "C" {
+    static __boot_state_start: u32;
+}
+
+// Must match the bootloader
+const STATE_MAGIC: u32 = 0x5354_4154;
+const STATE_MAGIC_OFFSET: usize = 0;
+const STATE_CONFIRMED: usize = 264;
+const STATE_ROLLED_BACK: usize = 268;
+
+const FLASH_BASE: usize = 0x1000_0000;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BootState {
+    /// Running a confirmed image, or one flashed without the bootloader's help
+    Confirmed,
+    /// Running a new image that hasn't confirmed itself yet
+    OnTrial,
+    /// The last update didn't confirm itself, this is the image from before it
+    RolledBack,
+}
+
+fn state_word(field: usize) -> u32 {
+    unsafe {
+        let start = &__boot_state_start as *const u32 as usize;
+        core::ptr::read_volatile((FLASH_BASE + start + field) as *const u32)
+    }
+}
+
+fn state_flag(field: usize) -> bool {
+    state_word(field) != 0xffff_ffff
+}
+
+pub fn get() -> BootState {
+    if state_word(STATE_MAGIC_OFFSET) != STATE_MAGIC || state_flag(STATE_CONFIRMED) {
+        BootState::Confirmed
+    } else if state_flag(STATE_ROLLED_BACK) {
+        BootState::RolledBack
+    } else {
+        BootState::OnTrial
+    }
+}
+
+/// Mark the running image as good so that the bootloader keeps it, returning
+/// whether it is. Until then the watchdog the bootloader started to catch it
+/// hanging must be left to run out
+pub async fn confirm() -> bool {
+    match get() {
+        BootState::Confirmed => true,
+        BootState::RolledBack => {
+            log::warn!(
+                "The last firmware update didn't start, running the firmware from before it"
+            );
+            true
+        }
+        BootState::OnTrial => {
+            // the flash is owned by the database, without it this image can't
+            // confirm itself and gets rolled back
+            let Some(db) = DB.get() else {
+                log::error!("Can't confirm the new firmware without the config database");
+                return false;
+            };
+
+            let offset = unsafe { &__boot_state_start as *const u32 as usize } + STATE_CONFIRMED;
+            let buf = AlignedBuf(0u32.to_le_bytes());
+            if db
+                .lock_flash()
+                .await
+                .chip()
+                .write(offset as u32, &buf.0)
+                .await
+                .is_err()
+            {
+                log::error!("Failed to confirm the new firmware");
+                return false;
+            }
+
+            log::info!("Confirmed the new firmware");
+            true
+        }
+    }
+}
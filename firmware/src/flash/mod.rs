@@ -9,69 +9,242 @@ use once_cell::sync::OnceCell;
 use rand::Rng;
 
 use crate::rng::MyRng;
+use crate::utils::log;
 
-static DB: OnceCell<
-    Database<
-        DbFlash<Flash<embassy_rp::peripherals::FLASH, embassy_rp::flash::Async, FLASH_SIZE>>,
-        ThreadModeRawMutex,
-    >,
-> = OnceCell::new();
+pub mod backup;
+#[cfg(feature = "bootloader")]
+pub mod boot_state;
+#[cfg(feature = "bootloader")]
+pub mod relay;
+#[cfg(feature = "bootloader")]
+pub mod update;
+
+type ConfigFlash =
+    DbFlash<Flash<embassy_rp::peripherals::FLASH, embassy_rp::flash::Async, FLASH_SIZE>>;
+
+static DB: OnceCell<Database<ConfigFlash, ThreadModeRawMutex>> = OnceCell::new();
 
 pub async fn init(flash: embassy_rp::peripherals::FLASH, dma: embassy_rp::dma::AnyChannel) {
     let flash = DbFlash {
         flash: Flash::new(flash, dma),
         start: unsafe { &__config_start as *const u32 as usize },
+        end: unsafe { &__config_end as *const u32 as usize },
     };
+
+    // the database is cut down to fit rather than running into the staging
+    // area after it
+    if flash.db_pages() < config::MAX_PAGE_COUNT {
+        log::warn!(
+            "The config region only has room for {} database pages",
+            flash.db_pages()
+        );
+    }
+    if !backup::has_spare_area(&flash) {
+        log::warn!("The config region has no room for a spare copy of the database");
+    }
+
     let mut cfg = ekv::Config::default();
     cfg.random_seed = MyRng.gen();
     let db = Database::new(flash, cfg);
 
-    if db.mount().await.is_err() && db.format().await.is_err() {
-        return;
+    if db.mount().await.is_err() {
+        log::warn!("Couldn't mount the config database, reformatting it");
+
+        // keep what we can for the host to pick up before it's lost
+        backup::rescue(&mut *db.lock_flash().await).await;
+
+        if db.format().await.is_err() {
+            return;
+        }
     }
 
     DB.set(db).ok().unwrap();
+
+    adopt_legacy::<crate::metrics::Metrics>().await;
+    adopt_legacy::<shared::keymap::Keymap>().await;
+    collect_garbage().await;
 }
 
-pub async fn set<T: core::any::Any + serde::Serialize>(value: &T) -> Option<()> {
+pub fn is_mounted() -> bool {
+    DB.get().is_some()
+}
+
+/// A value stored in the settings database
+pub trait Stored: serde::Serialize + serde::de::DeserializeOwned {
+    /// The key the value is stored under, this must never change or be reused
+    /// for something else, and needs adding to [`REGISTERED_KEYS`]. Once
+    /// nothing is stored under it, move it to [`RETIRED_KEYS`]
+    const KEY: &'static [u8];
+
+    /// Bump this whenever the serialized layout of the value changes
+    const VERSION: u8 = 0;
+
+    /// Convert a value stored by an older [`Self::VERSION`], by default old
+    /// values are thrown away
+    fn migrate(_version: u8, _data: &[u8]) -> Option<Self> {
+        None
+    }
+}
+
+/// Everything we store, see [`shared::stored::is_garbage`] for what else is
+/// deleted at boot
+const REGISTERED_KEYS: &[&[u8]] = &[
+    <crate::metrics::Metrics as Stored>::KEY,
+    <shared::keymap::Keymap as Stored>::KEY,
+    <shared::settings::Settings as Stored>::KEY,
+];
+
+/// Keys that used to be stored under and aren't any more
+const RETIRED_KEYS: &[&[u8]] = &[];
+
+pub async fn set<T: Stored>(value: &T) -> Option<()> {
     let mut buf = [0u8; ekv::config::MAX_VALUE_SIZE];
-    let buf = postcard::to_slice(value, &mut buf).ok()?;
-    let mut tx = DB.get().unwrap().write_transaction().await;
+    buf[0] = T::VERSION;
+    let len = postcard::to_slice(value, &mut buf[1..]).ok()?.len();
 
-    // convert the typeid of the key to a byte array
-    let key = unsafe {
-        core::mem::transmute::<_, [u8; core::mem::size_of::<TypeId>()]>(TypeId::of::<T>())
-    };
+    write(T::KEY, &buf[..len + 1]).await
+}
+
+pub async fn remove<T: Stored>() -> Option<()> {
+    let mut tx = DB.get()?.write_transaction().await;
 
-    tx.write(&key, &buf).await.ok()?;
+    tx.delete(T::KEY).await.ok()?;
     tx.commit().await.ok()?;
 
     Some(())
 }
 
-pub async fn get<T: core::any::Any + serde::de::DeserializeOwned>() -> Option<T> {
+pub async fn get<T: Stored>() -> Option<T> {
     let mut buf = [0u8; ekv::config::MAX_VALUE_SIZE];
 
-    let tx = DB.get().unwrap().read_transaction().await;
+    let tx = DB.get()?.read_transaction().await;
+    let len = tx.read(T::KEY, &mut buf).await.ok()?;
+    drop(tx);
+
+    let (&version, data) = buf[..len].split_first()?;
+
+    if version == T::VERSION {
+        return postcard::from_bytes(data).ok();
+    }
+
+    if version > T::VERSION {
+        // written by newer firmware, leave it be in case we get upgraded again
+        return None;
+    }
+
+    let value = T::migrate(version, data)?;
+    log::info!("Migrated flash entry from v{} to v{}", version, T::VERSION);
+    let _ = set(&value).await;
+
+    Some(value)
+}
+
+async fn write(key: &[u8], value: &[u8]) -> Option<()> {
+    let mut tx = DB.get()?.write_transaction().await;
+
+    tx.write(key, value).await.ok()?;
+    tx.commit().await.ok()?;
+
+    Some(())
+}
+
+/// Entries used to be keyed by their `TypeId`, move them over to their stable
+/// key if we can still find them.
+///
+/// This is best effort: a `TypeId` isn't stable between compiler versions, so
+/// the entry is only found if this firmware was built with the same compiler
+/// as the one that wrote it, and is otherwise thrown away by
+/// [`collect_garbage`].
+async fn adopt_legacy<T: Stored + core::any::Any>() {
+    let mut buf = [0u8; ekv::config::MAX_VALUE_SIZE];
 
-    // convert the typeid of the key to a byte array
     let key = unsafe {
         core::mem::transmute::<_, [u8; core::mem::size_of::<TypeId>()]>(TypeId::of::<T>())
     };
 
-    let len = tx.read(&key, &mut buf).await.ok()?;
+    let Some(db) = DB.get() else {
+        return;
+    };
+
+    let tx = db.read_transaction().await;
+    let Ok(len) = tx.read(&key, &mut buf).await else {
+        return;
+    };
+    drop(tx);
+
+    let Ok(value) = postcard::from_bytes::<T>(&buf[..len]) else {
+        return;
+    };
+
+    if get::<T>().await.is_none() && set(&value).await.is_none() {
+        return;
+    }
+
+    let mut tx = db.write_transaction().await;
+    if tx.delete(&key).await.is_ok() && tx.commit().await.is_ok() {
+        log::info!("Moved legacy flash entry to its stable key");
+    }
+}
+
+/// Delete entries left over from older firmware, including the `TypeId` keyed
+/// ones [`adopt_legacy`] couldn't find
+async fn collect_garbage() {
+    let Some(db) = DB.get() else {
+        return;
+    };
+
+    let mut key = [0u8; config::MAX_KEY_SIZE];
+    let mut value = [0u8; config::MAX_VALUE_SIZE];
+    let mut garbage = heapless::Vec::<heapless::Vec<u8, { config::MAX_KEY_SIZE }>, 8>::new();
+
+    {
+        let tx = db.read_transaction().await;
+        let Ok(mut cursor) = tx.read_all().await else {
+            return;
+        };
+
+        while let Ok(Some((key_len, _))) = cursor.next(&mut key, &mut value).await {
+            let key = &key[..key_len];
+
+            if shared::stored::is_garbage(key, REGISTERED_KEYS, RETIRED_KEYS) {
+                // anything past this gets picked up on the next boot
+                if garbage
+                    .push(heapless::Vec::from_slice(key).unwrap())
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        }
+    }
+
+    if garbage.is_empty() {
+        return;
+    }
+
+    // the cursor returns keys in order, which is what ekv needs when writing
+    let mut tx = db.write_transaction().await;
+    for key in &garbage {
+        if tx.delete(key).await.is_err() {
+            return;
+        }
+    }
 
-    postcard::from_bytes(&buf[..len]).ok()
+    if tx.commit().await.is_ok() {
+        log::info!("Removed {} old flash entries", garbage.len());
+    }
 }
 
+// the whole chip, updates are staged past the end of the config region
 #[cfg(feature = "m2")]
-const FLASH_SIZE: usize = 256 * 1024;
+const FLASH_SIZE: usize = 2048 * 1024;
 #[cfg(not(feature = "m2"))]
-const FLASH_SIZE: usize = 8192 * 1024;
+const FLASH_SIZE: usize = 16384 * 1024;
 
 extern "C" {
     // u32 as align is 4
     static __config_start: u32;
+    static __config_end: u32;
 }
 
 // Workaround for alignment requirements.
@@ -80,14 +253,47 @@ struct AlignedBuf<const N: usize>([u8; N]);
 
 struct DbFlash<T: NorFlash + ReadNorFlash> {
     start: usize,
+    end: usize,
     flash: T,
 }
 
+/// Access to the whole config region, offsets are from its start and buffers
+/// should be aligned
+impl<T: NorFlash + ReadNorFlash> DbFlash<T> {
+    fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// How many pages the database takes up from the start of the region, past
+    /// the end of the region is the update staging area
+    fn db_pages(&self) -> usize {
+        config::MAX_PAGE_COUNT.min(self.len() / config::PAGE_SIZE)
+    }
+
+    /// The whole flash chip, for things stored outside of the config region
+    fn chip(&mut self) -> &mut T {
+        &mut self.flash
+    }
+
+    async fn read_raw(&mut self, offset: usize, data: &mut [u8]) -> Result<(), T::Error> {
+        self.flash.read((self.start + offset) as u32, data).await
+    }
+
+    async fn write_raw(&mut self, offset: usize, data: &[u8]) -> Result<(), T::Error> {
+        self.flash.write((self.start + offset) as u32, data).await
+    }
+
+    async fn erase_raw(&mut self, offset: usize, len: usize) -> Result<(), T::Error> {
+        let from = self.start + offset;
+        self.flash.erase(from as u32, (from + len) as u32).await
+    }
+}
+
 impl<T: NorFlash + ReadNorFlash> flash::Flash for DbFlash<T> {
     type Error = T::Error;
 
     fn page_count(&self) -> usize {
-        config::MAX_PAGE_COUNT
+        self.db_pages()
     }
 
     async fn erase(&mut self, page_id: PageID) -> Result<(), <DbFlash<T> as flash::Flash>::Error> {
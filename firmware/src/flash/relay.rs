@@ -0,0 +1,175 @@
+//! Passing a firmware update on to the other half
+//!
+//! Only the half plugged into the host is sent the image. Once it has all
+//! arrived it's read back out of the staging area and sent over the
+//! interboard link a chunk at a time, each chunk being acknowledged before
+//! the next is sent. The other half stages it the same way as an upload from
+//! the host, checks the crc of the whole image and reboots to install it.
+
+use core::cell::Cell;
+
+use embassy_executor::Spawner;
+use embassy_sync::{
+    blocking_mutex::{raw::ThreadModeRawMutex, Mutex},
+    signal::Signal,
+};
+use embassy_time::{with_timeout, Duration, Timer};
+use shared::device_to_host::{Reply, RpcError};
+use shared::update::{RelayState, RelayStatus, UPDATE_CHUNK_LEN};
+
+use crate::{
+    interboard,
+    messages::{
+        device_to_device::DeviceToDevice,
+        reliable_msg,
+        transmissions::{Delivery, Undelivered},
+    },
+    side,
+    utils::log,
+};
+
+use super::{update, AlignedBuf};
+
+/// Erasing a page on the other half can take a while
+const ACK_TIMEOUT: Duration = Duration::from_millis(500);
+const ATTEMPTS: usize = 5;
+
+static STATUS: Mutex<ThreadModeRawMutex, Cell<RelayStatus>> = Mutex::new(Cell::new(RelayStatus {
+    sent: 0,
+    len: 0,
+    state: RelayState::Idle,
+}));
+
+static START: Signal<ThreadModeRawMutex, (u32, u32)> = Signal::new();
+static ACKS: Signal<ThreadModeRawMutex, Result<u32, RpcError>> = Signal::new();
+static DELIVERIES: Signal<ThreadModeRawMutex, Delivery> = Signal::new();
+
+pub fn init(spawner: &Spawner) {
+    spawner.must_spawn(relay_task());
+}
+
+pub fn status() -> RelayStatus {
+    STATUS.lock(|s| s.get())
+}
+
+fn update_status(f: impl FnOnce(&mut RelayStatus)) {
+    STATUS.lock(|s| {
+        let mut status = s.get();
+        f(&mut status);
+        s.set(status);
+    });
+}
+
+/// Check the image received from the host and start sending it to the other
+/// half
+pub async fn start(crc: u32) -> Result<Reply, RpcError> {
+    if !side::this_side_has_usb() {
+        return Err(RpcError::WrongSide);
+    }
+
+    if status().state == RelayState::Sending {
+        return Err(RpcError::Busy);
+    }
+
+    let len = update::check(crc).await?;
+
+    let status = RelayStatus {
+        sent: 0,
+        len,
+        state: RelayState::Sending,
+    };
+    STATUS.lock(|s| s.set(status));
+    START.signal((len, crc));
+
+    Ok(Reply::UpdateRelay(status))
+}
+
+#[embassy_executor::task]
+async fn relay_task() {
+    loop {
+        let (len, crc) = START.wait().await;
+
+        let state = match relay(len, crc).await {
+            Ok(()) => {
+                log::info!("Relayed the firmware update to the other side");
+                RelayState::Done
+            }
+            Err(e) => {
+                log::error!("Failed to relay the firmware update: {:?}", e);
+                RelayState::Failed(e)
+            }
+        };
+
+        update_status(|s| s.state = state);
+    }
+}
+
+async fn relay(len: u32, crc: u32) -> Result<(), RpcError> {
+    send_and_wait(DeviceToDevice::UpdateBegin { len }).await?;
+
+    let mut buf = AlignedBuf([0; UPDATE_CHUNK_LEN]);
+    for offset in (0..len).step_by(UPDATE_CHUNK_LEN) {
+        let chunk = &mut buf.0[..(len - offset).min(UPDATE_CHUNK_LEN as u32) as usize];
+        update::read(offset, chunk).await?;
+
+        let data = heapless::Vec::from_slice(chunk).unwrap();
+        let received = send_and_wait(DeviceToDevice::UpdateChunk { offset, data }).await?;
+
+        update_status(|s| s.sent = received);
+    }
+
+    send_and_wait(DeviceToDevice::UpdateCommit { crc }).await?;
+
+    Ok(())
+}
+
+/// Send a message and wait for the other side to acknowledge it, sending it
+/// again if the ack doesn't arrive
+async fn send_and_wait(msg: DeviceToDevice) -> Result<u32, RpcError> {
+    for _ in 0..ATTEMPTS {
+        ACKS.reset();
+        DELIVERIES.reset();
+        interboard::send_msg(reliable_msg(msg.clone()).reporting_to(&DELIVERIES), 3).await;
+
+        // nothing's getting through, so there's no ack to wait for
+        if DELIVERIES.wait().await == Err(Undelivered::LinkDown) {
+            break;
+        }
+
+        if let Ok(result) = with_timeout(ACK_TIMEOUT, ACKS.wait()).await {
+            return result;
+        }
+    }
+
+    Err(RpcError::OtherSideUnreachable)
+}
+
+/// Called with each ack from the other side
+pub fn acked(result: Result<u32, RpcError>) {
+    ACKS.signal(result);
+}
+
+async fn ack(result: Result<u32, RpcError>) {
+    interboard::send_msg(reliable_msg(DeviceToDevice::UpdateAck(result)), 3).await;
+}
+
+/// Handle the start of an update relayed from the other side
+pub async fn receive_begin(len: u32) {
+    ack(update::begin(len).await).await;
+}
+
+pub async fn receive_chunk(offset: u32, data: &[u8]) {
+    ack(update::receive_chunk(offset, data).await).await;
+}
+
+pub async fn receive_commit(crc: u32) {
+    match update::commit(crc).await {
+        Ok(()) => {
+            ack(Ok(0)).await;
+            // give the ack time to make it across
+            Timer::after(Duration::from_millis(100)).await;
+            cortex_m::peripheral::SCB::sys_reset();
+        }
+        Err(e) => ack(Err(e)).await,
+    }
+}
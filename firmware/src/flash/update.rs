@@ -0,0 +1,180 @@
+//! Firmware updates streamed from the host
+//!
+//! The image is written to the staging area past the config region. Once it
+//! has all arrived and matches its crc a header is written to the first page
+//! of the staging area, which the bootloader looks for at boot to swap the
+//! image into the application slot, see [`super::boot_state`].
+
+use ekv::config::PAGE_SIZE;
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex};
+use embedded_storage_async::nor_flash::{NorFlash, ReadNorFlash};
+use shared::device_to_host::RpcError;
+use shared::update::UPDATE_CHUNK_LEN;
+
+use crate::utils::log;
+
+use super::boot_state::{self, BootState};
+use super::{AlignedBuf, DB};
+
+extern "C" {
+    static __staging_start: u32;
+    static __staging_end: u32;
+}
+
+/// The header is this followed by the length and crc of the image, as little
+/// endian words. Must match the bootloader
+const UPDATE_MAGIC: u32 = 0x5550_4454;
+
+fn staging_start() -> usize {
+    unsafe { &__staging_start as *const u32 as usize }
+}
+
+/// Largest image that fits after the header page
+fn max_len() -> usize {
+    unsafe { &__staging_end as *const u32 as usize - staging_start() - PAGE_SIZE }
+}
+
+struct Upload {
+    len: u32,
+    received: u32,
+}
+
+static UPLOAD: Mutex<ThreadModeRawMutex, Upload> = Mutex::new(Upload {
+    len: 0,
+    received: 0,
+});
+
+/// Start receiving an image of `len` bytes, returning how much has been
+/// received so far
+pub async fn begin(len: u32) -> Result<u32, RpcError> {
+    if len as usize > max_len() {
+        return Err(RpcError::InvalidUpdate);
+    }
+
+    // the staging area holds the previous firmware until this one confirms
+    // itself, it's needed if this one has to be rolled back
+    if boot_state::get() == BootState::OnTrial {
+        return Err(RpcError::InvalidUpdate);
+    }
+
+    let db = DB.get().ok_or(RpcError::FlashFailure)?;
+    let mut flash = db.lock_flash().await;
+
+    // make sure the bootloader won't pick up a half written image
+    let header = staging_start() as u32;
+    flash
+        .chip()
+        .erase(header, header + PAGE_SIZE as u32)
+        .await
+        .map_err(|_| RpcError::FlashFailure)?;
+
+    let mut upload = UPLOAD.lock().await;
+    upload.len = len;
+    upload.received = 0;
+
+    Ok(0)
+}
+
+/// Store the next chunk of the image, returning how much has been received so
+/// far
+pub async fn receive_chunk(offset: u32, chunk: &[u8]) -> Result<u32, RpcError> {
+    let mut upload = UPLOAD.lock().await;
+
+    // a repeat of the last chunk, whoever sent it didn't hear our reply
+    if offset < upload.received && offset + chunk.len() as u32 == upload.received {
+        return Ok(upload.received);
+    }
+
+    // chunks are sent one at a time, so anything out of order means we missed
+    // one and the sender should start again
+    if offset != upload.received || offset + chunk.len() as u32 > upload.len {
+        return Err(RpcError::InvalidUpdate);
+    }
+
+    let db = DB.get().ok_or(RpcError::FlashFailure)?;
+    let mut flash = db.lock_flash().await;
+    let address = (staging_start() + PAGE_SIZE) as u32 + offset;
+
+    // pages are erased as they're reached, erasing everything up front takes
+    // longer than the host waits for a reply
+    let first_page = address.next_multiple_of(PAGE_SIZE as u32);
+    if first_page < address + chunk.len() as u32 {
+        flash
+            .chip()
+            .erase(first_page, first_page + PAGE_SIZE as u32)
+            .await
+            .map_err(|_| RpcError::FlashFailure)?;
+    }
+
+    let mut buf = AlignedBuf([0; UPDATE_CHUNK_LEN]);
+    buf.0[..chunk.len()].copy_from_slice(chunk);
+    flash
+        .chip()
+        .write(address, &buf.0[..chunk.len()])
+        .await
+        .map_err(|_| RpcError::FlashFailure)?;
+
+    upload.received += chunk.len() as u32;
+
+    Ok(upload.received)
+}
+
+/// Check that the whole image has arrived and matches `crc`, returning its
+/// length
+pub async fn check(crc: u32) -> Result<u32, RpcError> {
+    let upload = UPLOAD.lock().await;
+
+    if upload.len == 0 || upload.received != upload.len {
+        return Err(RpcError::InvalidUpdate);
+    }
+
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buf = AlignedBuf([0; PAGE_SIZE]);
+    for offset in (0..upload.len).step_by(PAGE_SIZE) {
+        let len = (upload.len - offset).min(PAGE_SIZE as u32) as usize;
+        read(offset, &mut buf.0[..len]).await?;
+        hasher.update(&buf.0[..len]);
+    }
+
+    if hasher.finalize() != crc {
+        return Err(RpcError::InvalidUpdate);
+    }
+
+    Ok(upload.len)
+}
+
+/// Read part of the received image
+pub async fn read(offset: u32, buf: &mut [u8]) -> Result<(), RpcError> {
+    let db = DB.get().ok_or(RpcError::FlashFailure)?;
+    let start = (staging_start() + PAGE_SIZE) as u32;
+
+    db.lock_flash()
+        .await
+        .chip()
+        .read(start + offset, buf)
+        .await
+        .map_err(|_| RpcError::FlashFailure)
+}
+
+/// Check the uploaded image and mark it for the bootloader to install, the
+/// caller then needs to reboot
+pub async fn commit(crc: u32) -> Result<(), RpcError> {
+    let len = check(crc).await?;
+
+    let db = DB.get().ok_or(RpcError::FlashFailure)?;
+    let mut flash = db.lock_flash().await;
+
+    let mut buf = AlignedBuf([0; 12]);
+    for (dest, word) in buf.0.chunks_exact_mut(4).zip([UPDATE_MAGIC, len, crc]) {
+        dest.copy_from_slice(&word.to_le_bytes());
+    }
+    flash
+        .chip()
+        .write(staging_start() as u32, &buf.0)
+        .await
+        .map_err(|_| RpcError::FlashFailure)?;
+
+    log::info!("Staged a firmware update of {} bytes", len);
+
+    Ok(())
+}
@@ -0,0 +1,16 @@
+//! Header of this firmware image, checked by the bootloader before booting it
+//!
+//! See [`shared::image`], the length and crc are filled in after the build.
+
+use shared::image::ImageHeader;
+
+const BUILD_TIME: u32 = include!(concat!(env!("OUT_DIR"), "/build_time.rs"));
+
+#[link_section = ".image_header"]
+#[used]
+static IMAGE_HEADER: ImageHeader = ImageHeader::unsealed(env!("CARGO_PKG_VERSION"), BUILD_TIME);
+
+/// The header as it was sealed, rather than as the compiler saw it
+pub fn header() -> ImageHeader {
+    unsafe { core::ptr::read_volatile(&IMAGE_HEADER) }
+}
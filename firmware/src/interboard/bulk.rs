@@ -0,0 +1,100 @@
+//! Messages too big for a [`DeviceToDevice`], sent to the other half as a run
+//! of fragments, see [`transmissions::fragments`]
+
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex, signal::Signal};
+use embassy_time::Duration;
+use serde::{Deserialize, Serialize};
+use shared::fragment::Fragment;
+use shared::keymap::{Keymap, MAX_KEYMAP_LEN};
+
+use crate::{
+    keys::keymap,
+    messages::{
+        device_to_device::DeviceToDevice,
+        reliable_msg,
+        transmissions::{
+            fragments::{fragments, Reassembler},
+            Delivery,
+        },
+    },
+    utils::log,
+};
+
+/// Longest a message can be once encoded
+const MAX_BULK_LEN: usize = MAX_KEYMAP_LEN + 8;
+
+/// How long to wait for each fragment, the link gives up on one well before
+/// this
+const FRAGMENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize)]
+pub enum Bulk {
+    /// The keymap stored on the usb side, `None` if it's using the built in
+    /// one
+    Keymap(Option<Keymap>),
+}
+
+struct Outgoing {
+    transfer: u8,
+    buf: [u8; MAX_BULK_LEN],
+}
+
+/// Held while a message is being sent, so that the fragments of two don't get
+/// mixed up
+static OUTGOING: Mutex<ThreadModeRawMutex, Outgoing> = Mutex::new(Outgoing {
+    transfer: 0,
+    buf: [0; MAX_BULK_LEN],
+});
+
+static DELIVERIES: Signal<ThreadModeRawMutex, Delivery> = Signal::new();
+
+static INCOMING: Mutex<ThreadModeRawMutex, Reassembler<MAX_BULK_LEN>> =
+    Mutex::new(Reassembler::new(FRAGMENT_TIMEOUT));
+
+/// Send a message to the other half, waiting until its fragments have been
+/// delivered
+pub async fn send(msg: &Bulk) {
+    let mut outgoing = OUTGOING.lock().await;
+    outgoing.transfer = outgoing.transfer.wrapping_add(1);
+    let transfer = outgoing.transfer;
+
+    let Ok(payload) = postcard::to_slice(msg, &mut outgoing.buf) else {
+        log::warn!("Message for the other side is too big to send");
+        return;
+    };
+
+    // behind everything else, the other half can wait for these. Each one is
+    // sent once the one before has been delivered, so they arrive in order and
+    // never take up more than one slot in the queue
+    for fragment in fragments(transfer, payload) {
+        DELIVERIES.reset();
+        let msg = reliable_msg(DeviceToDevice::Fragment(fragment)).reporting_to(&DELIVERIES);
+        super::send_msg(msg, 4).await;
+
+        if DELIVERIES.wait().await.is_err() {
+            log::warn!("Gave up sending a message to the other side");
+            return;
+        }
+    }
+}
+
+/// Called with each fragment received from the other half
+pub async fn receive(fragment: Fragment) {
+    let mut incoming = INCOMING.lock().await;
+    let Some(payload) = incoming.push(fragment) else {
+        return;
+    };
+
+    let msg = match postcard::from_bytes::<Bulk>(payload) {
+        Ok(msg) => msg,
+        Err(_e) => {
+            log::warn!("Couldn't decode a message from the other side");
+            return;
+        }
+    };
+    drop(incoming);
+
+    match msg {
+        Bulk::Keymap(keymap) => keymap::sync_from_other_side(keymap).await,
+    }
+}
@@ -4,9 +4,11 @@ use embassy_sync::pubsub::PubSubChannel;
 
 use crate::messages::device_to_device::DeviceToDevice;
 use crate::messages::transmissions;
+use crate::messages::transmissions::priority::PrioritisedMessage;
 use crate::messages::TransmittedMessage;
+use crate::watchdog;
 
-use super::onewire;
+use super::{heartbeat, onewire};
 
 pub static THIS_SIDE_MESSAGE_BUS: PubSubChannel<ThreadModeRawMutex, DeviceToDevice, 16, 6, 6> =
     PubSubChannel::new();
@@ -16,45 +18,35 @@ pub static COMMANDS_TO_OTHER_SIDE: PriorityChannel<
     embassy_sync::priority_channel::Min,
     16,
 > = PriorityChannel::new();
+pub static COUNTERS: transmissions::Counters = transmissions::Counters::new();
 
-#[derive(Debug)]
-pub struct PrioritisedMessage<T> {
-    pub msg: T,
-    pub priority: u8,
-}
-
-impl<T> Eq for PrioritisedMessage<T> {}
-
-impl<T> Ord for PrioritisedMessage<T> {
-    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
-        self.priority.cmp(&other.priority)
-    }
-}
-
-impl<T> PartialEq for PrioritisedMessage<T> {
-    fn eq(&self, other: &Self) -> bool {
-        self.priority == other.priority
-    }
-}
-
-impl<T> PartialOrd for PrioritisedMessage<T> {
-    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
-        self.priority.partial_cmp(&other.priority)
-    }
-}
+/// How many reliable messages can be on their way to the other half at once,
+/// so a burst of key events doesn't wait a round trip for each one
+const WINDOW: transmissions::Window = transmissions::Window::new(4);
 
 #[embassy_executor::task]
 pub async fn eventer_task() {
     let msg_pub = THIS_SIDE_MESSAGE_BUS.publisher().unwrap();
     let rx_fn = || async { COMMANDS_TO_OTHER_SIDE.receive().await.msg };
     let tx_fn = |e| async {
+        heartbeat::heard();
+
+        // only ever published by the heartbeat, one that arrived is corrupt or
+        // from a confused other half
+        if matches!(e, DeviceToDevice::LinkUp | DeviceToDevice::LinkDown) {
+            return;
+        }
+
         msg_pub.publish(e).await;
     };
     transmissions::eventer(
         &onewire::OTHER_SIDE_TX,
         &onewire::OTHER_SIDE_RX,
+        WINDOW,
         rx_fn,
         tx_fn,
+        watchdog::Task::Interboard,
+        &COUNTERS,
     )
     .await;
 }
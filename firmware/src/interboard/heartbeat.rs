@@ -0,0 +1,79 @@
+//! Pinging the other half so that it going quiet gets noticed
+//!
+//! Both halves ping each other, and anything at all received from the other
+//! half counts as hearing from it. The pings aren't acknowledged, so a reliable
+//! message that doesn't get through also takes the link down, as the eventer
+//! gives up on those well before the other half has gone quiet for long.
+//! [`DeviceToDevice::LinkUp`] and [`DeviceToDevice::LinkDown`] are published
+//! on this side's message bus when the link comes up and goes down.
+
+use embassy_time::{Duration, Instant};
+use portable_atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::{
+    messages::{device_to_device::DeviceToDevice, unreliable_msg},
+    utils::{log, Ticker},
+};
+
+use super::channel::{COUNTERS, THIS_SIDE_MESSAGE_BUS};
+
+const INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long the other half can go without being heard from before the link is
+/// taken to be down, and how long it stays down after a message to the other
+/// half doesn't get through
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// When the other half was last heard from, in ticks
+static LAST_HEARD: AtomicU64 = AtomicU64::new(0);
+static LINK_UP: AtomicBool = AtomicBool::new(false);
+
+/// Called with everything received from the other half
+pub fn heard() {
+    LAST_HEARD.store(Instant::now().as_ticks(), Ordering::Relaxed);
+}
+
+/// Whether the other half has been heard from recently
+pub fn link_is_up() -> bool {
+    LINK_UP.load(Ordering::Relaxed)
+}
+
+#[embassy_executor::task]
+pub async fn heartbeat_task() {
+    let msg_pub = THIS_SIDE_MESSAGE_BUS.publisher().unwrap();
+    let mut ticker = Ticker::every(INTERVAL);
+    let mut failures = COUNTERS.failures.load(Ordering::Relaxed);
+    let mut last_failed = None;
+
+    loop {
+        // dropped if the queue's full, as then it's not needed to keep the
+        // other half hearing from us
+        let _ = super::try_send_msg(unreliable_msg(DeviceToDevice::Ping), 3);
+
+        let now_failures = COUNTERS.failures.load(Ordering::Relaxed);
+        if now_failures != failures {
+            failures = now_failures;
+            last_failed = Some(Instant::now());
+        }
+        let failing = last_failed.is_some_and(|t: Instant| t.elapsed() < TIMEOUT);
+
+        let last_heard = Instant::from_ticks(LAST_HEARD.load(Ordering::Relaxed));
+        let up = last_heard.as_ticks() != 0 && last_heard.elapsed() < TIMEOUT && !failing;
+
+        if up != LINK_UP.swap(up, Ordering::Relaxed) {
+            log::info!(
+                "Link to the other half is {}",
+                if up { "up" } else { "down" }
+            );
+
+            let evt = if up {
+                DeviceToDevice::LinkUp
+            } else {
+                DeviceToDevice::LinkDown
+            };
+            msg_pub.publish(evt).await;
+        }
+
+        ticker.next().await;
+    }
+}
@@ -3,11 +3,18 @@ use embassy_rp::{
     dma, peripherals::PIO0, pio::{Common, PioPin}, Peripheral
 };
 
-use crate::messages::{device_to_device::DeviceToDevice, TransmittedMessage};
+use crate::messages::{
+    device_to_device::DeviceToDevice,
+    transmissions::{priority::PrioritisedMessage, Delivery, Report},
+    TransmittedMessage,
+};
+use crate::utils::log;
 
 pub use self::channel::THIS_SIDE_MESSAGE_BUS;
-use self::{channel::PrioritisedMessage, onewire::SM};
+use self::onewire::SM;
+pub mod bulk;
 pub mod channel;
+pub mod heartbeat;
 pub mod onewire;
 
 pub fn init(
@@ -21,16 +28,35 @@ pub fn init(
     onewire::init(spawner, common, tx_sm, rx_sm, pin, dma);
 
     spawner.must_spawn(channel::eventer_task());
+    spawner.must_spawn(heartbeat::heartbeat_task());
 }
 
 pub async fn send_msg(msg: TransmittedMessage<DeviceToDevice>, priority: u8) {
     channel::COMMANDS_TO_OTHER_SIDE
-        .send(PrioritisedMessage { msg, priority })
+        .send(PrioritisedMessage::new(msg, priority))
         .await;
 }
 
+/// Warns about reliable messages that don't get through, for those that are
+/// sent again with the next change anyway
+pub struct WarnUndelivered(pub &'static str);
+
+impl Report for WarnUndelivered {
+    fn report(&self, delivery: Delivery) {
+        if delivery.is_err() {
+            log::warn!("{} didn't reach the other half", self.0);
+        }
+    }
+}
+
+/// Whether the queue to the other half is full, which happens when it stops
+/// acknowledging what it's sent, until the eventer gives up on the link
+pub fn is_backed_up() -> bool {
+    channel::COMMANDS_TO_OTHER_SIDE.is_full()
+}
+
 pub fn try_send_msg(msg: TransmittedMessage<DeviceToDevice>, priority: u8) -> Result<(), ()> {
     channel::COMMANDS_TO_OTHER_SIDE
-        .try_send(PrioritisedMessage { msg, priority })
+        .try_send(PrioritisedMessage::new(msg, priority))
         .map_err(|_e| ())
 }
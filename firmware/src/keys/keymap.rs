@@ -0,0 +1,249 @@
+//! Keymaps uploaded by the host, which replace the compiled in [`LAYERS`]
+//! without reflashing
+
+use dilemma_keys::KeyProcessor;
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex, signal::Signal};
+use shared::device_to_host::{Reply, RpcError};
+use shared::keymap::{checksum, Keymap, MAX_KEYMAP_LEN};
+
+use crate::{
+    flash,
+    interboard::bulk::{self, Bulk},
+    side,
+    utils::log,
+};
+
+use super::{layout::LAYERS, Layers};
+
+#[cfg(feature = "alloc")]
+pub use super::runtime_layers::RuntimeLayers;
+
+/// Runtime keymaps need an allocator
+#[cfg(not(feature = "alloc"))]
+pub enum RuntimeLayers {}
+
+#[cfg(not(feature = "alloc"))]
+impl RuntimeLayers {
+    pub fn new(_keymap: &Keymap) -> Self {
+        unreachable!("runtime keymaps need the alloc feature")
+    }
+
+    /// # Safety
+    ///
+    /// Can't be called, there are no values of this type
+    pub unsafe fn layers(&self) -> &'static super::Layers {
+        match *self {}
+    }
+}
+
+// one byte goes to the version
+const _: () = assert!(MAX_KEYMAP_LEN < ekv::config::MAX_VALUE_SIZE);
+
+impl flash::Stored for Keymap {
+    const KEY: &'static [u8] = b"keymap";
+}
+
+/// `None` switches back to the compiled in layers
+static KEYMAP_UPDATES: Signal<ThreadModeRawMutex, Option<RuntimeLayers>> = Signal::new();
+
+struct Upload {
+    len: usize,
+    data: heapless::Vec<u8, MAX_KEYMAP_LEN>,
+}
+
+/// Signalled when the other half is heard from again, it might have missed a
+/// keymap change while it was away
+static OTHER_SIDE_CONNECTED: Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+static UPLOAD: Mutex<ThreadModeRawMutex, Upload> = Mutex::new(Upload {
+    len: 0,
+    data: heapless::Vec::new(),
+});
+
+/// A layout along with the runtime layers it points into, if any
+pub struct ActiveLayout {
+    // fields are dropped in order, the layout has to go first
+    pub keys: KeyProcessor,
+    _layers: Option<RuntimeLayers>,
+}
+
+impl ActiveLayout {
+    pub fn new(layers: Option<RuntimeLayers>) -> Self {
+        // Safety: the layout is dropped before the layers
+        let keys = KeyProcessor::new(unsafe { layers_of(&layers) });
+
+        Self {
+            keys,
+            _layers: layers,
+        }
+    }
+
+    /// Switch to other layers, anything held is released
+    pub fn replace(&mut self, layers: Option<RuntimeLayers>) {
+        // Safety: the layout stops using the old layers before they're dropped
+        self.keys.set_layers(unsafe { layers_of(&layers) });
+        self._layers = layers;
+    }
+}
+
+/// # Safety
+///
+/// The layers are only valid for as long as the runtime layers, if any, live
+unsafe fn layers_of(layers: &Option<RuntimeLayers>) -> &'static Layers {
+    match layers {
+        Some(l) => l.layers(),
+        None => &LAYERS,
+    }
+}
+
+/// Load the keymap stored in flash, if there is one
+pub async fn load() -> Option<RuntimeLayers> {
+    if !cfg!(feature = "alloc") {
+        return None;
+    }
+
+    let keymap = flash::get::<Keymap>().await?;
+
+    if let Err(_e) = keymap.validate() {
+        log::warn!("Ignoring invalid keymap in flash");
+        return None;
+    }
+
+    log::info!("Loaded keymap from flash");
+
+    Some(RuntimeLayers::new(&keymap))
+}
+
+/// Wait for the host to change the keymap
+pub async fn wait_for_update() -> Option<RuntimeLayers> {
+    KEYMAP_UPDATES.wait().await
+}
+
+pub async fn begin_upload(len: u16) -> Result<Reply, RpcError> {
+    if !cfg!(feature = "alloc") {
+        return Err(RpcError::UnknownCommand);
+    }
+
+    if len as usize > MAX_KEYMAP_LEN {
+        return Err(RpcError::InvalidKeymap);
+    }
+
+    let mut upload = UPLOAD.lock().await;
+    upload.len = len as usize;
+    upload.data.clear();
+
+    Ok(Reply::KeymapReceived { len: 0 })
+}
+
+pub async fn receive_chunk(offset: u16, chunk: &[u8]) -> Result<Reply, RpcError> {
+    let mut upload = UPLOAD.lock().await;
+    let offset = offset as usize;
+
+    // a repeat of the last chunk, the host didn't hear our reply
+    if offset < upload.data.len()
+        && offset + chunk.len() == upload.data.len()
+        && upload.data[offset..] == *chunk
+    {
+        return Ok(Reply::KeymapReceived {
+            len: upload.data.len() as u16,
+        });
+    }
+
+    // chunks are sent one at a time, so anything out of order means we missed
+    // one and the host should start again
+    if offset != upload.data.len() || upload.data.len() + chunk.len() > upload.len {
+        return Err(RpcError::InvalidKeymap);
+    }
+
+    upload.data.extend_from_slice(chunk).unwrap();
+
+    Ok(Reply::KeymapReceived {
+        len: upload.data.len() as u16,
+    })
+}
+
+pub async fn commit_upload(crc: u32) -> Result<Reply, RpcError> {
+    if !cfg!(feature = "alloc") {
+        return Err(RpcError::UnknownCommand);
+    }
+
+    let mut upload = UPLOAD.lock().await;
+
+    if upload.data.len() != upload.len || checksum(&upload.data) != crc {
+        return Err(RpcError::InvalidKeymap);
+    }
+
+    let keymap: Keymap = postcard::from_bytes(&upload.data).map_err(|_| RpcError::InvalidKeymap)?;
+    upload.data.clear();
+    drop(upload);
+
+    if let Err(_e) = keymap.validate() {
+        log::warn!("Rejecting invalid keymap");
+        return Err(RpcError::InvalidKeymap);
+    }
+
+    flash::set(&keymap).await.ok_or(RpcError::FlashFailure)?;
+
+    // both halves store the keymap, but only the usb side uses it
+    if side::this_side_has_usb() {
+        KEYMAP_UPDATES.signal(Some(RuntimeLayers::new(&keymap)));
+        log::info!("Switched to uploaded keymap");
+    }
+
+    Ok(Reply::KeymapApplied)
+}
+
+pub async fn reset() -> Result<Reply, RpcError> {
+    flash::remove::<Keymap>()
+        .await
+        .ok_or(RpcError::FlashFailure)?;
+
+    if side::this_side_has_usb() {
+        KEYMAP_UPDATES.signal(None);
+        log::info!("Switched back to the default keymap");
+    }
+
+    Ok(Reply::KeymapApplied)
+}
+
+pub fn other_side_connected() {
+    OTHER_SIDE_CONNECTED.signal(());
+}
+
+/// Send the keymap stored on the usb side to the other half whenever it
+/// connects, so that both halves have the same one
+#[embassy_executor::task]
+pub async fn sync_to_other_side_task() {
+    loop {
+        OTHER_SIDE_CONNECTED.wait().await;
+
+        if side::this_side_has_usb() {
+            let keymap = flash::get::<Keymap>().await;
+            bulk::send(&Bulk::Keymap(keymap)).await;
+        }
+    }
+}
+
+/// Store the keymap the usb side sent over, `None` going back to the built in
+/// one
+pub async fn sync_from_other_side(keymap: Option<Keymap>) {
+    // usually it's the one we already have, so don't wear out the flash
+    if flash::get::<Keymap>().await == keymap {
+        return;
+    }
+
+    let stored = match keymap {
+        Some(keymap) if keymap.validate().is_err() => {
+            log::warn!("Ignoring invalid keymap from the other side");
+            return;
+        }
+        Some(keymap) => flash::set(&keymap).await,
+        None => flash::remove::<Keymap>().await,
+    };
+
+    if stored.is_some() {
+        log::info!("Stored the keymap from the other side");
+    } else {
+        log::warn!("Couldn't store the keymap from the other side");
+    }
+}
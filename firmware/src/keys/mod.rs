@@ -1,43 +1,40 @@
+use dilemma_keys::{chord::ChordingEngine, Clock};
 use embassy_executor::Spawner;
-use embassy_futures::select::select;
+use embassy_futures::select::{select, select3, Either, Either3};
 use embassy_rp::gpio::{Input, Output};
 use embassy_sync::{
     blocking_mutex::raw::ThreadModeRawMutex, channel::Channel, pubsub::PubSubChannel,
 };
-use embassy_time::Duration;
-use keyberon::{key_code::KeyCode, layout::Event};
-use packed_struct::PrimitiveEnum;
+use embassy_time::{Duration, Instant};
+use keyberon::layout::Event;
+use portable_atomic::{AtomicBool, Ordering};
+use shared::keymap::{COLS, ROWS};
 use usbd_human_interface_device::device::keyboard::NKROBootKeyboardReport;
 
 use crate::{
     interboard::{self, THIS_SIDE_MESSAGE_BUS},
+    master,
     messages::{
-        device_to_device::{DeviceToDevice, MouseState},
+        device_to_device::{DeviceToDevice, MouseState, MAX_HELD_KEYS},
         reliable_msg,
+        transmissions::{Delivery, Report},
     },
+    settings::{self, SETTING_UPDATES},
     side,
     usb::hid::publish_keyboard_report,
     utils::Ticker,
+    watchdog,
 };
 
-use self::{chord::ChordingEngine, layout::LAYERS};
+use self::keymap::ActiveLayout;
 
-#[derive(Clone, Copy)]
-pub enum UnicodeMode {
-    Linux,
-    Mac,
-}
-
-#[derive(Clone, Copy)]
-pub enum CustomEvent {
-    MouseLeft,
-    MouseRight,
-    MouseScroll,
-    TypeUnicode(&'static str),
-}
+// the chord and layout macros refer to these as `crate::keys::..`
+pub use dilemma_keys::{chord, CustomEvent, Layers};
 
-pub mod chord;
+pub mod keymap;
 pub mod layout;
+#[cfg(feature = "alloc")]
+mod runtime_layers;
 pub mod scan;
 mod unicode;
 
@@ -60,10 +57,19 @@ pub type ScannerInstance<'a> = scan::Scanner<
 async fn matrix_scanner(mut scanner: ScannerInstance<'static>) {
     let mut ticker = Ticker::every(Duration::from_hz(1000));
     let matrix_events = MATRIX_EVENTS.publisher().unwrap();
+    let mut setting_updates = SETTING_UPDATES.subscriber().unwrap();
+
+    scanner.set_debounce_period(settings::current().debounce_period);
 
     let is_right = side::get_side().is_right();
 
     loop {
+        watchdog::check_in(watchdog::Task::MatrixScanner);
+
+        if let Some(s) = setting_updates.try_next_message_pure() {
+            scanner.set_debounce_period(s.debounce_period);
+        }
+
         for evt in scanner.scan() {
             let evt = if is_right {
                 evt.transform(|x, y| (x, 9 - y))
@@ -82,10 +88,17 @@ async fn matrix_scanner(mut scanner: ScannerInstance<'static>) {
 async fn matrix_processor() {
     let mut sub = MATRIX_EVENTS.subscriber().unwrap();
     let key_events = KEY_EVENTS.publisher().unwrap();
-    let mut chorder = ChordingEngine::new(layout::chorder());
+    let mut setting_updates = SETTING_UPDATES.subscriber().unwrap();
+    let mut chorder = ChordingEngine::new(layout::chorder(), EmbassyClock);
     let mut ticker = Ticker::every(Duration::from_hz(1000));
 
+    chorder.set_timeout(chord_timeout(settings::current()));
+
     loop {
+        if let Some(s) = setting_updates.try_next_message_pure() {
+            chorder.set_timeout(chord_timeout(s));
+        }
+
         match select(ticker.next(), sub.next_message_pure()).await {
             embassy_futures::select::Either::Second(evt) => {
                 //key_events.publish(evt).await;
@@ -113,15 +126,69 @@ async fn matrix_processor() {
     }
 }
 
+fn chord_timeout(settings: shared::settings::Settings) -> core::time::Duration {
+    core::time::Duration::from_millis(settings.chord_timeout_ms as u64)
+}
+
+struct EmbassyClock;
+
+impl Clock for EmbassyClock {
+    fn now(&self) -> core::time::Duration {
+        core::time::Duration::from_micros(Instant::now().as_micros())
+    }
+}
+
+/// How often to try telling the other half which keys are held, while it might
+/// have missed a key event
+const RESYNC_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Set when a key event might not have reached the other half, as a lost
+/// release would leave the key held there
+struct Resync(AtomicBool);
+
+impl Report for Resync {
+    fn report(&self, delivery: Delivery) {
+        if delivery.is_err() {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+static RESYNC: Resync = Resync(AtomicBool::new(false));
+
+/// The other half might have missed key events while it was away, or have
+/// rebooted
+pub fn other_side_connected() {
+    RESYNC.0.store(true, Ordering::Relaxed);
+}
+
 #[embassy_executor::task]
 async fn send_events_to_other_side() {
+    // what the other half has been told is held
+    let mut held = heapless::Vec::<(u8, u8), MAX_HELD_KEYS>::new();
+    let mut ticker = Ticker::every(RESYNC_INTERVAL);
+
     loop {
-        let evt = KEYS_TO_OTHER_SIDE.receive().await;
-        let evt = match evt {
-            Event::Press(x, y) => DeviceToDevice::KeyPress(x, y),
-            Event::Release(x, y) => DeviceToDevice::KeyRelease(x, y),
+        let evt = match select(KEYS_TO_OTHER_SIDE.receive(), ticker.next()).await {
+            Either::First(Event::Press(x, y)) => {
+                if !held.contains(&(x, y)) {
+                    let _ = held.push((x, y));
+                }
+                DeviceToDevice::KeyPress(x, y)
+            }
+            Either::First(Event::Release(x, y)) => {
+                held.retain(|&k| k != (x, y));
+                DeviceToDevice::KeyRelease(x, y)
+            }
+            // queued behind any events still on their way, and tried again if
+            // it doesn't get through either
+            Either::Second(()) if RESYNC.0.swap(false, Ordering::Relaxed) => {
+                DeviceToDevice::HeldKeys(held.clone())
+            }
+            Either::Second(()) => continue,
         };
-        interboard::send_msg(reliable_msg(evt), 1).await;
+
+        interboard::send_msg(reliable_msg(evt).reporting_to(&RESYNC), 1).await;
     }
 }
 
@@ -131,11 +198,46 @@ async fn receive_events_from_other_side() {
         .subscriber()
         .unwrap();
     let key_events = KEY_EVENTS.publisher().unwrap();
+    // what the other half says is held down, so its releases can be made up if
+    // it goes quiet before sending them, with room for every key so none are
+    // left out
+    let mut held = heapless::Vec::<(u8, u8), { ROWS * COLS }>::new();
 
     loop {
         let evt = match sub.next_message_pure().await {
-            DeviceToDevice::KeyPress(x, y) => Event::Press(x, y),
-            DeviceToDevice::KeyRelease(x, y) => Event::Release(x, y),
+            DeviceToDevice::KeyPress(x, y) => {
+                if !held.contains(&(x, y)) {
+                    let _ = held.push((x, y));
+                }
+                Event::Press(x, y)
+            }
+            DeviceToDevice::KeyRelease(x, y) => {
+                held.retain(|&k| k != (x, y));
+                Event::Release(x, y)
+            }
+            // release whatever it's stopped holding and press what it's
+            // started, in case their events went missing
+            DeviceToDevice::HeldKeys(keys) => {
+                for (x, y) in held.clone() {
+                    if !keys.contains(&(x, y)) {
+                        held.retain(|&k| k != (x, y));
+                        key_events.publish(Event::Release(x, y)).await;
+                    }
+                }
+                for (x, y) in keys {
+                    if !held.contains(&(x, y)) {
+                        let _ = held.push((x, y));
+                        key_events.publish(Event::Press(x, y)).await;
+                    }
+                }
+                continue;
+            }
+            DeviceToDevice::LinkDown => {
+                while let Some((x, y)) = held.pop() {
+                    key_events.publish(Event::Release(x, y)).await;
+                }
+                continue;
+            }
             _ => {
                 continue;
             }
@@ -145,36 +247,100 @@ async fn receive_events_from_other_side() {
     }
 }
 
+static MOUSE_STATE_SYNC: interboard::WarnUndelivered =
+    interboard::WarnUndelivered("The mouse buttons");
+
 #[embassy_executor::task]
 async fn key_event_processor() {
+    let mut role_updates = master::ROLE_UPDATES.subscriber().unwrap();
+
+    loop {
+        // the other half has the keys while it's the master
+        watchdog::idle(watchdog::Task::KeyEventProcessor, async {
+            while !side::this_side_has_usb() {
+                role_updates.next_message_pure().await;
+            }
+        })
+        .await;
+
+        let demoted = async {
+            while side::this_side_has_usb() {
+                role_updates.next_message_pure().await;
+            }
+        };
+
+        // starts from scratch, with whatever keymap the other half sent while
+        // it was the master
+        select(process_key_events(), demoted).await;
+
+        // release whatever was held on this half's host, which can still take
+        // it until its usb device is disabled
+        publish_keyboard_report(NKROBootKeyboardReport::new(core::iter::empty())).await;
+
+        // don't leave the mouse buttons held on the new master
+        let evt = DeviceToDevice::SyncMouseState(MouseState::new());
+        THIS_SIDE_MESSAGE_BUS
+            .immediate_publisher()
+            .publish_immediate(evt.clone());
+        interboard::send_msg(reliable_msg(evt).reporting_to(&MOUSE_STATE_SYNC), 1).await;
+    }
+}
+
+async fn process_key_events() {
     let msg_bus_pub = THIS_SIDE_MESSAGE_BUS.publisher().unwrap();
     let mut sub = KEY_EVENTS.subscriber().unwrap();
-    let mut layout = keyberon::layout::Layout::new(&LAYERS);
-    let mut state = heapless::Vec::<KeyCode, 24>::new();
+    let mut active = ActiveLayout::new(keymap::load().await);
     let mut ticker = Ticker::every(Duration::from_hz(1000));
     let mut mouse_state = MouseState::new();
 
     loop {
-        match select(ticker.next(), sub.next_message_pure()).await {
-            embassy_futures::select::Either::Second(evt) => {
+        watchdog::check_in(watchdog::Task::KeyEventProcessor);
+
+        match select3(
+            ticker.next(),
+            sub.next_message_pure(),
+            keymap::wait_for_update(),
+        )
+        .await
+        {
+            Either3::Second(evt) => {
                 // crate::utils::log::info!("evt: {:?}", evt);
 
-                layout.event(evt);
+                active.keys.event(evt);
             }
-            embassy_futures::select::Either::First(_) => {
-                let cevent = layout.tick();
-                if let Some((evt, is_press)) = match cevent {
-                    keyberon::layout::CustomEvent::NoEvent => None,
-                    keyberon::layout::CustomEvent::Press(m) => Some((*m, true)),
-                    keyberon::layout::CustomEvent::Release(m) => Some((*m, false)),
-                } {
+            Either3::Third(layers) => {
+                // start from scratch, anything held will be released
+                active.replace(layers);
+
+                if mouse_state != MouseState::new() {
+                    mouse_state = MouseState::new();
+
+                    let evt = DeviceToDevice::SyncMouseState(mouse_state);
+
+                    embassy_futures::join::join(
+                        interboard::send_msg(
+                            reliable_msg(evt.clone()).reporting_to(&MOUSE_STATE_SYNC),
+                            1,
+                        ),
+                        msg_bus_pub.publish(evt),
+                    )
+                    .await;
+                }
+            }
+            Either3::First(_) => {
+                if let Some((evt, is_press)) = active.keys.tick() {
                     match evt {
                         CustomEvent::MouseLeft => mouse_state.set_left(is_press),
                         CustomEvent::MouseRight => mouse_state.set_right(is_press),
                         CustomEvent::MouseScroll => mouse_state.set_scrolling(is_press),
                         CustomEvent::TypeUnicode(msg) => {
                             if !is_press {
-                                unicode::send_unicode(msg).await;
+                                // waits for the previous string to be typed out
+                                watchdog::idle(
+                                    watchdog::Task::KeyEventProcessor,
+                                    unicode::send_unicode(msg),
+                                )
+                                .await;
                             }
                         }
                     }
@@ -182,7 +348,10 @@ async fn key_event_processor() {
                     let evt = DeviceToDevice::SyncMouseState(mouse_state);
 
                     embassy_futures::join::join(
-                        interboard::send_msg(reliable_msg(evt.clone()), 1),
+                        interboard::send_msg(
+                            reliable_msg(evt.clone()).reporting_to(&MOUSE_STATE_SYNC),
+                            1,
+                        ),
                         msg_bus_pub.publish(evt),
                     )
                     .await;
@@ -190,15 +359,8 @@ async fn key_event_processor() {
             }
         }
 
-        let new_state = heapless::Vec::<_, 24>::from_iter(layout.keycodes());
-
-        if new_state != state {
-            state = new_state;
-
-            publish_keyboard_report(NKROBootKeyboardReport::new(state.iter().filter_map(|k| {
-                usbd_human_interface_device::page::Keyboard::from_primitive(*k as u8)
-            })))
-            .await;
+        if let Some(report) = active.keys.report() {
+            publish_keyboard_report(report).await;
         }
     }
 }
@@ -208,8 +370,8 @@ pub fn init(spawner: &Spawner, scanner: ScannerInstance<'static>) {
     spawner.must_spawn(matrix_scanner(scanner));
     spawner.must_spawn(send_events_to_other_side());
     spawner.must_spawn(receive_events_from_other_side());
-    if side::this_side_has_usb() {
-        spawner.must_spawn(key_event_processor());
-        spawner.must_spawn(unicode::unicode_task());
-    }
+    spawner.must_spawn(keymap::sync_to_other_side_task());
+    // either half can become the master, see [`crate::master`]
+    spawner.must_spawn(key_event_processor());
+    spawner.must_spawn(unicode::unicode_task());
 }
@@ -0,0 +1,169 @@
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::{raw::ThreadModeRawMutex, Mutex};
+use keyberon::{
+    action::{Action, HoldTapAction, HoldTapConfig},
+    key_code::KeyCode,
+};
+use shared::keymap::{self, BasicAction, CustomAction, KeyAction, Keymap, COLS, LAYERS, ROWS};
+
+use super::{CustomEvent, Layers};
+
+/// Strings handed to the unicode task might still be queued up after the
+/// keymap changes, so each distinct string is leaked once and reused instead
+/// of being freed with the layers
+static UNICODE_STRINGS: Mutex<ThreadModeRawMutex, RefCell<Vec<&'static str>>> =
+    Mutex::new(RefCell::new(Vec::new()));
+
+enum Allocation {
+    HoldTap(*mut HoldTapAction<CustomEvent, KeyCode>),
+    KeyCodes(*mut [KeyCode]),
+    KeyCodesRef(*mut &'static [KeyCode]),
+}
+
+/// Keyberon layers built from a [`Keymap`] at runtime
+///
+/// Keyberon wants `'static` references for everything, so the layers and
+/// everything they point to are leaked and only reclaimed when this is
+/// dropped.
+pub struct RuntimeLayers {
+    layers: *mut Layers,
+    allocations: Vec<Allocation>,
+}
+
+// the pointers are uniquely owned by this
+unsafe impl Send for RuntimeLayers {}
+
+impl RuntimeLayers {
+    /// Convert a keymap, it must have passed [`Keymap::validate`]
+    pub fn new(keymap: &Keymap) -> Self {
+        let mut this = Self {
+            layers: core::ptr::null_mut(),
+            allocations: Vec::new(),
+        };
+
+        let mut layers: Box<Layers> = Box::new([[[Action::NoOp; COLS]; ROWS]; LAYERS]);
+
+        for (layer, src_layer) in layers.iter_mut().zip(keymap.layers.iter()) {
+            for (row, src_row) in layer.iter_mut().zip(src_layer.iter()) {
+                for (action, src) in row.iter_mut().zip(src_row.iter()) {
+                    *action = this.action(keymap, src);
+                }
+            }
+        }
+
+        this.layers = Box::into_raw(layers);
+        this
+    }
+
+    /// # Safety
+    ///
+    /// The returned reference must not be used once this is dropped
+    pub unsafe fn layers(&self) -> &'static Layers {
+        &*self.layers
+    }
+
+    fn action(&mut self, keymap: &Keymap, action: &KeyAction) -> Action<CustomEvent> {
+        match action {
+            KeyAction::NoOp => Action::NoOp,
+            KeyAction::Trans => Action::Trans,
+            KeyAction::KeyCode(k) => Action::KeyCode(keycode(*k)),
+            KeyAction::MultipleKeyCodes(ks) => {
+                let codes: Box<[KeyCode]> = ks.iter().map(|k| keycode(*k)).collect();
+                let codes = Box::into_raw(codes);
+                self.allocations.push(Allocation::KeyCodes(codes));
+
+                let codes_ref = Box::into_raw(Box::new(unsafe { &*codes }));
+                self.allocations.push(Allocation::KeyCodesRef(codes_ref));
+
+                Action::MultipleKeyCodes(unsafe { &*codes_ref })
+            }
+            KeyAction::Layer(l) => Action::Layer(*l as usize),
+            KeyAction::DefaultLayer(l) => Action::DefaultLayer(*l as usize),
+            KeyAction::HoldTap {
+                timeout,
+                hold,
+                tap,
+                config,
+                tap_hold_interval,
+            } => {
+                let hold_tap = Box::into_raw(Box::new(HoldTapAction {
+                    timeout: *timeout,
+                    hold: basic_action(keymap, *hold),
+                    tap: basic_action(keymap, *tap),
+                    config: match config {
+                        keymap::HoldTapConfig::Default => HoldTapConfig::Default,
+                        keymap::HoldTapConfig::HoldOnOtherKeyPress => {
+                            HoldTapConfig::HoldOnOtherKeyPress
+                        }
+                        keymap::HoldTapConfig::PermissiveHold => HoldTapConfig::PermissiveHold,
+                    },
+                    tap_hold_interval: *tap_hold_interval,
+                }));
+                self.allocations.push(Allocation::HoldTap(hold_tap));
+
+                Action::HoldTap(unsafe { &*hold_tap })
+            }
+            KeyAction::Custom(c) => Action::Custom(custom_event(keymap, *c)),
+        }
+    }
+}
+
+impl Drop for RuntimeLayers {
+    fn drop(&mut self) {
+        unsafe {
+            drop(Box::from_raw(self.layers));
+
+            for allocation in self.allocations.drain(..) {
+                match allocation {
+                    Allocation::HoldTap(p) => drop(Box::from_raw(p)),
+                    Allocation::KeyCodes(p) => drop(Box::from_raw(p)),
+                    Allocation::KeyCodesRef(p) => drop(Box::from_raw(p)),
+                }
+            }
+        }
+    }
+}
+
+fn basic_action(keymap: &Keymap, action: BasicAction) -> Action<CustomEvent> {
+    match action {
+        BasicAction::NoOp => Action::NoOp,
+        BasicAction::Trans => Action::Trans,
+        BasicAction::KeyCode(k) => Action::KeyCode(keycode(k)),
+        BasicAction::Layer(l) => Action::Layer(l as usize),
+        BasicAction::Custom(c) => Action::Custom(custom_event(keymap, c)),
+    }
+}
+
+fn custom_event(keymap: &Keymap, action: CustomAction) -> CustomEvent {
+    match action {
+        CustomAction::MouseLeft => CustomEvent::MouseLeft,
+        CustomAction::MouseRight => CustomEvent::MouseRight,
+        CustomAction::MouseScroll => CustomEvent::MouseScroll,
+        CustomAction::TypeUnicode(idx) => {
+            CustomEvent::TypeUnicode(intern(&keymap.unicode[idx as usize]))
+        }
+    }
+}
+
+fn intern(s: &str) -> &'static str {
+    UNICODE_STRINGS.lock(|strings| {
+        let mut strings = strings.borrow_mut();
+
+        if let Some(existing) = strings.iter().find(|e| **e == s) {
+            return *existing;
+        }
+
+        let leaked: &'static str = Box::leak(String::from(s).into_boxed_str());
+        strings.push(leaked);
+        leaked
+    })
+}
+
+fn keycode(code: u8) -> KeyCode {
+    assert!(keymap::is_valid_keycode(code));
+
+    // KeyCode is a repr(u8) enum covering every code `is_valid_keycode` accepts
+    unsafe { core::mem::transmute::<u8, KeyCode>(code) }
+}
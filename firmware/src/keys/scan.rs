@@ -1,6 +1,7 @@
 use core::convert::Infallible;
 
 use embedded_hal_0_2::digital::v2::{InputPin, OutputPin};
+use shared::settings::Settings;
 
 pub struct Scanner<C, R>
 where
@@ -11,6 +12,7 @@ where
     cols: C,
     rows: R,
     debouncers: <R as ScanMatrix<C>>::Debouncers,
+    debounce_period: u8,
 }
 
 impl<C, R> Scanner<C, R>
@@ -24,11 +26,20 @@ where
             cols,
             rows,
             debouncers: Default::default(),
+            debounce_period: Settings::DEFAULT.debounce_period,
         }
     }
 
+    /// Number of scans a key has to be stable for before another change is
+    /// reported
+    pub fn set_debounce_period(&mut self, debounce_period: u8) {
+        self.debounce_period = debounce_period;
+    }
+
     pub fn scan(&mut self) -> impl Iterator<Item = keyberon::layout::Event> {
-        let scan_result = self.rows.scan_matrix(&self.cols, &mut self.debouncers);
+        let scan_result =
+            self.rows
+                .scan_matrix(&self.cols, &mut self.debouncers, self.debounce_period);
 
         scan_result.into_iter().enumerate().flat_map(|(i, col)| {
             col.into_iter()
@@ -50,11 +61,9 @@ pub trait ScanColumns {
     type Result: IntoIterator<Item = Option<bool>>;
     type Debouncers;
 
-    fn scan_columns(&self, debouncers: &mut Self::Debouncers) -> Self::Result;
+    fn scan_columns(&self, debouncers: &mut Self::Debouncers, period: u8) -> Self::Result;
 }
 
-const DEBOUNCE_PERIOD: u8 = 40; // polling at 1000hz
-
 impl<C0, C1, C2, C3> ScanColumns for (C0, C1, C2, C3)
 where
     C0: InputPin<Error = Infallible>,
@@ -63,15 +72,15 @@ where
     C3: InputPin<Error = Infallible>,
 {
     type Result = [Option<bool>; 4];
-    type Debouncers = [Debouncer<DEBOUNCE_PERIOD>; 4];
+    type Debouncers = [Debouncer; 4];
 
-    fn scan_columns(&self, debouncers: &mut Self::Debouncers) -> Self::Result {
+    fn scan_columns(&self, debouncers: &mut Self::Debouncers, period: u8) -> Self::Result {
         cortex_m::asm::delay(1000);
         [
-            debouncers[0].update(self.0.is_low().unwrap()),
-            debouncers[1].update(self.1.is_low().unwrap()),
-            debouncers[2].update(self.2.is_low().unwrap()),
-            debouncers[3].update(self.3.is_low().unwrap()),
+            debouncers[0].update(self.0.is_low().unwrap(), period),
+            debouncers[1].update(self.1.is_low().unwrap(), period),
+            debouncers[2].update(self.2.is_low().unwrap(), period),
+            debouncers[3].update(self.3.is_low().unwrap(), period),
         ]
     }
 }
@@ -80,7 +89,12 @@ pub trait ScanMatrix<C: ScanColumns> {
     type Result: IntoIterator<Item = C::Result>;
     type Debouncers;
 
-    fn scan_matrix(&mut self, columns: &C, debouncers: &mut Self::Debouncers) -> Self::Result;
+    fn scan_matrix(
+        &mut self,
+        columns: &C,
+        debouncers: &mut Self::Debouncers,
+        period: u8,
+    ) -> Self::Result;
 }
 
 impl<C, R0, R1, R2, R3, R4> ScanMatrix<C> for (R0, R1, R2, R3, R4)
@@ -95,59 +109,56 @@ where
     type Result = [C::Result; 5];
     type Debouncers = [C::Debouncers; 5];
 
-    fn scan_matrix(&mut self, columns: &C, debouncers: &mut Self::Debouncers) -> Self::Result {
+    fn scan_matrix(
+        &mut self,
+        columns: &C,
+        debouncers: &mut Self::Debouncers,
+        period: u8,
+    ) -> Self::Result {
         self.0.set_low().unwrap();
-        let a = columns.scan_columns(&mut debouncers[0]);
+        let a = columns.scan_columns(&mut debouncers[0], period);
         self.0.set_high().unwrap();
 
         self.1.set_low().unwrap();
-        let b = columns.scan_columns(&mut debouncers[1]);
+        let b = columns.scan_columns(&mut debouncers[1], period);
         self.1.set_high().unwrap();
 
         self.2.set_low().unwrap();
-        let c = columns.scan_columns(&mut debouncers[2]);
+        let c = columns.scan_columns(&mut debouncers[2], period);
         self.2.set_high().unwrap();
 
         self.3.set_low().unwrap();
-        let d = columns.scan_columns(&mut debouncers[3]);
+        let d = columns.scan_columns(&mut debouncers[3], period);
         self.3.set_high().unwrap();
 
         self.4.set_low().unwrap();
-        let e = columns.scan_columns(&mut debouncers[4]);
+        let e = columns.scan_columns(&mut debouncers[4], period);
         self.4.set_high().unwrap();
 
         [a, b, c, d, e]
     }
 }
 
-pub struct Debouncer<const MAX: u8> {
+#[derive(Default)]
+pub struct Debouncer {
     timer: u8,
     is_pressed: bool,
 }
 
-impl<const MAX: u8> Default for Debouncer<MAX> {
-    fn default() -> Self {
-        Self {
-            timer: 0,
-            is_pressed: false,
-        }
-    }
-}
-
-impl<const MAX: u8> Debouncer<MAX> {
-    fn update(&mut self, is_pressed: bool) -> Option<bool> {
+impl Debouncer {
+    fn update(&mut self, is_pressed: bool, period: u8) -> Option<bool> {
         self.timer = self.timer.saturating_sub(1);
 
         if is_pressed {
-            self.pressed()
+            self.pressed(period)
         } else {
-            self.unpressed()
+            self.unpressed(period)
         }
     }
 
-    fn unpressed(&mut self) -> Option<bool> {
+    fn unpressed(&mut self, period: u8) -> Option<bool> {
         if self.timer == 0 && self.is_pressed {
-            self.timer = MAX;
+            self.timer = period;
             self.is_pressed = false;
             return Some(false);
         }
@@ -155,9 +166,9 @@ impl<const MAX: u8> Debouncer<MAX> {
         None
     }
 
-    fn pressed(&mut self) -> Option<bool> {
+    fn pressed(&mut self, period: u8) -> Option<bool> {
         if self.timer == 0 && !self.is_pressed {
-            self.timer = MAX;
+            self.timer = period;
             self.is_pressed = true;
 
             return Some(true);
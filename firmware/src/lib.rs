@@ -28,8 +28,6 @@ use embassy_rp::usb::Driver;
 use embassy_time::{Duration, Timer};
 use shared::side::KeyboardSide;
 
-#[cfg(not(feature = "probe"))]
-use panic_reset as _;
 #[cfg(feature = "probe")]
 use {defmt_rtt as _, panic_probe as _};
 
@@ -41,29 +39,36 @@ use crate::keys::ScannerInstance;
 mod allocator;
 #[cfg(feature = "binaryinfo")]
 pub mod binary_info;
+mod crash;
 #[cfg(feature = "display-slint")]
 mod display;
 pub mod event;
 mod flash;
+#[cfg(feature = "bootloader")]
+mod image;
 pub mod interboard;
 pub mod keys;
 pub mod logger;
+pub mod master;
 pub mod messages;
 mod metrics;
 pub mod rgb;
 pub mod rng;
+pub mod settings;
 pub mod side;
 pub mod trackpad;
 pub mod usb;
 pub mod utils;
+mod watchdog;
 
 pub fn set_status_led(value: Level) {
     unsafe { ManuallyDrop::new(Output::new(PIN_17::steal(), value)).set_level(value) };
 }
 
 pub static VERSION: &str = "0.1.0";
+pub static BUILD_DATE: &str = include_str!(concat!(env!("OUT_DIR"), "/build_date.txt"));
 
-fn detect_usb(pin: Input) -> bool {
+fn detect_usb(pin: &Input) -> bool {
     let connected = pin.is_high();
     log::info!("Usb connected? {}", connected);
     connected
@@ -114,22 +119,19 @@ pub async fn main(spawner: Spawner) {
 
     log::info!("Just a whisper, I hear it in my ghost.");
 
+    crash::init();
+
     set_status_led(Level::High);
 
     let s = detect_side(Input::new(p.PIN_29, embassy_rp::gpio::Pull::Down));
-    side::init(
-        s,
-        detect_usb(Input::new(p.PIN_19, embassy_rp::gpio::Pull::Down)),
-    );
-
-    if side::this_side_has_usb() {
-        log::info!("usb connected");
-        let usb_driver = Driver::new(p.USB, UsbIrqs);
+    let vbus_pin = Input::new(p.PIN_19, embassy_rp::gpio::Pull::Down);
+    let vbus = detect_usb(&vbus_pin);
+    side::init(s);
+    master::init(&spawner, vbus_pin, vbus);
 
-        usb::init(&spawner, usb_driver);
-    } else {
-        log::info!("No usb connected");
-    }
+    // started on both halves, usb can be plugged into either later on
+    let usb_driver = Driver::new(p.USB, UsbIrqs);
+    usb::init(&spawner, usb_driver);
 
     messages::init(&spawner);
 
@@ -142,6 +144,7 @@ pub async fn main(spawner: Spawner) {
     interboard::init(&spawner, &mut pio0.common, pio0.sm0, pio0.sm1, p.PIN_1, p.DMA_CH4);
 
     flash::init(p.FLASH, p.DMA_CH3.degrade()).await;
+    settings::init(&spawner).await;
 
     let mut pio1 = Pio::new(p.PIO1, PioIrq1);
     rgb::init(&spawner, &mut pio1.common, pio1.sm0, p.PIN_10, p.DMA_CH2);
@@ -195,6 +198,21 @@ pub async fn main(spawner: Spawner) {
 
     metrics::init(&spawner).await;
 
+    #[cfg(feature = "bootloader")]
+    flash::relay::init(&spawner);
+
+    // the bootloader rolls back to the previous firmware if this isn't reached
+    #[cfg(feature = "bootloader")]
+    let confirmed = flash::boot_state::confirm().await;
+    #[cfg(not(feature = "bootloader"))]
+    let confirmed = true;
+
+    // an image that couldn't confirm itself is left to the bootloader's
+    // watchdog, which runs out and rolls it back
+    if confirmed {
+        watchdog::init(&spawner, p.WATCHDOG);
+    }
+
     log::info!("All set up, have fun :)");
 
     // allowing the main task to exit somehow causes the LED task to break?
@@ -0,0 +1,186 @@
+//! Which half talks to the host
+//!
+//! Usb can be plugged into either half, and moved between them while the
+//! keyboard is running. Each half watches its own vbus and tells the other
+//! whether it has usb, and the half that does is the master: it runs the key
+//! processor, sends reports to the host and picks the animations.
+//!
+//! If both do, the half picked by the `master-side` setting is the master and
+//! the other stops answering its host, so that only one keyboard shows up.
+//! Each half tells the other which one its setting picks along with whether it
+//! has usb, and while the two copies disagree (they're synced, but might not
+//! have been yet) the left half is picked. The master's keymap is synced to
+//! the other half (see [`crate::keys::keymap`]), so either can take over.
+//!
+//! Neither half is the master at boot until it's heard whether the other has
+//! usb, or given up waiting for it, so that two plugged in halves don't both
+//! show up as a keyboard in the meantime.
+//!
+//! Changes are published on [`ROLE_UPDATES`], and [`side::this_side_has_usb`]
+//! says which half is the master at the moment.
+
+use embassy_executor::Spawner;
+use embassy_rp::gpio::Input;
+use embassy_sync::{
+    blocking_mutex::raw::ThreadModeRawMutex, pubsub::PubSubChannel, signal::Signal,
+};
+use embassy_time::{with_timeout, Duration};
+use portable_atomic::{AtomicBool, Ordering};
+use shared::side::KeyboardSide;
+
+use crate::{
+    interboard,
+    keys::keymap,
+    messages::{device_to_device::DeviceToDevice, reliable_msg},
+    settings, side,
+    utils::{log, Ticker},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How many polls in a row vbus has to read the same before a change counts,
+/// so a cable being wiggled in doesn't hand the keyboard back and forth
+const SETTLE_POLLS: u8 = 4;
+
+/// How long to wait at boot to hear from the other half, long enough for the
+/// link to come up if it's there
+const ELECTION_TIMEOUT: Duration = Duration::from_millis(1500);
+
+static VBUS: AtomicBool = AtomicBool::new(false);
+static OTHER_SIDE_VBUS: AtomicBool = AtomicBool::new(false);
+static OTHER_SIDE_PREFERS_LEFT: AtomicBool = AtomicBool::new(true);
+
+/// Cleared once the other half has been heard from at boot, or waited for long
+/// enough
+static ELECTING: AtomicBool = AtomicBool::new(true);
+static HEARD_FROM_OTHER_SIDE: Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+/// Whether this half has become the master, subscribed to by the key processor
+/// and the usb device
+pub static ROLE_UPDATES: PubSubChannel<ThreadModeRawMutex, bool, 1, 2, 1> = PubSubChannel::new();
+
+/// Start watching vbus, `vbus` is what it read at boot
+pub fn init(spawner: &Spawner, pin: Input<'static>, vbus: bool) {
+    VBUS.store(vbus, Ordering::Relaxed);
+
+    spawner.must_spawn(vbus_monitor(pin));
+}
+
+#[embassy_executor::task]
+async fn vbus_monitor(pin: Input<'static>) {
+    if with_timeout(ELECTION_TIMEOUT, HEARD_FROM_OTHER_SIDE.wait())
+        .await
+        .is_err()
+    {
+        log::info!("Didn't hear from the other half, deciding the master alone");
+    }
+    ELECTING.store(false, Ordering::Relaxed);
+    update();
+
+    let mut ticker = Ticker::every(POLL_INTERVAL);
+    let mut settled = 0;
+
+    loop {
+        ticker.next().await;
+
+        let vbus = pin.is_high();
+        if vbus == VBUS.load(Ordering::Relaxed) {
+            settled = 0;
+            continue;
+        }
+
+        settled += 1;
+        if settled < SETTLE_POLLS {
+            continue;
+        }
+        settled = 0;
+
+        log::info!("Usb {}", if vbus { "plugged in" } else { "unplugged" });
+
+        VBUS.store(vbus, Ordering::Relaxed);
+        tell_other_side().await;
+        update();
+    }
+}
+
+/// Which half this one's settings pick as the master when both have usb
+fn preferred() -> KeyboardSide {
+    match settings::current().master_side {
+        0 => KeyboardSide::Left,
+        _ => KeyboardSide::Right,
+    }
+}
+
+async fn tell_other_side() {
+    let msg = DeviceToDevice::HasUsb {
+        has_usb: VBUS.load(Ordering::Relaxed),
+        preferred: preferred(),
+    };
+    interboard::send_msg(reliable_msg(msg), 3).await;
+}
+
+/// Called when the `master-side` setting changes
+pub async fn preference_changed() {
+    tell_other_side().await;
+    update();
+}
+
+/// The other half might have missed a change while it was away, or have
+/// rebooted
+pub async fn other_side_connected() {
+    tell_other_side().await;
+}
+
+/// Whatever it had before, the other half can't be talking to the host for us
+/// while it's not responding
+pub fn other_side_lost() {
+    OTHER_SIDE_VBUS.store(false, Ordering::Relaxed);
+    update();
+}
+
+pub fn other_side_has_usb(has_usb: bool, preferred: KeyboardSide) {
+    OTHER_SIDE_VBUS.store(has_usb, Ordering::Relaxed);
+    OTHER_SIDE_PREFERS_LEFT.store(preferred.is_left(), Ordering::Relaxed);
+    HEARD_FROM_OTHER_SIDE.signal(());
+    update();
+}
+
+/// Which half is the master when both have usb, both halves come to the same
+/// answer as long as they've heard from each other
+fn elected() -> KeyboardSide {
+    let other_side_prefers_left = OTHER_SIDE_PREFERS_LEFT.load(Ordering::Relaxed);
+
+    match preferred() {
+        KeyboardSide::Right if !other_side_prefers_left => KeyboardSide::Right,
+        _ => KeyboardSide::Left,
+    }
+}
+
+fn update() {
+    if ELECTING.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let vbus = VBUS.load(Ordering::Relaxed);
+    let other_side_vbus = OTHER_SIDE_VBUS.load(Ordering::Relaxed);
+    let master = vbus && (!other_side_vbus || side::get_side() == elected());
+
+    if master == side::this_side_has_usb() {
+        return;
+    }
+
+    log::info!(
+        "This half is {} the master",
+        if master { "now" } else { "no longer" }
+    );
+
+    side::set_has_usb(master);
+    ROLE_UPDATES.immediate_publisher().publish_immediate(master);
+
+    // the other half was only sent our keymap and settings if it connected
+    // while we were the master, which at boot is before the election finishes
+    if master {
+        keymap::other_side_connected();
+        settings::other_side_connected();
+    }
+}
@@ -1,9 +1,21 @@
 use core::hash::Hash;
 use serde::{Deserialize, Serialize};
-use shared::{device_to_host::DeviceToHost, hid::MouseReport, host_to_device::HostToDeviceMsg};
+use shared::{
+    device_to_host::{DeviceToHost, RpcError},
+    fragment::Fragment,
+    hid::MouseReport,
+    host_to_device::HostToDevice,
+    keymap::{COLS, ROWS},
+    settings::Setting,
+    side::KeyboardSide,
+    update::UPDATE_CHUNK_LEN,
+};
 
 use crate::rgb::animations::AnimationSync;
 
+/// The most keys one half can be holding down at once
+pub const MAX_HELD_KEYS: usize = ROWS * COLS / 2;
+
 #[cfg_attr(feature = "probe", derive(defmt::Format))]
 #[bitfield_struct::bitfield(u8)]
 #[derive(Serialize, Deserialize, Eq, PartialEq, Hash)]
@@ -20,12 +32,42 @@ pub struct MouseState {
 pub enum DeviceToDevice {
     Ping,
     Pong,
-    ForwardedFromHost(HostToDeviceMsg),
+    ForwardedFromHost(HostToDevice),
     ForwardedToHost(DeviceToHost),
     ForwardedToHostMouse(MouseReport),
     KeyPress(u8, u8),
     KeyRelease(u8, u8),
+    /// Every key the sending half is holding down, sent when one of its key
+    /// events might not have got through
+    HeldKeys(heapless::Vec<(u8, u8), MAX_HELD_KEYS>),
     SetAnimation(AnimationSync),
     SyncAnimation(AnimationSync),
     SyncMouseState(MouseState),
+    SyncSetting(Setting),
+    UpdateBegin {
+        len: u32,
+    },
+    UpdateChunk {
+        offset: u32,
+        data: heapless::Vec<u8, UPDATE_CHUNK_LEN>,
+    },
+    UpdateCommit {
+        crc: u32,
+    },
+    UpdateAck(Result<u32, RpcError>),
+    /// Part of a message too big to send in one go, see
+    /// [`crate::interboard::bulk`]
+    Fragment(Fragment),
+    /// Whether the sending half has usb plugged in, and which half its
+    /// settings pick as the master if both do, see [`crate::master`]
+    HasUsb {
+        has_usb: bool,
+        preferred: KeyboardSide,
+    },
+    /// Published on this side's message bus when the other half is heard from
+    /// again, never sent. See [`crate::interboard::heartbeat`]
+    LinkUp,
+    /// Published on this side's message bus when the other half goes quiet,
+    /// never sent. Both are dropped if they arrive from the other half
+    LinkDown,
 }
@@ -1,12 +1,31 @@
-use shared::device_to_host::{DeviceToHost, DeviceToHostMsg};
-use shared::host_to_device::HostToDeviceMsg;
+use embassy_time::{Duration, Timer};
+use portable_atomic::AtomicBool;
+use shared::device_to_host::{DeviceToHost, DeviceToHostMsg, Reply, RpcError};
+use shared::handshake::{Capabilities, DeviceInfo, PROTOCOL_VERSION};
+use shared::host_to_device::{HostToDevice, HostToDeviceMsg, RequestId};
+use shared::link_stats::LinkKind;
 
-use crate::side;
-use crate::{interboard, usb};
+use crate::flash::backup;
+#[cfg(feature = "bootloader")]
+use crate::flash::{relay, update};
+use crate::keys::{self, keymap};
+use crate::rgb::animations::DynAnimation;
+use crate::{crash, flash, interboard, master, rgb, trackpad, usb};
+use crate::{settings, side};
 
 use super::device_to_device::DeviceToDevice;
 use super::{reliable_msg, unreliable_msg, TransmittedMessage};
 
+/// Set once the host has said hello with a protocol version we understand,
+/// until then anything else it sends could be garbage
+static HOST_COMPATIBLE: AtomicBool = AtomicBool::new(false);
+
+/// Forget about the handshake, called when the serial connection drops and when
+/// the host resets, suspends or unconfigures the usb device
+pub fn reset_host_session() {
+    HOST_COMPATIBLE.store(false, portable_atomic::Ordering::Relaxed);
+}
+
 #[embassy_executor::task]
 pub async fn from_usb_distributor() {
     let mut sub = crate::usb::COMMANDS_FROM_HOST.subscriber().unwrap();
@@ -14,18 +33,167 @@ pub async fn from_usb_distributor() {
     loop {
         let msg = sub.next_message_pure().await;
 
+        if let HostToDeviceMsg::Hello { protocol_version } = msg.msg {
+            let compatible = protocol_version == PROTOCOL_VERSION;
+            if !compatible {
+                crate::log::warn!(
+                    "Host speaks protocol v{}, we speak v{}",
+                    protocol_version,
+                    PROTOCOL_VERSION
+                );
+            }
+            HOST_COMPATIBLE.store(compatible, portable_atomic::Ordering::Relaxed);
+        } else if !HOST_COMPATIBLE.load(portable_atomic::Ordering::Relaxed) {
+            crate::log::warn!("Ignoring a message from the host before the handshake");
+            continue;
+        }
+
         if msg.targets_side(side::get_side()) {
-            #[allow(unreachable_code)]
-            handle_from_host(msg.msg.clone()).await;
+            handle_from_host(msg.clone()).await;
         }
         if msg.targets_side(side::get_other_side()) {
-            interboard::send_msg(reliable_msg(DeviceToDevice::ForwardedFromHost(msg.msg)), 2).await;
+            interboard::send_msg(reliable_msg(DeviceToDevice::ForwardedFromHost(msg)), 2).await;
         }
     }
 }
 
-async fn handle_from_host(msg: HostToDeviceMsg) {
-    match msg {}
+async fn handle_from_host(req: HostToDevice) {
+    let id = req.id;
+
+    let result = match req.msg {
+        HostToDeviceMsg::Hello { protocol_version } => {
+            // sent first so that it's already there once the host has both
+            // hellos, an older host wouldn't understand it
+            if protocol_version == PROTOCOL_VERSION {
+                if let Some(report) = crash::take() {
+                    let msg = DeviceToHostMsg::CrashReport(report);
+                    send_to_host(reliable_msg(msg), MessageProvenance::Origin).await;
+                }
+            }
+
+            // the host checks the version, it'll need our info either way to
+            // tell the user what went wrong
+            let msg = DeviceToHostMsg::Hello(device_info());
+            send_to_host(reliable_msg(msg), MessageProvenance::Origin).await;
+            return;
+        }
+        HostToDeviceMsg::Ping { payload } => Ok(Reply::Pong { payload }),
+        HostToDeviceMsg::Reboot => {
+            respond_to_host(id, Ok(Reply::Rebooting)).await;
+            wait_for_reply_to_flush().await;
+            cortex_m::peripheral::SCB::sys_reset();
+        }
+        HostToDeviceMsg::RebootToBootloader => {
+            respond_to_host(id, Ok(Reply::Rebooting)).await;
+            wait_for_reply_to_flush().await;
+            embassy_rp::rom_data::reset_to_usb_boot(1 << 17, 0);
+            unreachable!();
+        }
+        HostToDeviceMsg::SetAnimation(kind) => {
+            // the usb side is in charge of animations, it'll update the other side
+            if side::this_side_has_usb() {
+                rgb::set_animation(DynAnimation::from_kind(kind)).await;
+                Ok(Reply::AnimationSet(kind))
+            } else if req.target_side.is_none() {
+                // the other side got this too and will update us
+                Ok(Reply::AnimationSet(kind))
+            } else {
+                Err(RpcError::WrongSide)
+            }
+        }
+        HostToDeviceMsg::QueryVersion => {
+            let version = heapless::String::try_from(crate::VERSION).unwrap();
+            Ok(Reply::Version { version })
+        }
+        HostToDeviceMsg::BeginKeymap { len } => keymap::begin_upload(len).await,
+        HostToDeviceMsg::KeymapChunk { offset, data } => keymap::receive_chunk(offset, &data).await,
+        HostToDeviceMsg::CommitKeymap { crc } => keymap::commit_upload(crc).await,
+        HostToDeviceMsg::ResetKeymap => keymap::reset().await,
+        HostToDeviceMsg::GetSettings => Ok(Reply::Settings(settings::current())),
+        HostToDeviceMsg::SetSetting(setting) => settings::set(setting).await,
+        HostToDeviceMsg::ConfigStatus => Ok(Reply::ConfigStatus(backup::status().await)),
+        HostToDeviceMsg::BeginBackup(source) => backup::begin_backup(source).await,
+        HostToDeviceMsg::ReadBackup { source, offset } => backup::read_backup(source, offset).await,
+        HostToDeviceMsg::BeginRestore(header) => backup::begin_restore(header).await,
+        HostToDeviceMsg::RestoreChunk { offset, data } => {
+            backup::receive_restore_chunk(offset, &data).await
+        }
+        HostToDeviceMsg::CommitRestore => match backup::check_restore().await {
+            Ok(()) => {
+                respond_to_host(id, Ok(Reply::Rebooting)).await;
+                wait_for_reply_to_flush().await;
+                backup::apply_restore().await;
+            }
+            Err(e) => Err(e),
+        },
+        #[cfg(feature = "bootloader")]
+        HostToDeviceMsg::BeginUpdate { len } => update::begin(len)
+            .await
+            .map(|len| Reply::UpdateReceived { len }),
+        #[cfg(feature = "bootloader")]
+        HostToDeviceMsg::UpdateChunk { offset, data } => update::receive_chunk(offset, &data)
+            .await
+            .map(|len| Reply::UpdateReceived { len }),
+        #[cfg(feature = "bootloader")]
+        HostToDeviceMsg::CommitUpdate { crc } => match update::commit(crc).await {
+            Ok(()) => {
+                respond_to_host(id, Ok(Reply::Rebooting)).await;
+                wait_for_reply_to_flush().await;
+                cortex_m::peripheral::SCB::sys_reset();
+            }
+            Err(e) => Err(e),
+        },
+        #[cfg(feature = "bootloader")]
+        HostToDeviceMsg::RelayUpdate { crc } => relay::start(crc).await,
+        #[cfg(feature = "bootloader")]
+        HostToDeviceMsg::UpdateRelayStatus => Ok(Reply::UpdateRelay(relay::status())),
+        #[cfg(feature = "bootloader")]
+        HostToDeviceMsg::GetImageHeader => Ok(Reply::ImageHeader(crate::image::header())),
+        // without the bootloader there are no updates or image header
+        #[cfg(not(feature = "bootloader"))]
+        HostToDeviceMsg::BeginUpdate { .. }
+        | HostToDeviceMsg::UpdateChunk { .. }
+        | HostToDeviceMsg::CommitUpdate { .. }
+        | HostToDeviceMsg::RelayUpdate { .. }
+        | HostToDeviceMsg::UpdateRelayStatus
+        | HostToDeviceMsg::GetImageHeader => Err(RpcError::UnknownCommand),
+        HostToDeviceMsg::FactoryReset => {
+            respond_to_host(id, Ok(Reply::Rebooting)).await;
+            wait_for_reply_to_flush().await;
+            backup::factory_reset().await;
+        }
+        HostToDeviceMsg::GetLinkStats(kind) => Ok(Reply::LinkStats(match kind {
+            LinkKind::Usb => usb::channel::COUNTERS.snapshot(),
+            LinkKind::Interboard => interboard::channel::COUNTERS.snapshot(),
+        })),
+    };
+
+    respond_to_host(id, result).await;
+}
+
+fn device_info() -> DeviceInfo {
+    let display = cfg!(feature = "display-slint") && side::get_side().is_left();
+
+    DeviceInfo {
+        protocol_version: PROTOCOL_VERSION,
+        firmware_version: heapless::String::try_from(crate::VERSION).unwrap(),
+        build_date: heapless::String::try_from(crate::BUILD_DATE.trim()).unwrap_or_default(),
+        capabilities: Capabilities::new()
+            .with_display(display)
+            .with_trackpad(trackpad::is_present())
+            .with_flash_db(flash::is_mounted())
+            .with_usb(side::this_side_has_usb())
+            .with_update(cfg!(feature = "bootloader")),
+    }
+}
+
+async fn respond_to_host(id: RequestId, result: Result<Reply, RpcError>) {
+    let msg = DeviceToHostMsg::Response { id, result };
+    send_to_host(reliable_msg(msg), MessageProvenance::Origin).await;
+}
+
+async fn wait_for_reply_to_flush() {
+    Timer::after(Duration::from_millis(100)).await;
 }
 
 #[embassy_executor::task]
@@ -41,17 +209,53 @@ pub async fn from_other_side_distributor() {
         match msg {
             DeviceToDevice::Ping => {
                 // log::info!("Got a ping");
-                interboard::send_msg(reliable_msg(DeviceToDevice::Pong), 3).await;
+                // pings are a heartbeat, so there's no point retrying
+                interboard::send_msg(unreliable_msg(DeviceToDevice::Pong), 3).await;
             }
             DeviceToDevice::Pong => {
                 // log::info!("Got a pong");
             }
             DeviceToDevice::ForwardedToHost(msg) => {
-                usb::send_msg(unreliable_msg(msg)).await;
+                // responses need to make it back, logs can be dropped
+                let msg = if matches!(
+                    msg.msg,
+                    DeviceToHostMsg::Response { .. }
+                        | DeviceToHostMsg::Hello(_)
+                        | DeviceToHostMsg::CrashReport(_)
+                ) {
+                    reliable_msg(msg)
+                } else {
+                    unreliable_msg(msg)
+                };
+                usb::send_msg(msg).await;
             }
             DeviceToDevice::ForwardedFromHost(msg) => {
                 handle_from_host(msg).await;
             }
+            DeviceToDevice::SyncSetting(setting) => {
+                settings::sync_from_other_side(setting).await;
+            }
+            DeviceToDevice::Fragment(fragment) => interboard::bulk::receive(fragment).await,
+            DeviceToDevice::HasUsb { has_usb, preferred } => {
+                master::other_side_has_usb(has_usb, preferred);
+            }
+            DeviceToDevice::LinkUp => {
+                keys::other_side_connected();
+                keymap::other_side_connected();
+                settings::other_side_connected();
+                master::other_side_connected().await;
+            }
+            DeviceToDevice::LinkDown => master::other_side_lost(),
+            #[cfg(feature = "bootloader")]
+            DeviceToDevice::UpdateBegin { len } => relay::receive_begin(len).await,
+            #[cfg(feature = "bootloader")]
+            DeviceToDevice::UpdateChunk { offset, data } => {
+                relay::receive_chunk(offset, &data).await;
+            }
+            #[cfg(feature = "bootloader")]
+            DeviceToDevice::UpdateCommit { crc } => relay::receive_commit(crc).await,
+            #[cfg(feature = "bootloader")]
+            DeviceToDevice::UpdateAck(result) => relay::acked(result),
             _ => {}
         }
     }
@@ -64,7 +268,11 @@ pub enum MessageProvenance {
 }
 
 pub async fn send_to_host(
-    TransmittedMessage { msg, timeout }: TransmittedMessage<DeviceToHostMsg>,
+    TransmittedMessage {
+        msg,
+        timeout,
+        report,
+    }: TransmittedMessage<DeviceToHostMsg>,
     provenance: MessageProvenance,
 ) {
     let side = side::get_side();
@@ -73,17 +281,29 @@ pub async fn send_to_host(
         msg,
     };
     if side::this_side_has_usb() {
-        let msg = TransmittedMessage { msg, timeout };
+        let msg = TransmittedMessage {
+            msg,
+            timeout,
+            report,
+        };
         usb::send_msg(msg).await;
     } else if provenance == MessageProvenance::Origin {
         let msg = DeviceToDevice::ForwardedToHost(msg);
-        let msg = TransmittedMessage { msg, timeout };
+        let msg = TransmittedMessage {
+            msg,
+            timeout,
+            report,
+        };
         interboard::send_msg(msg, 3).await;
     }
 }
 
 pub fn try_send_to_host(
-    TransmittedMessage { msg, timeout }: TransmittedMessage<DeviceToHostMsg>,
+    TransmittedMessage {
+        msg,
+        timeout,
+        report,
+    }: TransmittedMessage<DeviceToHostMsg>,
     provenance: MessageProvenance,
 ) -> Option<()> {
     let side = side::get_side();
@@ -92,11 +312,19 @@ pub fn try_send_to_host(
         msg,
     };
     if side::this_side_has_usb() {
-        let msg = TransmittedMessage { msg, timeout };
+        let msg = TransmittedMessage {
+            msg,
+            timeout,
+            report,
+        };
         usb::try_send_msg(msg).ok()
     } else if provenance == MessageProvenance::Origin {
         let msg = DeviceToDevice::ForwardedToHost(msg);
-        let msg = TransmittedMessage { msg, timeout };
+        let msg = TransmittedMessage {
+            msg,
+            timeout,
+            report,
+        };
         interboard::try_send_msg(msg, 3).ok()
     } else {
         // if we get here it means both sides have no usb connection
@@ -3,9 +3,10 @@ use core::num::Wrapping;
 use embassy_executor::Spawner;
 use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex, pubsub::PubSubChannel};
 use embassy_time::Duration;
+use portable_atomic::Ordering;
 use serde::{Deserialize, Serialize};
 
-use crate::{flash, keys::KEY_EVENTS, utils};
+use crate::{crash, flash, interboard, keys::KEY_EVENTS, utils};
 
 static CURRENT_METRICS: Mutex<ThreadModeRawMutex, Metrics> = Mutex::new(Metrics::default());
 
@@ -15,12 +16,58 @@ pub static METRIC_UPDATES: PubSubChannel<ThreadModeRawMutex, Metrics, 1, 4, 1> =
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
 pub struct Metrics {
     pub keys_pressed: Wrapping<usize>,
+    /// Resets caused by a panic or fault, see [`crash`]
+    pub crashes: Wrapping<usize>,
+    /// Messages to the other half sent again after going unacknowledged
+    pub interboard_retries: Wrapping<usize>,
+    /// Messages to the other half that didn't get through
+    pub interboard_failures: Wrapping<usize>,
+}
+
+impl flash::Stored for Metrics {
+    const KEY: &'static [u8] = b"metrics";
+    const VERSION: u8 = 2;
+
+    fn migrate(version: u8, data: &[u8]) -> Option<Self> {
+        #[derive(Deserialize)]
+        struct MetricsV0 {
+            keys_pressed: Wrapping<usize>,
+        }
+
+        #[derive(Deserialize)]
+        struct MetricsV1 {
+            keys_pressed: Wrapping<usize>,
+            crashes: Wrapping<usize>,
+        }
+
+        match version {
+            0 => {
+                let old: MetricsV0 = postcard::from_bytes(data).ok()?;
+                Some(Self {
+                    keys_pressed: old.keys_pressed,
+                    ..Self::default()
+                })
+            }
+            1 => {
+                let old: MetricsV1 = postcard::from_bytes(data).ok()?;
+                Some(Self {
+                    keys_pressed: old.keys_pressed,
+                    crashes: old.crashes,
+                    ..Self::default()
+                })
+            }
+            _ => None,
+        }
+    }
 }
 
 impl Metrics {
     const fn default() -> Self {
         Self {
             keys_pressed: Wrapping(0),
+            crashes: Wrapping(0),
+            interboard_retries: Wrapping(0),
+            interboard_failures: Wrapping(0),
         }
     }
 }
@@ -32,8 +79,18 @@ pub async fn init(spawner: &Spawner) {
         push_update(m);
     }
 
+    if crash::crashed_before_boot() {
+        let mut m = CURRENT_METRICS.lock().await;
+        m.crashes += 1;
+
+        // stored straight away, another crash could come before the next sync
+        let _ = flash::set(&*m).await;
+        push_update(m.clone());
+    }
+
     spawner.must_spawn(metrics_syncer());
     spawner.must_spawn(key_counter());
+    spawner.must_spawn(interboard_counter());
 }
 
 fn push_update(m: Metrics) {
@@ -62,6 +119,33 @@ async fn key_counter() {
     }
 }
 
+/// Folds the interboard eventer's counters into the metrics every so often,
+/// they change too often to push an update for each one
+#[embassy_executor::task]
+async fn interboard_counter() {
+    let counters = &interboard::channel::COUNTERS;
+    let mut tick = embassy_time::Ticker::every(Duration::from_secs(1));
+    let (mut retries, mut failures) = (0u32, 0u32);
+
+    loop {
+        tick.next().await;
+
+        let now_retries = counters.retries.load(Ordering::Relaxed);
+        let now_failures = counters.failures.load(Ordering::Relaxed);
+
+        if (now_retries, now_failures) == (retries, failures) {
+            continue;
+        }
+
+        let mut m = CURRENT_METRICS.lock().await;
+        m.interboard_retries += now_retries.wrapping_sub(retries) as usize;
+        m.interboard_failures += now_failures.wrapping_sub(failures) as usize;
+        (retries, failures) = (now_retries, now_failures);
+
+        push_update(m.clone());
+    }
+}
+
 #[embassy_executor::task]
 async fn metrics_syncer() {
     let mut tick = embassy_time::Ticker::every(Duration::from_secs(60 * 5));
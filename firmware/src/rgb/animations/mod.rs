@@ -2,6 +2,7 @@ use cichlid::ColorRGB;
 use embassy_time::Duration;
 use rand::seq::SliceRandom;
 use serde::{Deserialize, Serialize};
+use shared::host_to_device::AnimationKind;
 
 use crate::rng::MyRng;
 
@@ -28,6 +29,15 @@ impl DynAnimation {
         ];
         OPTS.choose(&mut MyRng).unwrap()()
     }
+
+    pub fn from_kind(kind: AnimationKind) -> Self {
+        match kind {
+            AnimationKind::Snow => DynAnimation::Snow(snow::Snow::default()),
+            AnimationKind::Perlin => DynAnimation::Perlin(perlin::Perlin::default()),
+            AnimationKind::Rain => DynAnimation::Rain(rain::Rain::default()),
+            AnimationKind::Off => DynAnimation::Null(null::Null),
+        }
+    }
 }
 
 macro_rules! dyn_impl {
@@ -36,10 +36,7 @@ pub fn init(
 
     spawner.must_spawn(runner::rgb_runner(d));
     spawner.must_spawn(command_listener());
-
-    if side::this_side_has_usb() {
-        spawner.must_spawn(animation_randomizer());
-    }
+    spawner.must_spawn(animation_randomizer());
 }
 
 #[embassy_executor::task]
@@ -62,14 +59,21 @@ async fn animation_randomizer() {
     loop {
         Timer::after(Duration::from_secs(60 * 5)).await;
 
-        let anim = DynAnimation::random();
-        let sync = anim.construct_sync();
-
-        send_cmd(Command::SetNextAnimation(sync.clone())).await;
-        interboard::send_msg(reliable_msg(DeviceToDevice::SetAnimation(sync)), 3).await;
+        // the master picks for both halves
+        if side::this_side_has_usb() {
+            set_animation(DynAnimation::random()).await;
+        }
     }
 }
 
+/// Transition to a new animation on both halves
+pub async fn set_animation(anim: DynAnimation) {
+    let sync = anim.construct_sync();
+
+    send_cmd(Command::SetNextAnimation(sync.clone())).await;
+    interboard::send_msg(reliable_msg(DeviceToDevice::SetAnimation(sync)), 3).await;
+}
+
 pub async fn send_cmd(cmd: Command) {
     RGB_CMD_CHANNEL.send(cmd).await
 }
@@ -10,8 +10,10 @@ use fixed_macro::fixed;
 use crate::{
     interboard,
     messages::{device_to_device::DeviceToDevice, reliable_msg, unreliable_msg},
+    settings::{self, SETTING_UPDATES},
     side::get_side,
     utils::Ticker,
+    watchdog,
 };
 
 use super::{
@@ -23,16 +25,14 @@ use super::{
     RGB_CMD_CHANNEL,
 };
 
-const MAX_LEVEL: u8 = 180;
 const COLOUR_CORRECTION: ColorRGB = ColorRGB::new(190, 200, 255);
-const FADE_DURATION: Duration = Duration::from_secs(3);
 
-fn ease_fade_on_time(duration: Duration) -> u8 {
-    if duration > FADE_DURATION {
+fn ease_fade_on_time(duration: Duration, fade_duration: Duration) -> u8 {
+    if duration >= fade_duration {
         255
     } else {
         let n = U32F32::saturating_from_num(duration.as_ticks() as u32);
-        let d = U32F32::saturating_from_num(FADE_DURATION.as_ticks() as u32);
+        let d = U32F32::saturating_from_num(fade_duration.as_ticks() as u32);
         ease_fade((n / d).saturating_to_num())
     }
 }
@@ -42,6 +42,7 @@ struct PerformingAnimation<'a, T> {
     ticker: Ticker,
     colours: &'a mut [ColorRGB; NUM_LEDS as usize],
     lights: &'static [Light; NUM_LEDS as usize],
+    max_level: u8,
 }
 
 impl<'a, T: Animation> PerformingAnimation<'a, T> {
@@ -49,6 +50,7 @@ impl<'a, T: Animation> PerformingAnimation<'a, T> {
         animation: T,
         colours: &'a mut [ColorRGB; NUM_LEDS as usize],
         lights: &'static [Light; NUM_LEDS as usize],
+        max_level: u8,
     ) -> Self {
         let ticker = Ticker::every(animation.tick_rate());
 
@@ -57,6 +59,7 @@ impl<'a, T: Animation> PerformingAnimation<'a, T> {
             ticker,
             colours,
             lights,
+            max_level,
         };
         performing_animation.render();
         performing_animation
@@ -80,7 +83,7 @@ impl<'a, T: Animation> PerformingAnimation<'a, T> {
 
         for (dest, light) in self.colours.iter_mut().zip(self.lights) {
             let mut color = self.animation.render(light);
-            color.scale(MAX_LEVEL);
+            color.scale(self.max_level);
 
             if light.kind == layout::Kind::Switch {
                 color.scale_from_other(COLOUR_CORRECTION);
@@ -102,10 +105,14 @@ pub async fn rgb_runner(mut driver: Ws2812<'static, PIO1, 0, { NUM_LEDS as usize
         &layout::RIGHT
     };
 
+    let mut settings = settings::current();
+    let mut setting_updates = SETTING_UPDATES.subscriber().unwrap();
+
     let mut current = PerformingAnimation::new(
         animations::DynAnimation::Null(animations::null::Null),
         &mut current_colours,
         lights,
+        settings.rgb_max_level,
     );
 
     let mut next: Option<(Instant, PerformingAnimation<'_, animations::DynAnimation>)> =
@@ -114,6 +121,7 @@ pub async fn rgb_runner(mut driver: Ws2812<'static, PIO1, 0, { NUM_LEDS as usize
                 animations::DynAnimation::random(),
                 &mut next_colours,
                 lights,
+                settings.rgb_max_level,
             );
 
             // reporo the animation to the other side
@@ -131,7 +139,16 @@ pub async fn rgb_runner(mut driver: Ws2812<'static, PIO1, 0, { NUM_LEDS as usize
     loop {
         let mut errors = [GammaErrorTracker::default(); NUM_LEDS as usize];
 
-        if let Some((_, next)) = next.take_if(|(f, _)| f.elapsed() > FADE_DURATION) {
+        if let Some(s) = setting_updates.try_next_message_pure() {
+            settings = s;
+            current.max_level = s.rgb_max_level;
+            if let Some((_, next)) = next.as_mut() {
+                next.max_level = s.rgb_max_level;
+            }
+        }
+        let fade_duration = Duration::from_millis(settings.rgb_fade_ms as u64);
+
+        if let Some((_, next)) = next.take_if(|(f, _)| f.elapsed() >= fade_duration) {
             current.reconstruct_from(next);
         }
 
@@ -151,6 +168,7 @@ pub async fn rgb_runner(mut driver: Ws2812<'static, PIO1, 0, { NUM_LEDS as usize
                             animations::DynAnimation::new_from_sync(a),
                             &mut next_colours,
                             lights,
+                            settings.rgb_max_level,
                         ),
                     ));
                 }
@@ -161,6 +179,8 @@ pub async fn rgb_runner(mut driver: Ws2812<'static, PIO1, 0, { NUM_LEDS as usize
         }
 
         loop {
+            watchdog::check_in(watchdog::Task::Rgb);
+
             if let Some((fade_start, next)) = next.as_mut() {
                 match select3(
                     current.step(),
@@ -180,7 +200,7 @@ pub async fn rgb_runner(mut driver: Ws2812<'static, PIO1, 0, { NUM_LEDS as usize
                             array::from_fn::<_, { NUM_LEDS as usize }, _>(|i| {
                                 let mut a = current.colours[i];
                                 let b = next.colours[i];
-                                a.blend(b, ease_fade_on_time(fade_start.elapsed()));
+                                a.blend(b, ease_fade_on_time(fade_start.elapsed(), fade_duration));
                                 errors[i].process(a)
                             });
 
@@ -0,0 +1,154 @@
+//! Settings the user can change at runtime, see [`shared::settings`]
+//!
+//! Tasks read the current value with [`current`] when they start and then
+//! follow [`SETTING_UPDATES`] for changes.
+
+use core::cell::Cell;
+
+use embassy_executor::Spawner;
+use embassy_sync::{
+    blocking_mutex::{raw::ThreadModeRawMutex, Mutex},
+    pubsub::PubSubChannel,
+    signal::Signal,
+};
+use serde::Deserialize;
+use shared::device_to_host::{Reply, RpcError};
+use shared::settings::{Setting, Settings};
+
+use crate::{
+    flash, interboard, master,
+    messages::{device_to_device::DeviceToDevice, reliable_msg},
+    side,
+    utils::log,
+};
+
+static CURRENT: Mutex<ThreadModeRawMutex, Cell<Settings>> =
+    Mutex::new(Cell::new(Settings::DEFAULT));
+
+/// Subscribed to by the rgb runner, matrix scanner, chord processor, trackpad,
+/// display and mouse writer, with room for a couple more
+pub static SETTING_UPDATES: PubSubChannel<ThreadModeRawMutex, Settings, 1, 8, 1> =
+    PubSubChannel::new();
+
+static OTHER_SIDE_CONNECTED: Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+impl flash::Stored for Settings {
+    const KEY: &'static [u8] = b"settings";
+    const VERSION: u8 = 1;
+
+    fn migrate(version: u8, data: &[u8]) -> Option<Self> {
+        #[derive(Deserialize)]
+        struct SettingsV0 {
+            rgb_max_level: u8,
+            rgb_fade_ms: u16,
+            display_timeout_secs: u16,
+            chord_timeout_ms: u16,
+            debounce_period: u8,
+            trackpad_cpi: u16,
+            scroll_period: u8,
+        }
+
+        match version {
+            0 => {
+                let old: SettingsV0 = postcard::from_bytes(data).ok()?;
+                Some(Self {
+                    rgb_max_level: old.rgb_max_level,
+                    rgb_fade_ms: old.rgb_fade_ms,
+                    display_timeout_secs: old.display_timeout_secs,
+                    chord_timeout_ms: old.chord_timeout_ms,
+                    debounce_period: old.debounce_period,
+                    trackpad_cpi: old.trackpad_cpi,
+                    scroll_period: old.scroll_period,
+                    ..Self::DEFAULT
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+pub fn current() -> Settings {
+    CURRENT.lock(|c| c.get())
+}
+
+/// Load the settings from flash, must be called before anything reads them
+pub async fn init(spawner: &Spawner) {
+    if let Some(s) = flash::get::<Settings>().await {
+        if s.is_valid() {
+            log::info!("Loaded settings from flash");
+            CURRENT.lock(|c| c.set(s));
+            // usb tasks are already running by now
+            SETTING_UPDATES.immediate_publisher().publish_immediate(s);
+        } else {
+            log::warn!("Ignoring invalid settings in flash");
+        }
+    }
+
+    spawner.must_spawn(sync_to_other_side_task());
+}
+
+pub fn other_side_connected() {
+    OTHER_SIDE_CONNECTED.signal(());
+}
+
+/// Send the settings to the other half whenever it connects, the halves might
+/// have been set up separately and the usb side wins
+#[embassy_executor::task]
+async fn sync_to_other_side_task() {
+    loop {
+        OTHER_SIDE_CONNECTED.wait().await;
+
+        if side::this_side_has_usb() {
+            for setting in current().iter() {
+                interboard::send_msg(reliable_msg(DeviceToDevice::SyncSetting(setting)), 3).await;
+            }
+        }
+    }
+}
+
+/// Change a setting on the host's behalf and pass it on to the other side
+pub async fn set(setting: Setting) -> Result<Reply, RpcError> {
+    if !setting.is_valid() {
+        return Err(RpcError::InvalidSetting);
+    }
+
+    let mut settings = current();
+    if settings.apply(setting) {
+        // the change still takes effect if it can't be stored
+        let stored = update(settings).await;
+        interboard::send_msg(reliable_msg(DeviceToDevice::SyncSetting(setting)), 3).await;
+        stored.ok_or(RpcError::FlashFailure)?;
+    }
+
+    Ok(Reply::Settings(settings))
+}
+
+/// Take on a setting changed by the other side
+///
+/// Single settings are synced rather than the whole lot, so that changes to
+/// different settings arriving from both sides at once don't undo each other
+pub async fn sync_from_other_side(setting: Setting) {
+    if !setting.is_valid() {
+        return;
+    }
+
+    let mut settings = current();
+    if settings.apply(setting) && update(settings).await.is_none() {
+        log::warn!("Couldn't store a setting synced from the other side");
+    }
+}
+
+async fn update(settings: Settings) -> Option<()> {
+    let master_side_changed = current().master_side != settings.master_side;
+
+    CURRENT.lock(|c| c.set(settings));
+    SETTING_UPDATES
+        .immediate_publisher()
+        .publish_immediate(settings);
+
+    if master_side_changed {
+        master::preference_changed().await;
+    }
+
+    flash::set(&settings).await
+}
@@ -4,9 +4,8 @@ use shared::side::KeyboardSide;
 static SIDE_IS_LEFT: AtomicBool = AtomicBool::new(false);
 static HAS_USB: AtomicBool = AtomicBool::new(false);
 
-pub fn init(side: KeyboardSide, has_usb: bool) {
+pub fn init(side: KeyboardSide) {
     SIDE_IS_LEFT.store(side.is_left(), portable_atomic::Ordering::Relaxed);
-    HAS_USB.store(has_usb, portable_atomic::Ordering::Relaxed);
 }
 
 pub fn is_this_side(side: KeyboardSide) -> bool {
@@ -25,6 +24,12 @@ pub fn get_other_side() -> KeyboardSide {
     get_side().other()
 }
 
+/// Whether this half is the one talking to the host, which can change while
+/// running and is false until it's been decided at boot, see [`crate::master`]
 pub fn this_side_has_usb() -> bool {
     HAS_USB.load(portable_atomic::Ordering::Relaxed)
 }
+
+pub fn set_has_usb(has_usb: bool) {
+    HAS_USB.store(has_usb, portable_atomic::Ordering::Relaxed);
+}
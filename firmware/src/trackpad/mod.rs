@@ -7,14 +7,25 @@ use embassy_rp::{
 };
 use embassy_time::Duration;
 use embedded_hal_bus::spi::ExclusiveDevice;
+use portable_atomic::AtomicBool;
 use shared::hid::MouseReport;
 
-use crate::utils::Ticker;
+use crate::{
+    settings::{self, SETTING_UPDATES},
+    utils::Ticker,
+};
 
 pub mod driver;
 mod glide;
 pub mod regs;
 
+static PRESENT: AtomicBool = AtomicBool::new(false);
+
+/// Whether a trackpad was found and initialized on this side
+pub fn is_present() -> bool {
+    PRESENT.load(portable_atomic::Ordering::Relaxed)
+}
+
 type TrackpadSpi = ExclusiveDevice<Spi<'static, SPI0, Async>, Output<'static>, embassy_time::Delay>;
 
 #[allow(clippy::too_many_arguments)]
@@ -52,9 +63,18 @@ async fn trackpad_task(spi: TrackpadSpi) {
         return;
     }
 
+    PRESENT.store(true, portable_atomic::Ordering::Relaxed);
+
+    let mut setting_updates = SETTING_UPDATES.subscriber().unwrap();
+    trackpad.set_scale(settings::current().trackpad_cpi);
+
     let mut ticker = Ticker::every(Duration::from_hz(250));
 
     loop {
+        if let Some(s) = setting_updates.try_next_message_pure() {
+            trackpad.set_scale(s.trackpad_cpi);
+        }
+
         match trackpad.get_report().await {
             Ok(Some(report)) => {
                 let rep = MouseReport {
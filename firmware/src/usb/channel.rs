@@ -8,14 +8,15 @@ use embassy_usb::Builder;
 use shared::device_to_host::DeviceToHost;
 use shared::host_to_device::HostToDevice;
 
-use crate::messages::transmissions;
 use crate::messages::TransmittedMessage;
+use crate::messages::{distributors, transmissions};
 use crate::utils;
 
 use super::{USBDriver, MAX_PACKET_SIZE};
 
 pub static COMMANDS_FROM_HOST: PubSubChannel<CS, HostToDevice, 4, 4, 1> = PubSubChannel::new();
 pub static COMMANDS_TO_HOST: Channel<CS, TransmittedMessage<DeviceToHost>, 16> = Channel::new();
+pub static COUNTERS: transmissions::Counters = transmissions::Counters::new();
 
 const BUF_SIZE: usize = 128;
 
@@ -30,6 +31,7 @@ async fn serial_in_task(
         while let Ok(len) = serial_rx.read_packet(&mut rx[..]).await {
             let _ = out_pipe.write(&rx[..len]).await;
         }
+        distributors::reset_host_session();
     }
 }
 
@@ -57,7 +59,17 @@ async fn eventer_task(tx: Writer<'static, CS, BUF_SIZE>, rx: Reader<'static, CS,
     let tx_fn = |e| async {
         msg_pub.publish(e).await;
     };
-    transmissions::eventer(tx, rx, rx_fn, tx_fn).await;
+    // the host only understands one message in flight at a time
+    transmissions::eventer(
+        tx,
+        rx,
+        transmissions::Window::STOP_AND_WAIT,
+        rx_fn,
+        tx_fn,
+        (),
+        &COUNTERS,
+    )
+    .await;
 }
 
 pub fn init(spawner: &Spawner, builder: &mut Builder<'static, USBDriver>) {
@@ -1,7 +1,12 @@
+use embassy_futures::select::select;
+use embassy_time::{Duration, Timer};
 use embassy_usb::driver::Driver;
-use embassy_usb::{Builder, Config};
+use embassy_usb::{Builder, Config, Handler};
+use portable_atomic::{AtomicBool, Ordering};
 
+use crate::messages::distributors;
 use crate::utils::singleton;
+use crate::{master, side};
 
 use super::USBDriver;
 
@@ -15,18 +20,83 @@ pub fn init_usb<'d, D: Driver<'d>>(driver: D) -> Builder<'d, D> {
     config.max_power = 500;
     config.max_packet_size_0 = MAX_PACKET_SIZE as u8;
 
-    Builder::new(
+    let mut builder = Builder::new(
         driver,
         config,
         singleton!([u8; 256], [0; 256]),
         singleton!([u8; 256], [0; 256]),
         singleton!([u8; 256], [0; 256]),
         singleton!([u8; 256], [0; 256]),
-    )
+    );
+    builder.handler(singleton!(BusState, BusState));
+
+    builder
+}
+
+/// How long the device keeps running after this half stops being the master,
+/// so the empty report releasing its keys reaches the host
+const HANDOVER_TIME: Duration = Duration::from_millis(100);
+
+static CONFIGURED: AtomicBool = AtomicBool::new(false);
+static SUSPENDED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the host is taking reports, while it isn't writes to the hid
+/// endpoints wait
+pub fn is_active() -> bool {
+    CONFIGURED.load(Ordering::Relaxed) && !SUSPENDED.load(Ordering::Relaxed)
+}
+
+struct BusState;
+
+// the host has to say hello again after any of these, it might not be the
+// same program talking to us
+impl Handler for BusState {
+    fn reset(&mut self) {
+        CONFIGURED.store(false, Ordering::Relaxed);
+        SUSPENDED.store(false, Ordering::Relaxed);
+        distributors::reset_host_session();
+    }
+
+    fn configured(&mut self, configured: bool) {
+        CONFIGURED.store(configured, Ordering::Relaxed);
+        if !configured {
+            distributors::reset_host_session();
+        }
+    }
+
+    fn suspended(&mut self, suspended: bool) {
+        SUSPENDED.store(suspended, Ordering::Relaxed);
+        if suspended {
+            distributors::reset_host_session();
+        }
+    }
 }
 
+/// Only the master answers its host, if usb is plugged into both halves the
+/// other one would be a keyboard that never types
 #[embassy_executor::task]
 pub async fn run_usb(builder: Builder<'static, USBDriver>) {
     let mut device = builder.build();
-    device.run().await;
+    let mut role_updates = master::ROLE_UPDATES.subscriber().unwrap();
+
+    loop {
+        while !side::this_side_has_usb() {
+            role_updates.next_message_pure().await;
+        }
+
+        let demoted = async {
+            while side::this_side_has_usb() {
+                role_updates.next_message_pure().await;
+            }
+            Timer::after(HANDOVER_TIME).await;
+        };
+
+        select(device.run(), demoted).await;
+
+        // dropping the run leaves the bus in an unknown state until it's
+        // disabled
+        device.disable().await;
+        CONFIGURED.store(false, Ordering::Relaxed);
+        distributors::reset_host_session();
+    }
 }
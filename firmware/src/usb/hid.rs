@@ -1,6 +1,9 @@
+use core::future::Future;
+
 use embassy_executor::Spawner;
 use embassy_futures::yield_now;
 use embassy_sync::channel::Channel;
+use embassy_time::{with_timeout, Duration};
 use embassy_usb::{class::hid::HidWriter, Builder};
 use num::Integer;
 use packed_struct::PackedStruct;
@@ -13,13 +16,19 @@ use usbd_human_interface_device::device::keyboard::{
 use crate::{
     interboard::{self, THIS_SIDE_MESSAGE_BUS},
     messages::{device_to_device::DeviceToDevice, low_latency_msg},
+    settings::{self, SETTING_UPDATES},
     side, utils,
+    watchdog::{self, Task},
 };
 
 use super::USBDriver;
 
 type CS = embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
 
+/// How long a half that's stopped being the master waits for its host to take
+/// a report, before its usb device is disabled
+const DEMOTED_WRITE_TIMEOUT: Duration = Duration::from_millis(50);
+
 static MOUSE_REPORTS: Channel<CS, shared::hid::MouseReport, 4> = Channel::new();
 static KEYBOARD_REPORTS: Channel<CS, NKROBootKeyboardReport, 2> = Channel::new();
 
@@ -40,25 +49,26 @@ async fn handle_mouse_clicks() {
     let mut sub = THIS_SIDE_MESSAGE_BUS.subscriber().unwrap();
 
     loop {
-        if let DeviceToDevice::SyncMouseState(b) = sub.next_message_pure().await {
-            let buttons: u8 = [
-                if b.left() { 0b01 } else { 0 },
-                if b.right() { 0b10 } else { 0 },
-            ]
-            .into_iter()
-            .sum();
-
-            MOUSE_BUTTON_STATE.store(buttons, portable_atomic::Ordering::SeqCst);
-            IS_SCROLLING.store(b.scrolling(), portable_atomic::Ordering::SeqCst);
-            MOUSE_REPORTS
-                .send(shared::hid::MouseReport::default())
-                .await;
-        }
+        let b = match sub.next_message_pure().await {
+            DeviceToDevice::SyncMouseState(b) => b,
+            _ => continue,
+        };
+
+        let buttons: u8 = [
+            if b.left() { 0b01 } else { 0 },
+            if b.right() { 0b10 } else { 0 },
+        ]
+        .into_iter()
+        .sum();
+
+        MOUSE_BUTTON_STATE.store(buttons, portable_atomic::Ordering::SeqCst);
+        IS_SCROLLING.store(b.scrolling(), portable_atomic::Ordering::SeqCst);
+        MOUSE_REPORTS
+            .send(shared::hid::MouseReport::default())
+            .await;
     }
 }
 
-const SCROLL_PERIOD: u8 = 12;
-
 #[derive(Default)]
 struct ScrollDivider {
     fwd: u8,
@@ -66,14 +76,14 @@ struct ScrollDivider {
 }
 
 impl ScrollDivider {
-    fn update(&mut self, diff: i8) -> i8 {
+    fn update(&mut self, diff: i8, period: u8) -> i8 {
         self.fwd = self.fwd.saturating_add_signed(diff);
         self.bwd = self.bwd.saturating_add_signed(-diff);
 
         let out_fwd;
-        (out_fwd, self.fwd) = self.fwd.div_mod_floor(&SCROLL_PERIOD);
+        (out_fwd, self.fwd) = self.fwd.div_mod_floor(&period);
         let out_bwd;
-        (out_bwd, self.bwd) = self.bwd.div_mod_floor(&SCROLL_PERIOD);
+        (out_bwd, self.bwd) = self.bwd.div_mod_floor(&period);
 
         0i8.saturating_add_unsigned(out_fwd)
             .saturating_sub_unsigned(out_bwd)
@@ -103,15 +113,33 @@ impl MovementCoalescer {
     }
 }
 
+/// Write a report to the host. Only the master's host takes reports, while this
+/// half isn't the master they're dropped instead of blocking the writer until
+/// the watchdog resets it
+async fn write_to_host(write: impl Future) {
+    if side::this_side_has_usb() {
+        write.await;
+    } else if super::is_active() {
+        let _ = with_timeout(DEMOTED_WRITE_TIMEOUT, write).await;
+    }
+}
+
 #[embassy_executor::task]
 async fn mouse_writer(mut mouse_writer: HidWriter<'static, USBDriver, 64>) {
     let mut vertical_scroll_state = ScrollDivider::default();
     let mut horizontal_scroll_state = ScrollDivider::default();
     let mut x_coalescer = MovementCoalescer::default();
     let mut y_coalescer = MovementCoalescer::default();
+    let mut setting_updates = SETTING_UPDATES.subscriber().unwrap();
+    let mut scroll_period = settings::current().scroll_period;
 
     loop {
-        let shared::hid::MouseReport { mut x, mut y } = MOUSE_REPORTS.receive().await;
+        let shared::hid::MouseReport { mut x, mut y } =
+            watchdog::idle(Task::UsbMouse, MOUSE_REPORTS.receive()).await;
+        if let Some(s) = setting_updates.try_next_message_pure() {
+            scroll_period = s.scroll_period;
+        }
+
         while x_coalescer.update(x) && y_coalescer.update(y) {
             let Some(shared::hid::MouseReport { x: x_, y: y_ }) = MOUSE_REPORTS.try_receive().ok()
             else {
@@ -121,8 +149,8 @@ async fn mouse_writer(mut mouse_writer: HidWriter<'static, USBDriver, 64>) {
         }
 
         let (x, y, wheel, pan) = if IS_SCROLLING.load(portable_atomic::Ordering::SeqCst) {
-            let y = vertical_scroll_state.update(y_coalescer.take());
-            let x = horizontal_scroll_state.update(x_coalescer.take());
+            let y = vertical_scroll_state.update(y_coalescer.take(), scroll_period);
+            let x = horizontal_scroll_state.update(x_coalescer.take(), scroll_period);
             (0, 0, y, x)
         } else {
             (x, y, 0, 0)
@@ -136,15 +164,15 @@ async fn mouse_writer(mut mouse_writer: HidWriter<'static, USBDriver, 64>) {
             pan,
         };
 
-        let _ = mouse_writer.write_serialize(&report).await;
+        write_to_host(mouse_writer.write_serialize(&report)).await;
     }
 }
 
 #[embassy_executor::task]
 async fn keyboard_writer(mut keyboard_writer: HidWriter<'static, USBDriver, 64>) {
     loop {
-        let report = KEYBOARD_REPORTS.receive().await;
-        let _ = keyboard_writer.write(&report.pack().unwrap()).await;
+        let report = watchdog::idle(Task::UsbKeyboard, KEYBOARD_REPORTS.receive()).await;
+        write_to_host(keyboard_writer.write(&report.pack().unwrap())).await;
     }
 }
 
@@ -191,7 +219,9 @@ pub fn init(spawner: &Spawner, builder: &mut Builder<'static, USBDriver>) {
     spawner.must_spawn(keyboard_writer(keyboard_hid_writer));
     spawner.must_spawn(handle_mouse_clicks());
 
-    if side::this_side_has_usb() && side::is_this_side(shared::side::KeyboardSide::Left) {
+    // the trackpad is on the right, which forwards its reports while the left
+    // is the master
+    if side::is_this_side(shared::side::KeyboardSide::Left) {
         spawner.must_spawn(interboard_receiver());
     }
 }
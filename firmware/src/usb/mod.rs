@@ -6,7 +6,7 @@ use embassy_sync::channel::TrySendError;
 use shared::device_to_host::DeviceToHost;
 
 pub use channel::COMMANDS_FROM_HOST;
-pub use device::MAX_PACKET_SIZE;
+pub use device::{is_active, MAX_PACKET_SIZE};
 pub use hid::publish_mouse_report;
 
 use crate::messages::TransmittedMessage;
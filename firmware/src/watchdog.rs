@@ -0,0 +1,169 @@
+//! Resets the chip if one of the important tasks stops making progress
+//!
+//! Watched tasks [`check_in`] each time around their loop, and wrap waits that
+//! can last any amount of time, such as for input, in [`idle`]. The supervisor
+//! feeds the hardware watchdog for as long as every watched task is either
+//! idle or has checked in recently. Once one hasn't, its name is left for the
+//! crash report and the watchdog is left to reset the chip.
+//!
+//! Anything that stops the executor altogether stops the supervisor too, which
+//! also ends in a reset, but without knowing which task was to blame.
+
+use core::future::Future;
+
+use embassy_executor::Spawner;
+use embassy_rp::peripherals::WATCHDOG;
+use embassy_rp::watchdog::Watchdog;
+use embassy_time::{Duration, Instant, Ticker};
+use portable_atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::{crash, interboard, side, usb, utils::log};
+
+/// Long enough for the slowest flash erase, which holds up the executor
+const TIMEOUT: Duration = Duration::from_secs(4);
+const CHECK_PERIOD: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "probe", derive(defmt::Format))]
+pub enum Task {
+    MatrixScanner,
+    KeyEventProcessor,
+    /// Receiving from the other half
+    Interboard,
+    Rgb,
+    UsbKeyboard,
+    UsbMouse,
+}
+
+impl Task {
+    const ALL: [Task; 6] = [
+        Task::MatrixScanner,
+        Task::KeyEventProcessor,
+        Task::Interboard,
+        Task::Rgb,
+        Task::UsbKeyboard,
+        Task::UsbMouse,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Task::MatrixScanner => "matrix_scanner",
+            Task::KeyEventProcessor => "key_event_processor",
+            Task::Interboard => "interboard",
+            Task::Rgb => "rgb_runner",
+            Task::UsbKeyboard => "keyboard_writer",
+            Task::UsbMouse => "mouse_writer",
+        }
+    }
+
+    /// How long the task can go without checking in while it isn't idle
+    fn limit(self) -> Duration {
+        match self {
+            Task::MatrixScanner | Task::KeyEventProcessor => Duration::from_millis(500),
+            Task::Interboard | Task::Rgb | Task::UsbKeyboard | Task::UsbMouse => {
+                Duration::from_secs(1)
+            }
+        }
+    }
+}
+
+struct Slot {
+    /// Tick of the last check in, tasks that haven't checked in yet (or don't
+    /// run on this side) aren't watched
+    last: AtomicU64,
+    idle: AtomicBool,
+}
+
+const NEVER: u64 = u64::MAX;
+
+static SLOTS: [Slot; Task::ALL.len()] = [const {
+    Slot {
+        last: AtomicU64::new(NEVER),
+        idle: AtomicBool::new(false),
+    }
+}; Task::ALL.len()];
+
+/// Record that `task` is still making progress
+pub fn check_in(task: Task) {
+    SLOTS[task as usize]
+        .last
+        .store(Instant::now().as_ticks(), Ordering::Relaxed);
+}
+
+/// Marks the task as busy again once the wait is over, even if it's cancelled
+struct IdleGuard(Task);
+
+impl Drop for IdleGuard {
+    fn drop(&mut self) {
+        SLOTS[self.0 as usize].idle.store(false, Ordering::Relaxed);
+        check_in(self.0);
+    }
+}
+
+/// Wait on something that may take any amount of time without it counting as
+/// `task` stalling
+pub async fn idle<F: Future>(task: Task, fut: F) -> F::Output {
+    SLOTS[task as usize].idle.store(true, Ordering::Relaxed);
+    let _guard = IdleGuard(task);
+
+    fut.await
+}
+
+impl dilemma_transmissions::Idle for Task {
+    async fn idle<F: Future>(&self, fut: F) -> F::Output {
+        idle(*self, fut).await
+    }
+}
+
+/// Whether the tasks are held up by something outside of the keyboard, which
+/// a reset won't fix: a sleeping host stops taking reports, and everything
+/// sending to the other half blocks while it isn't responding
+fn held_up() -> bool {
+    (side::this_side_has_usb() && !usb::is_active()) || interboard::is_backed_up()
+}
+
+/// Start the hardware watchdog and the supervisor feeding it
+pub fn init(spawner: &Spawner, watchdog: WATCHDOG) {
+    let mut watchdog = Watchdog::new(watchdog);
+    watchdog.pause_on_debug(true);
+    watchdog.start(TIMEOUT);
+
+    spawner.must_spawn(supervisor(watchdog));
+}
+
+#[embassy_executor::task]
+async fn supervisor(mut watchdog: Watchdog) {
+    let mut ticker = Ticker::every(CHECK_PERIOD);
+
+    loop {
+        ticker.next().await;
+
+        let now = Instant::now().as_ticks();
+        let held_up = held_up();
+
+        for task in Task::ALL {
+            let slot = &SLOTS[task as usize];
+            let last = slot.last.load(Ordering::Relaxed);
+
+            if last == NEVER {
+                continue;
+            }
+
+            if held_up || slot.idle.load(Ordering::Relaxed) {
+                // don't blame the task for the time it spent waiting
+                slot.last.store(now, Ordering::Relaxed);
+                continue;
+            }
+
+            if Duration::from_ticks(now.saturating_sub(last)) > task.limit() {
+                log::error!("The {} task has stopped responding", task.name());
+                crash::record_hang(task.name());
+
+                // leave the watchdog to reset the chip
+                core::future::pending::<()>().await;
+            }
+        }
+
+        watchdog.feed();
+    }
+}
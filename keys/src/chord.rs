@@ -1,7 +1,10 @@
-use embassy_time::{Duration, Instant};
+use core::time::Duration;
+
+use shared::settings::Settings;
+
+use crate::Clock;
 
 pub type Key = (u8, u8);
-pub const CHORD_TIMEOUT: Duration = Duration::from_millis(30);
 
 pub struct Chord {
     pub key_map: &'static phf::Map<[u8; 2], usize>,
@@ -42,25 +45,34 @@ pub struct Chorder {
     pub chords: &'static mut [Chord],
 }
 
-pub struct ChordingEngine {
+pub struct ChordingEngine<C> {
     chorder: Chorder,
     held_keys: heapless::Vec<Key, 16>,
 
     // after firing a release of a chord, ignore the following key releases
     ignored_releases: heapless::Vec<Key, 16>,
-    last_press: Instant,
+    last_press: Duration,
+    timeout: Duration,
+    clock: C,
 }
 
-impl ChordingEngine {
-    pub fn new(chorder: Chorder) -> Self {
+impl<C: Clock> ChordingEngine<C> {
+    pub fn new(chorder: Chorder, clock: C) -> Self {
         Self {
             chorder,
             held_keys: heapless::Vec::new(),
             ignored_releases: heapless::Vec::new(),
-            last_press: Instant::now(),
+            last_press: clock.now(),
+            timeout: Duration::from_millis(Settings::DEFAULT.chord_timeout_ms as u64),
+            clock,
         }
     }
 
+    /// How long the keys of a chord have to be pressed within
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
     pub fn purge(&mut self) -> heapless::Vec<Key, 16> {
         for &(x, y) in &self.held_keys {
             if let Some(appropriate_chords) = self.chorder.key_chord_map.get(&[x, y]) {
@@ -80,9 +92,9 @@ impl ChordingEngine {
     }
 
     pub fn tick(&mut self) -> heapless::Vec<Key, 16> {
-        let now = Instant::now();
+        let now = self.clock.now();
 
-        if now.duration_since(self.last_press) > CHORD_TIMEOUT {
+        if now.saturating_sub(self.last_press) > self.timeout {
             // ran out of time, release all the currently pressed keys
 
             return self.purge();
@@ -176,7 +188,7 @@ impl ChordingEngine {
                 }
             }
 
-            self.last_press = Instant::now();
+            self.last_press = self.clock.now();
 
             heapless::Vec::new()
         } else {
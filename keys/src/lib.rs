@@ -0,0 +1,44 @@
+//! The key handling behind the firmware's key tasks: chords, the keyberon
+//! layout and typing out unicode
+//!
+//! Nothing in here touches the hardware or reads the time itself, so it builds
+//! for the host and the whole pipeline can be driven from `cargo test` (see
+//! `tests/`).
+
+#![no_std]
+
+use core::time::Duration;
+
+pub mod chord;
+pub mod processor;
+pub mod unicode;
+
+pub use processor::KeyProcessor;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UnicodeMode {
+    Linux,
+    Mac,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CustomEvent {
+    MouseLeft,
+    MouseRight,
+    MouseScroll,
+    TypeUnicode(&'static str),
+}
+
+pub type Layers = keyberon::layout::Layers<
+    { shared::keymap::COLS },
+    { shared::keymap::ROWS },
+    { shared::keymap::LAYERS },
+    CustomEvent,
+>;
+
+/// Where the time comes from, the firmware reads the embassy timer and tests
+/// move a clock along by hand
+pub trait Clock {
+    /// The time since some fixed point, such as boot
+    fn now(&self) -> Duration;
+}
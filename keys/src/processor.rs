@@ -0,0 +1,63 @@
+//! Turning chord-processed key events into the reports sent to the host
+
+use keyberon::{
+    key_code::KeyCode,
+    layout::{Event, Layout},
+};
+use packed_struct::PrimitiveEnum;
+use shared::keymap::{COLS, LAYERS, ROWS};
+use usbd_human_interface_device::{device::keyboard::NKROBootKeyboardReport, page::Keyboard};
+
+use crate::{CustomEvent, Layers};
+
+pub struct KeyProcessor {
+    layout: Layout<COLS, ROWS, LAYERS, CustomEvent>,
+    /// The keycodes in the last report
+    reported: heapless::Vec<KeyCode, 24>,
+}
+
+impl KeyProcessor {
+    pub fn new(layers: &'static Layers) -> Self {
+        Self {
+            layout: Layout::new(layers),
+            reported: heapless::Vec::new(),
+        }
+    }
+
+    /// Start from scratch with different layers, anything held is released by
+    /// the next report
+    pub fn set_layers(&mut self, layers: &'static Layers) {
+        self.layout = Layout::new(layers);
+    }
+
+    pub fn event(&mut self, event: Event) {
+        self.layout.event(event);
+    }
+
+    /// Move the layout on by a millisecond, returning the custom action that
+    /// was pressed (`true`) or released, if any
+    pub fn tick(&mut self) -> Option<(CustomEvent, bool)> {
+        match self.layout.tick() {
+            keyberon::layout::CustomEvent::NoEvent => None,
+            keyberon::layout::CustomEvent::Press(m) => Some((*m, true)),
+            keyberon::layout::CustomEvent::Release(m) => Some((*m, false)),
+        }
+    }
+
+    /// The report to send if the keys held down changed since the last one
+    pub fn report(&mut self) -> Option<NKROBootKeyboardReport> {
+        let keycodes = heapless::Vec::<_, 24>::from_iter(self.layout.keycodes());
+
+        if keycodes == self.reported {
+            return None;
+        }
+
+        self.reported = keycodes;
+
+        Some(NKROBootKeyboardReport::new(
+            self.reported
+                .iter()
+                .filter_map(|k| Keyboard::from_primitive(*k as u8)),
+        ))
+    }
+}
@@ -0,0 +1,122 @@
+//! Typing out strings by entering each character's code point with the host's
+//! unicode input
+
+use core::iter::once;
+use core::time::Duration;
+
+use usbd_human_interface_device::{device::keyboard::NKROBootKeyboardReport, page::Keyboard};
+
+use crate::UnicodeMode;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Step {
+    Report(NKROBootKeyboardReport),
+    /// Give the host time to notice before carrying on
+    Pause(Duration),
+}
+
+/// The reports that type out `msg`
+pub fn steps(mode: UnicodeMode, msg: &str) -> impl Iterator<Item = Step> + '_ {
+    let (linux, mac) = match mode {
+        UnicodeMode::Linux => (Some(linux(msg)), None),
+        UnicodeMode::Mac => (None, Some(mac(msg))),
+    };
+
+    linux.into_iter().flatten().chain(mac.into_iter().flatten())
+}
+
+fn press(keys: &[Keyboard]) -> Step {
+    Step::Report(NKROBootKeyboardReport::new(keys.iter().copied()))
+}
+
+const HEX_KEYS: [Keyboard; 16] = [
+    Keyboard::Keyboard0,
+    Keyboard::Keyboard1,
+    Keyboard::Keyboard2,
+    Keyboard::Keyboard3,
+    Keyboard::Keyboard4,
+    Keyboard::Keyboard5,
+    Keyboard::Keyboard6,
+    Keyboard::Keyboard7,
+    Keyboard::Keyboard8,
+    Keyboard::Keyboard9,
+    Keyboard::A,
+    Keyboard::B,
+    Keyboard::C,
+    Keyboard::D,
+    Keyboard::E,
+    Keyboard::F,
+];
+
+fn to_escape(c: char) -> heapless::Vec<Keyboard, 6> {
+    let c = c as u32;
+    let mut seen_nonzero = false;
+    let mut out = heapless::Vec::new();
+
+    let mut f = |n: u32| {
+        let nibble = ((c >> n) & 15u32) as usize;
+        if seen_nonzero || nibble != 0 {
+            out.push(HEX_KEYS[nibble]).unwrap();
+            seen_nonzero = true;
+        }
+    };
+
+    f(20);
+    f(16);
+    f(12);
+    f(8);
+    f(4);
+    f(0);
+
+    out
+}
+
+fn linux(msg: &str) -> impl Iterator<Item = Step> + '_ {
+    const START: [Keyboard; 3] = [Keyboard::LeftControl, Keyboard::LeftShift, Keyboard::U];
+
+    msg.chars().flat_map(|c| {
+        once(press(&START))
+            .chain(
+                to_escape(c)
+                    .into_iter()
+                    .map(|k| press(&[START[0], START[1], START[2], k])),
+            )
+            .chain(once(press(&[])))
+    })
+}
+
+fn to_escape_surrogate(c: u16) -> heapless::Vec<Keyboard, 6> {
+    let mut seen_nonzero = false;
+    let mut out = heapless::Vec::new();
+
+    let mut f = |n: u16| {
+        let nibble = ((c >> n) & 15u16) as usize;
+        if seen_nonzero || nibble != 0 {
+            out.push(HEX_KEYS[nibble]).unwrap();
+            seen_nonzero = true;
+        }
+    };
+
+    f(12);
+    f(8);
+    f(4);
+    f(0);
+
+    out
+}
+
+fn mac(msg: &str) -> impl Iterator<Item = Step> + '_ {
+    once(press(&[Keyboard::RightAlt]))
+        .chain(once(Step::Pause(Duration::from_millis(50))))
+        .chain(msg.encode_utf16().flat_map(|c| {
+            once(press(&[Keyboard::RightAlt, Keyboard::LeftAlt])).chain(
+                to_escape_surrogate(c).into_iter().flat_map(|k| {
+                    [
+                        press(&[Keyboard::RightAlt, Keyboard::LeftAlt, k]),
+                        press(&[Keyboard::RightAlt, Keyboard::LeftAlt]),
+                    ]
+                }),
+            )
+        }))
+        .chain(once(press(&[])))
+}
@@ -0,0 +1,91 @@
+//! Drives the key pipeline the way the firmware's tasks do, a millisecond at a
+//! time, from a script of matrix events
+
+use std::{cell::Cell, rc::Rc, time::Duration};
+
+use dilemma_keys::{
+    chord::{Chorder, ChordingEngine},
+    Clock, KeyProcessor, Layers,
+};
+use keyberon::{action::Action, layout::Event};
+use shared::keymap::{COLS, LAYERS, ROWS};
+use usbd_human_interface_device::{device::keyboard::NKROBootKeyboardReport, page::Keyboard};
+
+/// A clock that only moves when the simulation moves it
+#[derive(Clone, Default)]
+pub struct ManualClock(Rc<Cell<Duration>>);
+
+impl ManualClock {
+    fn set(&self, ms: u64) {
+        self.0.set(Duration::from_millis(ms));
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.0.get()
+    }
+}
+
+pub struct Sim {
+    clock: ManualClock,
+    chords: ChordingEngine<ManualClock>,
+    keys: KeyProcessor,
+}
+
+impl Sim {
+    pub fn new(layers: &'static Layers, chorder: Chorder) -> Self {
+        let clock = ManualClock::default();
+
+        Self {
+            chords: ChordingEngine::new(chorder, clock.clone()),
+            keys: KeyProcessor::new(layers),
+            clock,
+        }
+    }
+
+    /// Play `timeline`, events along with the millisecond they happen at in
+    /// order, and carry on until `end`, returning the reports sent to the host
+    pub fn run(&mut self, timeline: &[(u64, Event)], end: u64) -> Vec<NKROBootKeyboardReport> {
+        let mut reports = Vec::new();
+        let mut timeline = timeline.iter().peekable();
+
+        for now in 0..=end {
+            self.clock.set(now);
+
+            // matrix_processor
+            while let Some((_, evt)) = timeline.next_if(|(t, _)| *t <= now) {
+                for evt in self.chords.process(*evt) {
+                    self.keys.event(evt);
+                }
+            }
+            for (x, y) in self.chords.tick() {
+                self.keys.event(Event::Press(x, y));
+            }
+
+            // key_event_processor
+            self.keys.tick();
+            reports.extend(self.keys.report());
+        }
+
+        assert!(timeline.next().is_none(), "timeline runs past the end");
+
+        reports
+    }
+}
+
+/// Layers with `keys` on the first layer and nothing anywhere else
+pub fn layers(keys: &[((usize, usize), Action<dilemma_keys::CustomEvent>)]) -> &'static Layers {
+    let mut layers: Layers = [[[Action::NoOp; COLS]; ROWS]; LAYERS];
+
+    for &((row, col), action) in keys {
+        layers[0][row][col] = action;
+    }
+
+    Box::leak(Box::new(layers))
+}
+
+/// A report of exactly `keys` being held down
+pub fn report(keys: &[Keyboard]) -> NKROBootKeyboardReport {
+    NKROBootKeyboardReport::new(keys.iter().copied())
+}
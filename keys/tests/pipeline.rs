@@ -0,0 +1,184 @@
+mod common;
+
+use common::{layers, report, Sim};
+use dilemma_keys::{
+    chord::Chorder,
+    unicode::{steps, Step},
+    CustomEvent, UnicodeMode,
+};
+use keyberon::{
+    action::{Action, HoldTapAction, HoldTapConfig},
+    key_code::KeyCode,
+    layout::Event::{Press, Release},
+};
+use usbd_human_interface_device::page::Keyboard;
+
+// the chord macros refer to `crate::keys::chord`, the same path as in the firmware
+mod keys {
+    pub use dilemma_keys::chord;
+}
+
+/// A chord of (1, 0) and (1, 1) which sends (3, 0). Each expansion can only be
+/// built once, so every test expands it itself
+macro_rules! chorder {
+    () => {
+        dilemma_macros::chords!([(1, 0), (1, 1)] => [(3, 0)])
+    };
+}
+
+static SHIFT_A: HoldTapAction<CustomEvent, KeyCode> = HoldTapAction {
+    timeout: 200,
+    hold: Action::KeyCode(KeyCode::LShift),
+    tap: Action::KeyCode(KeyCode::A),
+    config: HoldTapConfig::Default,
+    tap_hold_interval: 0,
+};
+
+fn sim(chorder: Chorder) -> Sim {
+    let layers = layers(&[
+        ((0, 0), Action::HoldTap(&SHIFT_A)),
+        ((0, 1), Action::KeyCode(KeyCode::B)),
+        ((1, 0), Action::KeyCode(KeyCode::Q)),
+        ((1, 1), Action::KeyCode(KeyCode::W)),
+        ((3, 0), Action::KeyCode(KeyCode::Escape)),
+    ]);
+
+    Sim::new(layers, chorder)
+}
+
+#[test]
+fn plain_key() {
+    let reports = sim(chorder!()).run(&[(0, Press(0, 1)), (40, Release(0, 1))], 100);
+
+    assert_eq!(reports, [report(&[Keyboard::B]), report(&[])]);
+}
+
+#[test]
+fn hold_tap_tapped() {
+    let reports = sim(chorder!()).run(&[(0, Press(0, 0)), (50, Release(0, 0))], 300);
+
+    assert_eq!(reports, [report(&[Keyboard::A]), report(&[])]);
+}
+
+#[test]
+fn hold_tap_held() {
+    let reports = sim(chorder!()).run(&[(0, Press(0, 0)), (300, Release(0, 0))], 400);
+
+    assert_eq!(reports, [report(&[Keyboard::LeftShift]), report(&[])]);
+}
+
+#[test]
+fn hold_tap_modifies_the_next_key() {
+    let reports = sim(chorder!()).run(
+        &[
+            (0, Press(0, 0)),
+            (250, Press(0, 1)),
+            (280, Release(0, 1)),
+            (300, Release(0, 0)),
+        ],
+        400,
+    );
+
+    assert_eq!(
+        reports,
+        [
+            report(&[Keyboard::LeftShift]),
+            report(&[Keyboard::LeftShift, Keyboard::B]),
+            report(&[Keyboard::LeftShift]),
+            report(&[]),
+        ]
+    );
+}
+
+#[test]
+fn chord() {
+    let reports = sim(chorder!()).run(
+        &[
+            (0, Press(1, 0)),
+            (10, Press(1, 1)),
+            (50, Release(1, 0)),
+            (60, Release(1, 1)),
+        ],
+        100,
+    );
+
+    assert_eq!(reports, [report(&[Keyboard::Escape]), report(&[])]);
+}
+
+#[test]
+fn chord_key_tapped_alone() {
+    let reports = sim(chorder!()).run(&[(0, Press(1, 0)), (10, Release(1, 0))], 100);
+
+    assert_eq!(reports, [report(&[Keyboard::Q]), report(&[])]);
+}
+
+#[test]
+fn chord_key_held_past_the_timeout() {
+    let reports = sim(chorder!()).run(&[(0, Press(1, 0)), (100, Release(1, 0))], 150);
+
+    assert_eq!(reports, [report(&[Keyboard::Q]), report(&[])]);
+}
+
+#[test]
+fn chord_keys_pressed_too_far_apart() {
+    let reports = sim(chorder!()).run(
+        &[
+            (0, Press(1, 0)),
+            (100, Press(1, 1)),
+            (150, Release(1, 1)),
+            (200, Release(1, 0)),
+        ],
+        250,
+    );
+
+    assert_eq!(
+        reports,
+        [
+            report(&[Keyboard::Q]),
+            report(&[Keyboard::Q, Keyboard::W]),
+            report(&[Keyboard::Q]),
+            report(&[]),
+        ]
+    );
+}
+
+#[test]
+fn unicode_linux() {
+    let steps: Vec<_> = steps(UnicodeMode::Linux, "é").collect();
+    let prefix = [Keyboard::LeftControl, Keyboard::LeftShift, Keyboard::U];
+
+    assert_eq!(
+        steps,
+        [
+            Step::Report(report(&prefix)),
+            Step::Report(report(&[prefix[0], prefix[1], prefix[2], Keyboard::E])),
+            Step::Report(report(&[
+                prefix[0],
+                prefix[1],
+                prefix[2],
+                Keyboard::Keyboard9
+            ])),
+            Step::Report(report(&[])),
+        ]
+    );
+}
+
+#[test]
+fn unicode_mac() {
+    let steps: Vec<_> = steps(UnicodeMode::Mac, "é").collect();
+    let alts = [Keyboard::RightAlt, Keyboard::LeftAlt];
+
+    assert_eq!(
+        steps,
+        [
+            Step::Report(report(&[Keyboard::RightAlt])),
+            Step::Pause(std::time::Duration::from_millis(50)),
+            Step::Report(report(&alts)),
+            Step::Report(report(&[alts[0], alts[1], Keyboard::E])),
+            Step::Report(report(&alts)),
+            Step::Report(report(&[alts[0], alts[1], Keyboard::Keyboard9])),
+            Step::Report(report(&alts)),
+            Step::Report(report(&[])),
+        ]
+    );
+}
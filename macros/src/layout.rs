@@ -0,0 +1,455 @@
+//! Builds the keyberon layers and chords from a keymap-drawer yaml file
+//!
+//! keymap-drawer only cares about what's written on the keys, so anything the
+//! firmware needs on top of that lives in the `rusty_dilemma` section.
+
+use std::collections::{BTreeMap, HashMap};
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use serde::Deserialize;
+
+use crate::ChordDefn;
+
+const COLS: usize = 10;
+const ROWS: usize = 6;
+/// The physical rows of letter keys, the rest of the rows are the thumb keys
+/// and then the virtual keys fired by chords
+const MAIN_ROWS: usize = 3;
+const THUMB_ROW: usize = 3;
+const CHORD_ROWS: std::ops::Range<usize> = 4..6;
+
+#[derive(Deserialize)]
+struct LayoutFile {
+    layers: indexmap::IndexMap<String, Vec<Vec<Key>>>,
+    #[serde(default)]
+    combos: Vec<Combo>,
+    rusty_dilemma: Options,
+}
+
+#[derive(Deserialize)]
+struct Key {
+    tap: Option<String>,
+    hold: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Combo {
+    key_positions: Vec<usize>,
+    key: Key,
+    /// Every layer if not given
+    #[serde(default)]
+    layers: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Options {
+    hold_tap: HoldTap,
+    thumb_hold_tap: HoldTap,
+    /// The column each thumb key lands in, from left to right
+    thumb_columns: Vec<usize>,
+    /// Keys that aren't a keyberon keycode or a character
+    #[serde(default)]
+    aliases: BTreeMap<String, Alias>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+struct HoldTap {
+    timeout: u16,
+    interval: u16,
+    config: HoldTapConfig,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+enum HoldTapConfig {
+    Default,
+    HoldOnOtherKeyPress,
+    PermissiveHold,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Alias {
+    /// A keycode or custom event
+    Single(String),
+    /// Keycodes pressed together
+    Multiple(Vec<String>),
+}
+
+const CUSTOM_EVENTS: &[&str] = &["MouseLeft", "MouseRight", "MouseScroll"];
+
+pub struct Layout {
+    pub layers: TokenStream,
+    pub chords: Vec<ChordDefn>,
+}
+
+pub fn build(text: &str) -> Result<Layout, String> {
+    let file: LayoutFile = serde_yaml::from_str(text).map_err(|e| e.to_string())?;
+    let opts = &file.rusty_dilemma;
+
+    let layer_names: Vec<&str> = file.layers.keys().map(String::as_str).collect();
+    let ctx = Ctx {
+        opts,
+        layer_names: &layer_names,
+    };
+
+    let num_thumbs = opts.thumb_columns.len();
+    if let Some(c) = opts.thumb_columns.iter().find(|c| **c >= COLS) {
+        return Err(format!(
+            "thumb column {c} is out of range, there are {COLS} columns"
+        ));
+    }
+
+    // the main rows are laid out as is, followed by the thumb keys
+    let position = |idx: usize| -> Option<(u8, u8)> {
+        if idx < MAIN_ROWS * COLS {
+            Some(((idx / COLS) as u8, (idx % COLS) as u8))
+        } else {
+            let col = *opts.thumb_columns.get(idx - MAIN_ROWS * COLS)?;
+            Some((THUMB_ROW as u8, col as u8))
+        }
+    };
+
+    // each distinct set of keys gets its own virtual key, shared by every layer
+    let mut chord_keys: Vec<Vec<(u8, u8)>> = Vec::new();
+    let mut chord_actions: HashMap<(usize, usize), TokenStream> = HashMap::new();
+
+    for (i, combo) in file.combos.iter().enumerate() {
+        let mut keys = combo
+            .key_positions
+            .iter()
+            .map(|p| {
+                position(*p).ok_or_else(|| {
+                    format!(
+                        "combo {i} uses key position {p}, but there are only {} keys",
+                        MAIN_ROWS * COLS + num_thumbs
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        keys.sort();
+
+        if keys.len() < 2 {
+            return Err(format!("combo {i} needs at least two keys"));
+        }
+
+        let slot = match chord_keys.iter().position(|k| *k == keys) {
+            Some(s) => s,
+            None => {
+                chord_keys.push(keys);
+                chord_keys.len() - 1
+            }
+        };
+
+        let layers = if combo.layers.is_empty() {
+            (0..layer_names.len()).collect()
+        } else {
+            combo
+                .layers
+                .iter()
+                .map(|l| {
+                    layer_names
+                        .iter()
+                        .position(|n| n == l)
+                        .ok_or_else(|| format!("combo {i} refers to unknown layer `{l}`"))
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        for layer in layers {
+            let action = ctx
+                .key(&combo.key, false)
+                .map_err(|e| format!("{e} in combo {i}"))?;
+            chord_actions.insert((layer, slot), action);
+        }
+    }
+
+    let max_chords = CHORD_ROWS.len() * COLS;
+    if chord_keys.len() > max_chords {
+        return Err(format!(
+            "there are {} distinct combos but only room for {max_chords}",
+            chord_keys.len()
+        ));
+    }
+
+    let chord_slot = |slot: usize| ((CHORD_ROWS.start + slot / COLS) as u8, (slot % COLS) as u8);
+
+    let mut layers = Vec::new();
+
+    for (layer_idx, (name, rows)) in file.layers.iter().enumerate() {
+        let noop = quote!(::keyberon::action::Action::NoOp);
+        let mut grid: Vec<Vec<TokenStream>> = vec![vec![noop.clone(); COLS]; ROWS];
+
+        if rows.len() != MAIN_ROWS + 1 {
+            return Err(format!(
+                "layer `{name}` has {} rows, expected {MAIN_ROWS} rows of keys and a row of thumb keys",
+                rows.len()
+            ));
+        }
+
+        for (r, row) in rows.iter().enumerate() {
+            let is_thumbs = r == MAIN_ROWS;
+            let expected = if is_thumbs { num_thumbs } else { COLS };
+
+            if row.len() != expected {
+                return Err(format!(
+                    "row {r} of layer `{name}` has {} keys, expected {expected}",
+                    row.len()
+                ));
+            }
+
+            for (c, key) in row.iter().enumerate() {
+                let action = ctx
+                    .key(key, is_thumbs)
+                    .map_err(|e| format!("{e} on layer `{name}`, row {r}, column {c}"))?;
+
+                if is_thumbs {
+                    grid[THUMB_ROW][opts.thumb_columns[c]] = action;
+                } else {
+                    grid[r][c] = action;
+                }
+            }
+        }
+
+        for slot in 0..chord_keys.len() {
+            if let Some(action) = chord_actions.remove(&(layer_idx, slot)) {
+                let (r, c) = chord_slot(slot);
+                grid[r as usize][c as usize] = action;
+            }
+        }
+
+        let rows = grid.into_iter().map(|row| quote!([#(#row),*]));
+        layers.push(quote!([#(#rows),*]));
+    }
+
+    let layers = quote!(
+        pub static LAYERS: crate::keys::Layers = [#(#layers),*];
+    );
+
+    let chords = chord_keys
+        .into_iter()
+        .enumerate()
+        .map(|(slot, inputs)| ChordDefn {
+            inputs,
+            outputs: vec![chord_slot(slot)],
+        })
+        .collect();
+
+    Ok(Layout { layers, chords })
+}
+
+struct Ctx<'a> {
+    opts: &'a Options,
+    layer_names: &'a [&'a str],
+}
+
+impl Ctx<'_> {
+    fn key(&self, key: &Key, is_thumb: bool) -> Result<TokenStream, String> {
+        let Some(tap) = &key.tap else {
+            if key.hold.is_some() {
+                return Err("hold without a tap".to_owned());
+            }
+            return Ok(quote!(::keyberon::action::Action::NoOp));
+        };
+
+        let tap = self.action(tap)?;
+
+        let Some(hold) = &key.hold else {
+            return Ok(tap);
+        };
+
+        let hold = self.action(hold)?;
+        let HoldTap {
+            timeout,
+            interval,
+            config,
+        } = if is_thumb {
+            self.opts.thumb_hold_tap
+        } else {
+            self.opts.hold_tap
+        };
+        let config = match config {
+            HoldTapConfig::Default => quote!(Default),
+            HoldTapConfig::HoldOnOtherKeyPress => quote!(HoldOnOtherKeyPress),
+            HoldTapConfig::PermissiveHold => quote!(PermissiveHold),
+        };
+
+        Ok(
+            quote!(::keyberon::action::Action::HoldTap(&::keyberon::action::HoldTapAction {
+                timeout: #timeout,
+                hold: #hold,
+                tap: #tap,
+                config: ::keyberon::action::HoldTapConfig::#config,
+                tap_hold_interval: #interval,
+            })),
+        )
+    }
+
+    fn action(&self, label: &str) -> Result<TokenStream, String> {
+        // keymap-drawer labels for single characters have a trailing space
+        let trimmed = label.trim_end();
+
+        if let Some(idx) = self.layer_names.iter().position(|n| *n == trimmed) {
+            return Ok(quote!(::keyberon::action::Action::Layer(#idx)));
+        }
+
+        if let Some(alias) = self.opts.aliases.get(trimmed) {
+            return match alias {
+                Alias::Single(name) => custom_event(name)
+                    .or_else(|| keycode(name).map(|k| keycodes_action(&[k])))
+                    .ok_or_else(|| format!("unknown keycode `{name}` in alias `{trimmed}`")),
+                Alias::Multiple(names) => {
+                    let codes = names
+                        .iter()
+                        .map(|n| {
+                            keycode(n).ok_or_else(|| {
+                                format!("unknown keycode `{n}` in alias `{trimmed}`")
+                            })
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Ok(keycodes_action(&codes))
+                }
+            };
+        }
+
+        let mut chars = trimmed.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if let Some(codes) = char_keycodes(c) {
+                return Ok(keycodes_action(&codes));
+            }
+
+            if !c.is_ascii() {
+                return Ok(quote!(::keyberon::action::Action::Custom(
+                    crate::keys::CustomEvent::TypeUnicode(#trimmed)
+                )));
+            }
+        }
+
+        if let Some(k) = keycode(trimmed) {
+            return Ok(keycodes_action(&[k]));
+        }
+
+        Err(format!("unknown key `{label}`"))
+    }
+}
+
+fn custom_event(name: &str) -> Option<TokenStream> {
+    CUSTOM_EVENTS.contains(&name).then(|| {
+        let name = quote::format_ident!("{}", name);
+        quote!(::keyberon::action::Action::Custom(crate::keys::CustomEvent::#name))
+    })
+}
+
+fn keycodes_action(codes: &[&str]) -> TokenStream {
+    let codes: Vec<_> = codes
+        .iter()
+        .map(|c| {
+            let c = quote::format_ident!("{}", c);
+            quote!(::keyberon::key_code::KeyCode::#c)
+        })
+        .collect();
+
+    if let [code] = codes.as_slice() {
+        quote!(::keyberon::action::Action::KeyCode(#code))
+    } else {
+        quote!(::keyberon::action::Action::MultipleKeyCodes(&[#(#codes),*].as_slice()))
+    }
+}
+
+/// The keycodes typing `c` on a US layout
+fn char_keycodes(c: char) -> Option<Vec<&'static str>> {
+    const UNSHIFTED: &[(char, &str)] = &[
+        ('1', "Kb1"),
+        ('2', "Kb2"),
+        ('3', "Kb3"),
+        ('4', "Kb4"),
+        ('5', "Kb5"),
+        ('6', "Kb6"),
+        ('7', "Kb7"),
+        ('8', "Kb8"),
+        ('9', "Kb9"),
+        ('0', "Kb0"),
+        ('-', "Minus"),
+        ('=', "Equal"),
+        ('[', "LBracket"),
+        (']', "RBracket"),
+        ('\\', "Bslash"),
+        (';', "SColon"),
+        ('\'', "Quote"),
+        ('`', "Grave"),
+        (',', "Comma"),
+        ('.', "Dot"),
+        ('/', "Slash"),
+    ];
+    const SHIFTED: &[(char, &str)] = &[
+        ('!', "Kb1"),
+        ('@', "Kb2"),
+        ('#', "Kb3"),
+        ('$', "Kb4"),
+        ('%', "Kb5"),
+        ('^', "Kb6"),
+        ('&', "Kb7"),
+        ('*', "Kb8"),
+        ('(', "Kb9"),
+        (')', "Kb0"),
+        ('_', "Minus"),
+        ('+', "Equal"),
+        ('{', "LBracket"),
+        ('}', "RBracket"),
+        ('|', "Bslash"),
+        (':', "SColon"),
+        ('"', "Quote"),
+        ('~', "Grave"),
+        ('<', "Comma"),
+        ('>', "Dot"),
+        ('?', "Slash"),
+    ];
+
+    if c.is_ascii_lowercase() {
+        return keycode(&c.to_ascii_uppercase().to_string()).map(|k| vec![k]);
+    }
+    if c.is_ascii_uppercase() {
+        return keycode(&c.to_string()).map(|k| vec!["LShift", k]);
+    }
+    if let Some((_, k)) = UNSHIFTED.iter().find(|(u, _)| *u == c) {
+        return Some(vec![k]);
+    }
+    if let Some((_, k)) = SHIFTED.iter().find(|(s, _)| *s == c) {
+        return Some(vec!["LShift", k]);
+    }
+
+    None
+}
+
+fn keycode(name: &str) -> Option<&'static str> {
+    KEYCODES.iter().find(|k| **k == name).copied()
+}
+
+/// Every variant of `keyberon::key_code::KeyCode`
+#[rustfmt::skip]
+const KEYCODES: &[&str] = &[
+    "No", "ErrorRollOver", "PostFail", "ErrorUndefined",
+    "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M",
+    "N", "O", "P", "Q", "R", "S", "T", "U", "V", "W", "X", "Y", "Z",
+    "Kb1", "Kb2", "Kb3", "Kb4", "Kb5", "Kb6", "Kb7", "Kb8", "Kb9", "Kb0",
+    "Enter", "Escape", "BSpace", "Tab", "Space", "Minus", "Equal", "LBracket", "RBracket",
+    "Bslash", "NonUsHash", "SColon", "Quote", "Grave", "Comma", "Dot", "Slash", "CapsLock",
+    "F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8", "F9", "F10", "F11", "F12",
+    "PScreen", "ScrollLock", "Pause", "Insert", "Home", "PgUp", "Delete", "End", "PgDown",
+    "Right", "Left", "Down", "Up", "NumLock", "KpSlash", "KpAsterisk", "KpMinus", "KpPlus",
+    "KpEnter", "Kp1", "Kp2", "Kp3", "Kp4", "Kp5", "Kp6", "Kp7", "Kp8", "Kp9", "Kp0", "KpDot",
+    "NonUsBslash", "Application", "Power", "KpEqual",
+    "F13", "F14", "F15", "F16", "F17", "F18", "F19", "F20", "F21", "F22", "F23", "F24",
+    "Execute", "Help", "Menu", "Select", "Stop", "Again", "Undo", "Cut", "Copy", "Paste",
+    "Find", "Mute", "VolUp", "VolDown", "LockingCapsLock", "LockingNumLock",
+    "LockingScrollLock", "KpComma", "KpEqualSign",
+    "Intl1", "Intl2", "Intl3", "Intl4", "Intl5", "Intl6", "Intl7", "Intl8", "Intl9",
+    "Lang1", "Lang2", "Lang3", "Lang4", "Lang5", "Lang6", "Lang7", "Lang8", "Lang9",
+    "AltErase", "SysReq", "Cancel", "Clear", "Prior", "Return", "Separator", "Out", "Oper",
+    "ClearAgain", "CrSel", "ExSel",
+    "LCtrl", "LShift", "LAlt", "LGui", "RCtrl", "RShift", "RAlt", "RGui",
+    "MediaPlayPause", "MediaStopCD", "MediaPreviousSong", "MediaNextSong", "MediaEjectCD",
+    "MediaVolUp", "MediaVolDown", "MediaMute", "MediaWWW", "MediaBack", "MediaForward",
+    "MediaStop", "MediaFind", "MediaScrollUp", "MediaScrollDown", "MediaEdit", "MediaSleep",
+    "MediaCoffee", "MediaRefresh", "MediaCalc",
+];
@@ -4,9 +4,11 @@ use proc_macro2::TokenStream;
 use quote::quote;
 use syn::{
     bracketed, parenthesized, parse::Parse, parse_macro_input, punctuated::Punctuated, token,
-    LitInt,
+    LitInt, LitStr,
 };
 
+mod layout;
+
 #[allow(unused)]
 struct Key {
     x: LitInt,
@@ -68,30 +70,89 @@ fn singleton(typ: TokenStream, x: TokenStream) -> TokenStream {
     })
 }
 
+pub(crate) struct ChordDefn {
+    inputs: Vec<(u8, u8)>,
+    outputs: Vec<(u8, u8)>,
+}
+
 #[proc_macro]
 pub fn chords(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let mut key_chord_map: HashMap<(u8, u8), Vec<usize>> = HashMap::new();
-    let mut chords: Vec<Chord> = Vec::new();
-
     let p = parse_macro_input!(item with Punctuated::<Chord, token::Comma>::parse_terminated);
 
-    for (i, chord) in p.into_iter().enumerate() {
-        for key in &chord.inputs {
-            key_chord_map.entry(key.val).or_default().push(i);
+    let chords = p
+        .into_iter()
+        .map(|c| ChordDefn {
+            inputs: c.inputs.iter().map(|k| k.val).collect(),
+            outputs: c.outputs.iter().map(|k| k.val).collect(),
+        })
+        .collect();
+
+    chorder(chords).into()
+}
+
+/// Build `LAYERS` and `chorder()` from a keymap-drawer yaml file, the path is
+/// relative to the crate root
+#[proc_macro]
+pub fn layout(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let path_lit = parse_macro_input!(item as LitStr);
+    let path =
+        std::path::Path::new(&std::env::var("CARGO_MANIFEST_DIR").unwrap()).join(path_lit.value());
+
+    let text = match std::fs::read_to_string(&path) {
+        Ok(t) => t,
+        Err(e) => {
+            let msg = format!("couldn't read {}: {e}", path.display());
+            return syn::Error::new(path_lit.span(), msg)
+                .to_compile_error()
+                .into();
+        }
+    };
+
+    let layout = match layout::build(&text) {
+        Ok(l) => l,
+        Err(e) => {
+            let msg = format!("{}: {e}", path_lit.value());
+            return syn::Error::new(path_lit.span(), msg)
+                .to_compile_error()
+                .into();
         }
+    };
 
-        chords.push(chord);
+    let layers = layout.layers;
+    let chorder = chorder(layout.chords);
+    let path = path.display().to_string();
+
+    quote!(
+        // rebuild when the layout changes
+        const _: &[u8] = include_bytes!(#path);
+
+        #layers
+
+        pub fn chorder() -> crate::keys::chord::Chorder {
+            #chorder
+        }
+    )
+    .into()
+}
+
+fn chorder(chords: Vec<ChordDefn>) -> TokenStream {
+    let mut key_chord_map: HashMap<(u8, u8), Vec<usize>> = HashMap::new();
+
+    for (i, chord) in chords.iter().enumerate() {
+        for key in &chord.inputs {
+            key_chord_map.entry(*key).or_default().push(i);
+        }
     }
 
     let chord_defns = chords.into_iter().map(|c| {
         let mut keys_map = phf_codegen::Map::new();
         let num_keys = c.inputs.len();
         for (i, key) in c.inputs.iter().enumerate() {
-            keys_map.entry([key.val.0, key.val.1], &i.to_string());
+            keys_map.entry([key.0, key.1], &i.to_string());
         }
         let keys_t = keys_map.build().to_string().parse::<TokenStream>().unwrap();
         let key_states_t = singleton(quote!([bool; #num_keys]), quote!([false; #num_keys]));
-        let actions_t = c.outputs.iter().map(|Key { x, y, .. }| quote!((#x, #y)));
+        let actions_t = c.outputs.iter().map(|(x, y)| quote!((#x, #y)));
         let action_t = quote!([#(#actions_t),*]);
 
         quote!(
@@ -111,7 +172,10 @@ pub fn chords(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
     });
 
     let num_chords = chord_defns.len();
-    let chord_defns_t = singleton(quote!([crate::keys::chord::Chord; #num_chords]), quote!([#(#chord_defns),*]));
+    let chord_defns_t = singleton(
+        quote!([crate::keys::chord::Chord; #num_chords]),
+        quote!([#(#chord_defns),*]),
+    );
 
     let mut key_chord_map_p = phf_codegen::Map::new();
     for (key, val) in key_chord_map {
@@ -135,5 +199,4 @@ pub fn chords(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
             chords,
         }
     })
-    .into()
 }
@@ -0,0 +1,79 @@
+//! Raw copies of the config database, for moving settings, keymaps and
+//! metrics between keyboards
+//!
+//! A backup file is a postcard encoded [`BackupHeader`] followed by the
+//! contents of the database pages. Each half has its own database, so backups
+//! are taken from and restored to one half at a time.
+
+use core::hash::Hash;
+use serde::{Deserialize, Serialize};
+
+pub const BACKUP_MAGIC: u32 = u32::from_le_bytes(*b"RDcf");
+/// Bump this whenever the layout of [`BackupHeader`] changes
+pub const BACKUP_FORMAT_VERSION: u16 = 1;
+
+/// Backups are transferred in chunks so that each message fits in a frame
+pub const BACKUP_CHUNK_LEN: usize = 64;
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BackupHeader {
+    pub magic: u32,
+    pub format_version: u16,
+    /// The database can only be restored onto firmware with the same layout
+    pub page_size: u32,
+    pub page_count: u32,
+    /// See [`checksum`]
+    pub crc: u32,
+}
+
+impl BackupHeader {
+    pub fn new(page_size: u32, page_count: u32, crc: u32) -> Self {
+        Self {
+            magic: BACKUP_MAGIC,
+            format_version: BACKUP_FORMAT_VERSION,
+            page_size,
+            page_count,
+            crc,
+        }
+    }
+
+    /// Number of bytes following the header
+    pub fn len(&self) -> u32 {
+        self.page_size * self.page_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.magic == BACKUP_MAGIC && self.format_version == BACKUP_FORMAT_VERSION
+    }
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConfigStatus {
+    pub mounted: bool,
+    /// The database couldn't be mounted this boot and was reformatted
+    pub rescued_at_boot: bool,
+    /// A copy of a database that failed to mount is available as
+    /// [`BackupSource::Rescued`]
+    pub has_rescued_copy: bool,
+}
+
+/// Which copy of the database a backup is taken from
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BackupSource {
+    /// The database in use
+    Live,
+    /// The copy kept when the database couldn't be mounted at boot
+    Rescued,
+}
+
+/// Checksum of the database contents
+pub fn checksum(data: &[u8]) -> u32 {
+    crc32fast::hash(data)
+}
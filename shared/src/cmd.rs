@@ -66,6 +66,23 @@ impl<T: Hash> Command<T> {
         let expected_csum = calc_csum(&self.cmd);
         self.csum == expected_csum
     }
+
+    /// Like [`Command::new_reliable`] and [`Command::new_unreliable`], but the
+    /// checksum covers `command_seq` too so a corrupted id can't pass for
+    /// another. The host doesn't know about these, so they're only sent
+    /// between the halves
+    pub fn new_sequenced(cmd: T, command_seq: CommandSeq) -> Self {
+        let csum = calc_csum((command_seq, &cmd));
+        Self {
+            command_seq,
+            cmd,
+            csum,
+        }
+    }
+
+    pub fn validate_sequenced(&self) -> bool {
+        self.csum == calc_csum((self.command_seq, &self.cmd))
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -75,6 +92,38 @@ pub enum CmdOrAck<T> {
     Cmd(Command<T>),
     Ack,
     Nack,
+    /// Acknowledges every reliable command up to and including the one with
+    /// `id`, for links with more than one waiting for an ack at a time
+    AckUpto { id: u8, csum: u16 },
+    /// Sent before the first reliable command on such a link, which will have
+    /// `id`. Answered with an [`CmdOrAck::AckUpto`] of the id before it
+    Sync { id: u8, csum: u16 },
+}
+
+impl<T> CmdOrAck<T> {
+    pub fn ack_upto(id: u8) -> Self {
+        Self::AckUpto {
+            id,
+            csum: calc_csum(("ack", id)),
+        }
+    }
+
+    pub fn sync(id: u8) -> Self {
+        Self::Sync {
+            id,
+            csum: calc_csum(("sync", id)),
+        }
+    }
+
+    /// Checks the checksum of an [`CmdOrAck::AckUpto`] or [`CmdOrAck::Sync`],
+    /// commands are checked with [`Command::validate`] instead
+    pub fn validate_id(&self) -> bool {
+        match *self {
+            Self::AckUpto { id, csum } => csum == calc_csum(("ack", id)),
+            Self::Sync { id, csum } => csum == calc_csum(("sync", id)),
+            Self::Cmd(_) | Self::Ack | Self::Nack => true,
+        }
+    }
 }
 
 #[derive(Debug, Default)]
@@ -0,0 +1,41 @@
+//! Reports of crashes, kept across the reset and sent to the host by the next
+//! boot
+//!
+//! The RP2040's Cortex-M0+ cores have no fault status registers, so a hard
+//! fault is described by the registers stacked when it happened: where it
+//! happened and what called it, and the xpsr whose low bits give the exception
+//! that was running.
+
+use core::hash::Hash;
+use serde::{Deserialize, Serialize};
+
+/// Long enough for the end of a source path, the start is cut off
+pub const MAX_CRASH_FILE_LEN: usize = 32;
+pub const MAX_CRASH_MESSAGE_LEN: usize = 40;
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CrashKind {
+    Panic,
+    HardFault,
+    /// The bootloader faulted before it got as far as starting the firmware
+    BootloaderFault,
+    /// A task stopped making progress and the watchdog reset the chip, the
+    /// message names the task
+    Hang,
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CrashReport {
+    pub kind: CrashKind,
+    /// Where a panic was raised, empty for faults
+    pub file: heapless::String<MAX_CRASH_FILE_LEN>,
+    pub line: u32,
+    /// The panic message, cut short if it didn't fit, or the task that hung
+    pub message: heapless::String<MAX_CRASH_MESSAGE_LEN>,
+    /// Registers stacked by a fault, zero for panics
+    pub pc: u32,
+    pub lr: u32,
+    pub xpsr: u32,
+}
@@ -1,7 +1,15 @@
 use core::hash::Hash;
 use serde::{Deserialize, Serialize};
 
+use crate::backup::{BackupHeader, ConfigStatus, BACKUP_CHUNK_LEN};
+use crate::crash::CrashReport;
+use crate::handshake::{DeviceInfo, MAX_VERSION_LEN};
+use crate::host_to_device::{AnimationKind, RequestId, MAX_PING_LEN};
+use crate::image::ImageHeader;
+use crate::link_stats::LinkStats;
+use crate::settings::Settings;
 use crate::side::KeyboardSide;
+use crate::update::RelayStatus;
 
 pub const MAX_LOG_LEN: usize = 16;
 
@@ -15,5 +23,94 @@ pub struct DeviceToHost {
 #[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum DeviceToHostMsg {
-    Log { msg: heapless::Vec<u8, MAX_LOG_LEN> },
+    /// This variant is frozen, see [`crate::handshake`]
+    Hello(DeviceInfo),
+    Log {
+        msg: heapless::Vec<u8, MAX_LOG_LEN>,
+    },
+    Response {
+        id: RequestId,
+        result: Result<Reply, RpcError>,
+    },
+    /// Why the device reset before this boot, sent along with the hello
+    CrashReport(CrashReport),
 }
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Reply {
+    Pong {
+        payload: heapless::Vec<u8, MAX_PING_LEN>,
+    },
+    /// Sent just before the device resets
+    Rebooting,
+    AnimationSet(AnimationKind),
+    Version {
+        version: heapless::String<MAX_VERSION_LEN>,
+    },
+    /// How much of the keymap upload has been received so far
+    KeymapReceived {
+        len: u16,
+    },
+    KeymapApplied,
+    /// The current settings, also the reply to a setting being changed
+    Settings(Settings),
+    ConfigStatus(ConfigStatus),
+    Backup(BackupHeader),
+    BackupChunk {
+        data: heapless::Vec<u8, BACKUP_CHUNK_LEN>,
+    },
+    /// How much of the backup being restored has been received so far
+    RestoreReceived {
+        len: u32,
+    },
+    /// How much of the firmware image has been received so far
+    UpdateReceived {
+        len: u32,
+    },
+    ImageHeader(ImageHeader),
+    UpdateRelay(RelayStatus),
+    LinkStats(LinkStats),
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RpcError {
+    /// The command isn't supported by this firmware build
+    UnknownCommand,
+    FlashFailure,
+    /// The command can't be handled by the side it was sent to
+    WrongSide,
+    /// Another request is still in progress, try again later
+    Busy,
+    /// The keymap didn't arrive intact or doesn't make sense
+    InvalidKeymap,
+    /// The value is outside of [`crate::settings::Setting::range`]
+    InvalidSetting,
+    /// The backup is missing, didn't arrive intact or doesn't fit this
+    /// firmware
+    InvalidBackup,
+    /// The firmware image didn't arrive intact or is too large
+    InvalidUpdate,
+    /// The other half stopped replying over the interboard link
+    OtherSideUnreachable,
+}
+
+impl core::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            RpcError::UnknownCommand => "command not supported by the firmware",
+            RpcError::FlashFailure => "failed to access flash",
+            RpcError::WrongSide => "command can't be handled by this side",
+            RpcError::Busy => "device is busy",
+            RpcError::InvalidKeymap => "keymap was corrupted or invalid",
+            RpcError::InvalidSetting => "setting value out of range",
+            RpcError::InvalidBackup => "backup missing, corrupted or made for different firmware",
+            RpcError::InvalidUpdate => "firmware image was corrupted or too large",
+            RpcError::OtherSideUnreachable => "the other half isn't responding",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RpcError {}
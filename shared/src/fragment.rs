@@ -0,0 +1,21 @@
+//! Pieces of a message too big to be sent in one frame, see
+//! `dilemma_transmissions::fragments` for splitting messages up and putting
+//! them back together
+
+use core::hash::Hash;
+use serde::{Deserialize, Serialize};
+
+/// Fragments are this long, apart from the last one of a message
+pub const FRAGMENT_LEN: usize = 64;
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Fragment {
+    /// Which message this is part of, so that fragments left over from one
+    /// that didn't make it aren't taken for part of the next
+    pub transfer: u8,
+    pub index: u16,
+    /// How many fragments the message was split into
+    pub count: u16,
+    pub data: heapless::Vec<u8, FRAGMENT_LEN>,
+}
@@ -0,0 +1,51 @@
+//! The first thing exchanged when the host opens the serial port
+//!
+//! A host and firmware built from different commits may disagree on the layout
+//! of any of the messages, so everything needed to check compatibility is
+//! frozen: the `CmdOrAck`/`Command` envelope, the `id` and `target_side` fields
+//! of [`crate::host_to_device::HostToDevice`], the first variant of both
+//! [`crate::host_to_device::HostToDeviceMsg`] and
+//! [`crate::device_to_host::DeviceToHostMsg`], and [`DeviceInfo`].
+//!
+//! Variants can still be added to the end of `CmdOrAck`, as the ones before
+//! keep their encoding. Anything else may change, as long as
+//! [`PROTOCOL_VERSION`] is bumped.
+
+use core::hash::Hash;
+use serde::{Deserialize, Serialize};
+
+/// Bump this whenever the layout of any (non-frozen) message changes
+pub const PROTOCOL_VERSION: u16 = 11;
+
+pub const MAX_VERSION_LEN: usize = 16;
+pub const MAX_BUILD_DATE_LEN: usize = 16;
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceInfo {
+    pub protocol_version: u16,
+    pub firmware_version: heapless::String<MAX_VERSION_LEN>,
+    pub build_date: heapless::String<MAX_BUILD_DATE_LEN>,
+    pub capabilities: Capabilities,
+}
+
+impl DeviceInfo {
+    pub fn is_compatible(&self) -> bool {
+        self.protocol_version == PROTOCOL_VERSION
+    }
+}
+
+#[bitfield_struct::bitfield(u32, defmt = cfg(feature = "defmt"))]
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash)]
+pub struct Capabilities {
+    pub display: bool,
+    pub trackpad: bool,
+    /// The settings database in flash is mounted
+    pub flash_db: bool,
+    /// This half is the one plugged into the host
+    pub usb: bool,
+    /// There's a bootloader to install firmware updates sent over the link
+    pub update: bool,
+    #[bits(27)]
+    _reserved: u32,
+}
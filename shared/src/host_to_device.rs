@@ -1,11 +1,21 @@
 use core::hash::Hash;
 use serde::{Deserialize, Serialize};
 
+use crate::backup::{BackupHeader, BackupSource, BACKUP_CHUNK_LEN};
+use crate::keymap::KEYMAP_CHUNK_LEN;
+use crate::link_stats::LinkKind;
+use crate::settings::Setting;
 use crate::side::KeyboardSide;
+use crate::update::UPDATE_CHUNK_LEN;
+
+pub type RequestId = u16;
 
 #[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct HostToDevice {
+    /// Echoed back in the [`crate::device_to_host::DeviceToHostMsg::Response`]
+    /// from each side handling the request
+    pub id: RequestId,
     /// Side the message should end up on, if None then both
     pub target_side: Option<KeyboardSide>,
     pub msg: HostToDeviceMsg,
@@ -17,6 +27,111 @@ impl HostToDevice {
     }
 }
 
+pub const MAX_PING_LEN: usize = 16;
+
 #[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub enum HostToDeviceMsg {}
+pub enum HostToDeviceMsg {
+    /// Must be the first message sent after opening the port, the device
+    /// ignores anything else until it gets one with a matching protocol
+    /// version. Replied to with a
+    /// [`crate::device_to_host::DeviceToHostMsg::Hello`] by each side.
+    ///
+    /// This variant is frozen, see [`crate::handshake`]
+    Hello {
+        protocol_version: u16,
+    },
+    /// Replied to with a [`crate::device_to_host::Reply::Pong`] carrying the
+    /// same payload
+    Ping {
+        payload: heapless::Vec<u8, MAX_PING_LEN>,
+    },
+    Reboot,
+    RebootToBootloader,
+    /// Animations are synced between both halves, so this is only acted on by
+    /// the half with usb connected, which then updates the other half.
+    /// Targeting only the other half fails with
+    /// [`crate::device_to_host::RpcError::WrongSide`]
+    SetAnimation(AnimationKind),
+    QueryVersion,
+    /// Start uploading a postcard encoded [`crate::keymap::Keymap`] of `len`
+    /// bytes, discarding any unfinished upload
+    BeginKeymap {
+        len: u16,
+    },
+    KeymapChunk {
+        offset: u16,
+        data: heapless::Vec<u8, KEYMAP_CHUNK_LEN>,
+    },
+    /// Check the uploaded keymap against `crc` (see
+    /// [`crate::keymap::checksum`]), store it in flash and switch to it
+    CommitKeymap {
+        crc: u32,
+    },
+    /// Forget the stored keymap and go back to the one built into the firmware
+    ResetKeymap,
+    /// Replied to with a [`crate::device_to_host::Reply::Settings`]
+    GetSettings,
+    /// Change a setting and store it in flash, the side handling this passes
+    /// the change on to the other side
+    SetSetting(Setting),
+    /// Replied to with a [`crate::device_to_host::Reply::ConfigStatus`]
+    ConfigStatus,
+    /// Start taking a backup of the config database, replied to with a
+    /// [`crate::device_to_host::Reply::Backup`] describing it
+    BeginBackup(BackupSource),
+    ReadBackup {
+        source: BackupSource,
+        offset: u32,
+    },
+    /// Start restoring a backup, it's staged until [`Self::CommitRestore`]
+    /// and replaces any rescued copy of the database
+    BeginRestore(BackupHeader),
+    RestoreChunk {
+        offset: u32,
+        data: heapless::Vec<u8, BACKUP_CHUNK_LEN>,
+    },
+    /// Check the staged backup against its header, then replace the database
+    /// with it and reboot
+    CommitRestore,
+    /// Wipe the config database and reboot
+    FactoryReset,
+    /// Start uploading a firmware image of `len` bytes, discarding any
+    /// unfinished upload. Only supported when the bootloader is in use
+    BeginUpdate {
+        len: u32,
+    },
+    UpdateChunk {
+        offset: u32,
+        data: heapless::Vec<u8, UPDATE_CHUNK_LEN>,
+    },
+    /// Check the uploaded image against `crc` (see
+    /// [`crate::update::checksum`]), then reboot so that the bootloader
+    /// installs it
+    CommitUpdate {
+        crc: u32,
+    },
+    /// Check the uploaded image against `crc` like [`Self::CommitUpdate`], then
+    /// start passing it on to the other half rather than installing it. Only
+    /// the half plugged into the host can do this, the progress is polled
+    /// with [`Self::UpdateRelayStatus`]
+    RelayUpdate {
+        crc: u32,
+    },
+    /// Replied to with a [`crate::device_to_host::Reply::UpdateRelay`]
+    UpdateRelayStatus,
+    /// Replied to with a [`crate::device_to_host::Reply::ImageHeader`] of the
+    /// running firmware. Only supported when the bootloader is in use
+    GetImageHeader,
+    /// Replied to with a [`crate::device_to_host::Reply::LinkStats`]
+    GetLinkStats(LinkKind),
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AnimationKind {
+    Snow,
+    Perlin,
+    Rain,
+    Off,
+}
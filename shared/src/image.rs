@@ -0,0 +1,139 @@
+//! Header embedded in firmware images built for the bootloader
+//!
+//! The header sits right after the vector table, at [`IMAGE_HEADER_OFFSET`]
+//! from the start of the image. The firmware build can't know its own length
+//! and crc, so it leaves them erased and [`seal`] fills them in once the final
+//! binary exists. The bootloader refuses to boot an image whose header doesn't
+//! match.
+
+use core::hash::Hash;
+use serde::{Deserialize, Serialize};
+
+pub const IMAGE_HEADER_MAGIC: u32 = u32::from_le_bytes(*b"RDim");
+
+/// The vector table is 48 words long, and the header follows it
+pub const IMAGE_HEADER_OFFSET: usize = 0xc0;
+pub const IMAGE_HEADER_LEN: usize = 32;
+
+/// Offset of [`ImageHeader::crc`] from the start of the image
+const CRC_OFFSET: usize = IMAGE_HEADER_OFFSET + 8;
+
+/// Value of the fields the build leaves for [`seal`]
+const UNSEALED: u32 = 0xffff_ffff;
+
+/// Must match the layout the bootloader expects
+#[repr(C)]
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ImageHeader {
+    pub magic: u32,
+    /// Length of the whole image, including everything before the header
+    pub len: u32,
+    /// See [`checksum`]
+    pub crc: u32,
+    /// Unix time the firmware was built at
+    pub build_time: u32,
+    /// Firmware version, nul padded
+    pub version: [u8; 16],
+}
+
+impl ImageHeader {
+    /// The header as the build leaves it, the version is cut short to leave
+    /// room for a nul
+    pub const fn unsealed(version: &str, build_time: u32) -> Self {
+        let mut padded = [0; 16];
+        let bytes = version.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() && i < padded.len() - 1 {
+            padded[i] = bytes[i];
+            i += 1;
+        }
+
+        Self {
+            magic: IMAGE_HEADER_MAGIC,
+            len: UNSEALED,
+            crc: UNSEALED,
+            build_time,
+            version: padded,
+        }
+    }
+
+    pub fn is_sealed(&self) -> bool {
+        self.len != UNSEALED || self.crc != UNSEALED
+    }
+
+    pub fn version(&self) -> &str {
+        let len = self
+            .version
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(self.version.len());
+        core::str::from_utf8(&self.version[..len]).unwrap_or("?")
+    }
+
+    pub fn to_bytes(&self) -> [u8; IMAGE_HEADER_LEN] {
+        let mut bytes = [0; IMAGE_HEADER_LEN];
+        for (i, word) in [self.magic, self.len, self.crc, self.build_time]
+            .into_iter()
+            .enumerate()
+        {
+            bytes[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        bytes[16..].copy_from_slice(&self.version);
+        bytes
+    }
+
+    /// Parse a header, `None` if it's too short or the magic doesn't match
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.get(..IMAGE_HEADER_LEN)?;
+        let word = |i: usize| u32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap());
+
+        let header = Self {
+            magic: word(0),
+            len: word(1),
+            crc: word(2),
+            build_time: word(3),
+            version: bytes[16..].try_into().unwrap(),
+        };
+
+        (header.magic == IMAGE_HEADER_MAGIC).then_some(header)
+    }
+
+    /// Find the header of an image
+    pub fn of_image(image: &[u8]) -> Option<Self> {
+        Self::from_bytes(image.get(IMAGE_HEADER_OFFSET..)?)
+    }
+}
+
+/// Crc32 of an image, taken with its crc field erased
+pub fn checksum(image: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&image[..CRC_OFFSET]);
+    hasher.update(&UNSEALED.to_le_bytes());
+    hasher.update(&image[CRC_OFFSET + 4..]);
+    hasher.finalize()
+}
+
+/// Fill in the length and crc of a freshly built image, returning the sealed
+/// header or `None` if the image doesn't have one
+pub fn seal(image: &mut [u8]) -> Option<ImageHeader> {
+    let mut header = ImageHeader::of_image(image)?;
+    let at = IMAGE_HEADER_OFFSET..IMAGE_HEADER_OFFSET + IMAGE_HEADER_LEN;
+
+    header.len = u32::try_from(image.len()).ok()?;
+    header.crc = UNSEALED;
+    image[at.clone()].copy_from_slice(&header.to_bytes());
+
+    header.crc = checksum(image);
+    image[at].copy_from_slice(&header.to_bytes());
+
+    Some(header)
+}
+
+/// Whether an image is sealed and intact
+pub fn verify(image: &[u8]) -> bool {
+    match ImageHeader::of_image(image) {
+        Some(header) => header.len as usize == image.len() && header.crc == checksum(image),
+        None => false,
+    }
+}
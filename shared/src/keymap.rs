@@ -0,0 +1,172 @@
+//! A keymap that can be sent over the wire and stored in flash
+//!
+//! This mirrors the subset of keyberon's `Action` used by the keyboard, with
+//! keycodes stored as their HID usage id so this doesn't need to depend on
+//! keyberon.
+
+use core::hash::Hash;
+use serde::{Deserialize, Serialize};
+
+pub const COLS: usize = 10;
+pub const ROWS: usize = 6;
+pub const LAYERS: usize = 3;
+
+/// Keys of a [`KeyAction::MultipleKeyCodes`] pressed at once
+pub const MAX_COMBO_KEYS: usize = 4;
+pub const MAX_UNICODE_STRINGS: usize = 8;
+pub const MAX_UNICODE_LEN: usize = 16;
+
+/// Largest encoded keymap the firmware will accept
+pub const MAX_KEYMAP_LEN: usize = 960;
+/// Keymaps are uploaded in chunks so that each message fits in a frame
+pub const KEYMAP_CHUNK_LEN: usize = 64;
+
+pub type Layer = [[KeyAction; COLS]; ROWS];
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Debug)]
+pub struct Keymap {
+    pub layers: [Layer; LAYERS],
+    /// Strings typed by [`CustomAction::TypeUnicode`]
+    pub unicode: heapless::Vec<heapless::String<MAX_UNICODE_LEN>, MAX_UNICODE_STRINGS>,
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum KeyAction {
+    NoOp,
+    Trans,
+    KeyCode(u8),
+    MultipleKeyCodes(heapless::Vec<u8, MAX_COMBO_KEYS>),
+    Layer(u8),
+    DefaultLayer(u8),
+    HoldTap {
+        timeout: u16,
+        hold: BasicAction,
+        tap: BasicAction,
+        config: HoldTapConfig,
+        tap_hold_interval: u16,
+    },
+    Custom(CustomAction),
+}
+
+/// The actions allowed on either side of a hold tap
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BasicAction {
+    NoOp,
+    Trans,
+    KeyCode(u8),
+    Layer(u8),
+    Custom(CustomAction),
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HoldTapConfig {
+    Default,
+    HoldOnOtherKeyPress,
+    PermissiveHold,
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CustomAction {
+    MouseLeft,
+    MouseRight,
+    MouseScroll,
+    /// Index into [`Keymap::unicode`]
+    TypeUnicode(u8),
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum KeymapError {
+    /// A keycode that isn't a usb hid keyboard usage
+    InvalidKeyCode { layer: u8, row: u8, col: u8 },
+    /// A layer switch to a layer that doesn't exist
+    InvalidLayer { layer: u8, row: u8, col: u8 },
+    /// A unicode action with no matching string
+    InvalidString { layer: u8, row: u8, col: u8 },
+}
+
+impl core::fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let (what, layer, row, col) = match *self {
+            KeymapError::InvalidKeyCode { layer, row, col } => ("invalid keycode", layer, row, col),
+            KeymapError::InvalidLayer { layer, row, col } => ("invalid layer", layer, row, col),
+            KeymapError::InvalidString { layer, row, col } => {
+                ("invalid unicode string", layer, row, col)
+            }
+        };
+
+        write!(f, "{what} on layer {layer} at row {row}, column {col}")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for KeymapError {}
+
+/// Whether `code` is one of the keycodes keyberon knows about
+pub fn is_valid_keycode(code: u8) -> bool {
+    matches!(code, 0x00..=0xA4 | 0xE0..=0xFB)
+}
+
+/// Checksum of an encoded keymap, checked by the device before applying it
+pub fn checksum(data: &[u8]) -> u32 {
+    crc32fast::hash(data)
+}
+
+impl Keymap {
+    /// Check that the keymap only refers to things that exist, the firmware
+    /// relies on this when converting it
+    pub fn validate(&self) -> Result<(), KeymapError> {
+        for (l, layer) in self.layers.iter().enumerate() {
+            for (r, row) in layer.iter().enumerate() {
+                for (c, action) in row.iter().enumerate() {
+                    self.check_action(action, (l as u8, r as u8, c as u8))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_action(&self, action: &KeyAction, pos: (u8, u8, u8)) -> Result<(), KeymapError> {
+        match action {
+            KeyAction::NoOp | KeyAction::Trans => Ok(()),
+            KeyAction::KeyCode(k) => self.check_basic(BasicAction::KeyCode(*k), pos),
+            KeyAction::MultipleKeyCodes(ks) => ks
+                .iter()
+                .try_for_each(|k| self.check_basic(BasicAction::KeyCode(*k), pos)),
+            KeyAction::Layer(l) | KeyAction::DefaultLayer(l) => {
+                self.check_basic(BasicAction::Layer(*l), pos)
+            }
+            KeyAction::HoldTap { hold, tap, .. } => {
+                self.check_basic(*hold, pos)?;
+                self.check_basic(*tap, pos)
+            }
+            KeyAction::Custom(c) => self.check_basic(BasicAction::Custom(*c), pos),
+        }
+    }
+
+    fn check_basic(
+        &self,
+        action: BasicAction,
+        (layer, row, col): (u8, u8, u8),
+    ) -> Result<(), KeymapError> {
+        match action {
+            BasicAction::KeyCode(k) if !is_valid_keycode(k) => {
+                Err(KeymapError::InvalidKeyCode { layer, row, col })
+            }
+            BasicAction::Layer(l) if l as usize >= LAYERS => {
+                Err(KeymapError::InvalidLayer { layer, row, col })
+            }
+            BasicAction::Custom(CustomAction::TypeUnicode(s))
+                if s as usize >= self.unicode.len() =>
+            {
+                Err(KeymapError::InvalidString { layer, row, col })
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -1,7 +1,17 @@
-#![cfg_attr(target_arch = "arm", no_std)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+pub mod backup;
 pub mod cmd;
+pub mod crash;
 pub mod device_to_host;
+pub mod fragment;
+pub mod handshake;
 pub mod hid;
 pub mod host_to_device;
+pub mod image;
+pub mod keymap;
+pub mod link_stats;
+pub mod settings;
 pub mod side;
+pub mod stored;
+pub mod update;
@@ -0,0 +1,55 @@
+//! How well the links to the host and between the halves are doing, counted
+//! since boot
+
+use core::hash::Hash;
+use serde::{Deserialize, Serialize};
+
+/// Round trip times are counted in this many buckets, each twice as wide as
+/// the one before, see [`rtt_bucket`]
+pub const RTT_BUCKETS: usize = 8;
+
+/// Round trips shorter than this go in the first bucket
+const FIRST_RTT_LIMIT_US: u32 = 250;
+
+/// Which bucket a round trip of `us` microseconds is counted in
+pub fn rtt_bucket(us: u64) -> usize {
+    (0..RTT_BUCKETS - 1)
+        .find(|&b| us < rtt_bucket_limit(b).unwrap() as u64)
+        .unwrap_or(RTT_BUCKETS - 1)
+}
+
+/// The round trips counted in `bucket` are shorter than this many
+/// microseconds, the last bucket has everything else
+pub fn rtt_bucket_limit(bucket: usize) -> Option<u32> {
+    (bucket < RTT_BUCKETS - 1).then(|| FIRST_RTT_LIMIT_US << bucket)
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LinkKind {
+    /// The usb serial port to the host
+    Usb,
+    /// The wire to the other half
+    Interboard,
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LinkStats {
+    /// Frames written out in full, not counting ones the write failed for
+    pub frames_sent: u32,
+    /// Frames that decoded and passed their checksum
+    pub frames_received: u32,
+    /// Frames that couldn't be decoded at all
+    pub decode_failures: u32,
+    /// Frames that decoded but failed their checksum
+    pub checksum_failures: u32,
+    pub nacks_received: u32,
+    /// Reliable messages sent again after going unacknowledged
+    pub retries: u32,
+    /// Reliable messages that didn't get through
+    pub failures: u32,
+    /// Round trip times of reliable messages acknowledged the first time they
+    /// were sent, see [`rtt_bucket`]
+    pub rtt: [u32; RTT_BUCKETS],
+}
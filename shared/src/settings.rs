@@ -0,0 +1,176 @@
+//! User settings that can be changed at runtime
+//!
+//! Both halves keep a copy in flash, a change made on one half is synced to
+//! the other.
+
+use core::hash::Hash;
+use core::ops::RangeInclusive;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Settings {
+    /// Brightness of the leds, out of 255
+    pub rgb_max_level: u8,
+    /// How long the leds take to fade into a new animation
+    pub rgb_fade_ms: u16,
+    /// How long the display stays on after the last key press
+    pub display_timeout_secs: u16,
+    /// How long the keys of a chord have to be pressed within
+    pub chord_timeout_ms: u16,
+    /// Number of scans (one per millisecond) a key has to settle for
+    pub debounce_period: u8,
+    pub trackpad_cpi: u16,
+    /// Trackpad movement needed for a single step of scrolling
+    pub scroll_period: u8,
+    /// Which half talks to the host when both are plugged in, 0 for the left
+    /// and 1 for the right
+    pub master_side: u8,
+}
+
+impl Settings {
+    pub const DEFAULT: Self = Self {
+        rgb_max_level: 180,
+        rgb_fade_ms: 3000,
+        display_timeout_secs: 30,
+        chord_timeout_ms: 30,
+        debounce_period: 40,
+        trackpad_cpi: 800,
+        scroll_period: 12,
+        master_side: 0,
+    };
+
+    /// Change a single setting, returning whether anything changed
+    pub fn apply(&mut self, setting: Setting) -> bool {
+        let before = *self;
+
+        match setting {
+            Setting::RgbMaxLevel(v) => self.rgb_max_level = v,
+            Setting::RgbFadeMs(v) => self.rgb_fade_ms = v,
+            Setting::DisplayTimeoutSecs(v) => self.display_timeout_secs = v,
+            Setting::ChordTimeoutMs(v) => self.chord_timeout_ms = v,
+            Setting::DebouncePeriod(v) => self.debounce_period = v,
+            Setting::TrackpadCpi(v) => self.trackpad_cpi = v,
+            Setting::ScrollPeriod(v) => self.scroll_period = v,
+            Setting::MasterSide(v) => self.master_side = v,
+        }
+
+        before != *self
+    }
+
+    /// Every setting along with its current value
+    pub fn iter(&self) -> impl Iterator<Item = Setting> {
+        [
+            Setting::RgbMaxLevel(self.rgb_max_level),
+            Setting::RgbFadeMs(self.rgb_fade_ms),
+            Setting::DisplayTimeoutSecs(self.display_timeout_secs),
+            Setting::ChordTimeoutMs(self.chord_timeout_ms),
+            Setting::DebouncePeriod(self.debounce_period),
+            Setting::TrackpadCpi(self.trackpad_cpi),
+            Setting::ScrollPeriod(self.scroll_period),
+            Setting::MasterSide(self.master_side),
+        ]
+        .into_iter()
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.iter().all(|s| s.is_valid())
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// A single setting along with its value
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Setting {
+    RgbMaxLevel(u8),
+    RgbFadeMs(u16),
+    DisplayTimeoutSecs(u16),
+    ChordTimeoutMs(u16),
+    DebouncePeriod(u8),
+    TrackpadCpi(u16),
+    ScrollPeriod(u8),
+    MasterSide(u8),
+}
+
+impl Setting {
+    /// Names used to refer to settings from the host
+    pub const NAMES: [&'static str; 8] = [
+        "rgb-max-level",
+        "rgb-fade-ms",
+        "display-timeout-secs",
+        "chord-timeout-ms",
+        "debounce-period",
+        "trackpad-cpi",
+        "scroll-period",
+        "master-side",
+    ];
+
+    /// Build a setting from its name, `None` if the name is unknown or the
+    /// value doesn't fit
+    pub fn from_name(name: &str, value: u16) -> Option<Self> {
+        let byte = u8::try_from(value).ok();
+
+        Some(match name {
+            "rgb-max-level" => Setting::RgbMaxLevel(byte?),
+            "rgb-fade-ms" => Setting::RgbFadeMs(value),
+            "display-timeout-secs" => Setting::DisplayTimeoutSecs(value),
+            "chord-timeout-ms" => Setting::ChordTimeoutMs(value),
+            "debounce-period" => Setting::DebouncePeriod(byte?),
+            "trackpad-cpi" => Setting::TrackpadCpi(value),
+            "scroll-period" => Setting::ScrollPeriod(byte?),
+            "master-side" => Setting::MasterSide(byte?),
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Setting::RgbMaxLevel(_) => "rgb-max-level",
+            Setting::RgbFadeMs(_) => "rgb-fade-ms",
+            Setting::DisplayTimeoutSecs(_) => "display-timeout-secs",
+            Setting::ChordTimeoutMs(_) => "chord-timeout-ms",
+            Setting::DebouncePeriod(_) => "debounce-period",
+            Setting::TrackpadCpi(_) => "trackpad-cpi",
+            Setting::ScrollPeriod(_) => "scroll-period",
+            Setting::MasterSide(_) => "master-side",
+        }
+    }
+
+    pub fn value(&self) -> u16 {
+        match *self {
+            Setting::RgbMaxLevel(v)
+            | Setting::DebouncePeriod(v)
+            | Setting::ScrollPeriod(v)
+            | Setting::MasterSide(v) => v as u16,
+            Setting::RgbFadeMs(v)
+            | Setting::DisplayTimeoutSecs(v)
+            | Setting::ChordTimeoutMs(v)
+            | Setting::TrackpadCpi(v) => v,
+        }
+    }
+
+    /// Values the firmware will accept for this setting
+    pub fn range(&self) -> RangeInclusive<u16> {
+        match self {
+            Setting::RgbMaxLevel(_) => 0..=255,
+            Setting::RgbFadeMs(_) => 0..=10_000,
+            Setting::DisplayTimeoutSecs(_) => 1..=3600,
+            Setting::ChordTimeoutMs(_) => 0..=200,
+            Setting::DebouncePeriod(_) => 1..=100,
+            Setting::TrackpadCpi(_) => 100..=4000,
+            // this divides the scroll distance
+            Setting::ScrollPeriod(_) => 1..=100,
+            Setting::MasterSide(_) => 0..=1,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.range().contains(&self.value())
+    }
+}
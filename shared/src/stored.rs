@@ -0,0 +1,18 @@
+//! Keys in the config database on each half
+
+use core::any::TypeId;
+
+/// Whether the entry under `key` can be deleted at boot, given the keys values
+/// are stored under now and the ones they used to be.
+///
+/// Entries used to be keyed by the `TypeId` of their value, which changes
+/// between compiler versions, so any key the size of one that isn't
+/// `registered` is left over from that. Anything else is left alone, it might
+/// have been written by newer firmware before a downgrade.
+pub fn is_garbage(key: &[u8], registered: &[&[u8]], retired: &[&[u8]]) -> bool {
+    if registered.contains(&key) {
+        return false;
+    }
+
+    retired.contains(&key) || key.len() == core::mem::size_of::<TypeId>()
+}
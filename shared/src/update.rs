@@ -0,0 +1,40 @@
+//! Firmware updates streamed over the serial link
+//!
+//! The host sends a raw application image (`objcopy -O binary`), which the
+//! firmware stages in flash and the bootloader installs on the next boot. The
+//! half plugged into the host can pass the image it received on to the other
+//! half, so that both are updated from one upload.
+
+use core::hash::Hash;
+use serde::{Deserialize, Serialize};
+
+use crate::device_to_host::RpcError;
+
+/// Images are uploaded in chunks so that each message fits in a frame
+pub const UPDATE_CHUNK_LEN: usize = 64;
+
+/// Checksum of an application image, checked by the device before staging it
+/// for the bootloader
+pub fn checksum(image: &[u8]) -> u32 {
+    crc32fast::hash(image)
+}
+
+/// How far passing an update on to the other half has got
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RelayStatus {
+    pub sent: u32,
+    pub len: u32,
+    pub state: RelayState,
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RelayState {
+    /// Nothing has been relayed since boot
+    Idle,
+    Sending,
+    /// The other half has staged the update and is rebooting to install it
+    Done,
+    Failed(RpcError),
+}
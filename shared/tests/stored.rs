@@ -0,0 +1,36 @@
+use shared::stored::is_garbage;
+
+const REGISTERED: &[&[u8]] = &[b"metrics", b"keymap", b"settings"];
+const RETIRED: &[&[u8]] = &[b"old"];
+
+#[test]
+fn registered_keys_are_kept() {
+    for key in REGISTERED {
+        assert!(!is_garbage(key, REGISTERED, RETIRED));
+    }
+}
+
+#[test]
+fn type_id_keys_are_removed() {
+    let key = [0x5a; core::mem::size_of::<core::any::TypeId>()];
+
+    assert!(is_garbage(&key, REGISTERED, RETIRED));
+}
+
+#[test]
+fn a_registered_key_the_size_of_a_type_id_is_kept() {
+    let key: &[u8] = b"sixteen byte key";
+    assert_eq!(key.len(), core::mem::size_of::<core::any::TypeId>());
+
+    assert!(!is_garbage(key, &[key], RETIRED));
+}
+
+#[test]
+fn retired_keys_are_removed() {
+    assert!(is_garbage(b"old", REGISTERED, RETIRED));
+}
+
+#[test]
+fn unknown_keys_are_kept() {
+    assert!(!is_garbage(b"from newer firmware", REGISTERED, RETIRED));
+}
@@ -0,0 +1,30 @@
+//! Bytes arriving over the link between the halves
+//!
+//! The firmware's `DeviceToDevice` holds animation state which only builds for
+//! the keyboard, so this decodes what it forwards from the other half to the
+//! host instead. The decoder doesn't care what it's decoding past postcard.
+
+#![no_main]
+
+use dilemma_transmissions::Window;
+use dilemma_transmissions_fuzz::decode_stream;
+use libfuzzer_sys::fuzz_target;
+use shared::{
+    device_to_host::{DeviceToHost, DeviceToHostMsg, Reply},
+    side::KeyboardSide,
+};
+
+fuzz_target!(|data: &[u8]| {
+    decode_stream(
+        data,
+        // the window the firmware uses between the halves
+        Window::new(4),
+        DeviceToHost {
+            from_side: KeyboardSide::Right,
+            msg: DeviceToHostMsg::Response {
+                id: 1,
+                result: Ok(Reply::Rebooting),
+            },
+        },
+    );
+});
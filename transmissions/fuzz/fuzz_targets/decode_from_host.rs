@@ -0,0 +1,25 @@
+//! Bytes arriving over usb from the host
+
+#![no_main]
+
+use dilemma_transmissions::Window;
+use dilemma_transmissions_fuzz::decode_stream;
+use libfuzzer_sys::fuzz_target;
+use shared::{
+    handshake::PROTOCOL_VERSION,
+    host_to_device::{HostToDevice, HostToDeviceMsg},
+};
+
+fuzz_target!(|data: &[u8]| {
+    decode_stream(
+        data,
+        Window::STOP_AND_WAIT,
+        HostToDevice {
+            id: 1,
+            target_side: None,
+            msg: HostToDeviceMsg::Hello {
+                protocol_version: PROTOCOL_VERSION,
+            },
+        },
+    );
+});
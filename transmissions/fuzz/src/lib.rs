@@ -0,0 +1,92 @@
+//! Checks shared by the fuzz targets
+
+use core::{fmt::Debug, hash::Hash};
+
+use dilemma_transmissions::{Decoder, Frame, Window, BUF_SIZE};
+use serde::{de::DeserializeOwned, Serialize};
+use shared::cmd::{CmdOrAck, Command, CommandSeq};
+
+fn encode<T: Serialize>(frame: &CmdOrAck<T>) -> Vec<u8> {
+    let mut buf = [0u8; BUF_SIZE];
+    postcard::to_slice_cobs(frame, &mut buf)
+        .expect("the known good frames fit")
+        .to_vec()
+}
+
+/// Frame `msg` the way the eventer sends it
+fn frame<T: Serialize + Hash>(window: Window, msg: T, id: u8) -> Vec<u8> {
+    let cmd = if window == Window::STOP_AND_WAIT {
+        Command::new_reliable(msg, id)
+    } else {
+        Command::new_sequenced(msg, CommandSeq::new().with_id(id).with_reliable(true))
+    };
+
+    encode(&CmdOrAck::Cmd(cmd))
+}
+
+/// Feed `data` to a decoder the way the eventer does, in reads of a size
+/// picked by the first byte, then check that it still decodes `good`
+pub fn decode_stream<T>(data: &[u8], window: Window, good: T)
+where
+    T: DeserializeOwned + Serialize + Hash + Clone + PartialEq + Debug,
+{
+    let Some((&read_len, data)) = data.split_first() else {
+        return;
+    };
+    let read_len = read_len as usize % BUF_SIZE + 1;
+
+    let mut decoder = Decoder::<T>::new(window);
+    let mut replies = 0;
+
+    for read in data.chunks(read_len) {
+        let mut window = read;
+        let mut steps = 0;
+
+        while !window.is_empty() {
+            let (frame, remaining) = decoder.feed(window);
+            window = remaining;
+
+            // a full buffer can leave the window as it was, but only once
+            steps += 1;
+            assert!(steps <= 2 * read.len(), "decoding stalled on {read:?}");
+
+            match frame {
+                Some(Frame::Corrupt(_) | Frame::Sync { .. } | Frame::Command { ack: Some(_), .. }) => {
+                    replies += 1
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // every nack asks for a retry, so each frame end may cause at most one
+    let frame_ends = data.iter().filter(|&&b| b == 0).count();
+    assert!(
+        replies <= frame_ends,
+        "{replies} replies to {frame_ends} frames"
+    );
+
+    // after a frame end whatever came before is forgotten, so a sync and the
+    // command after it get through again
+    let mut recovery = vec![0];
+    recovery.extend(encode(&CmdOrAck::<T>::sync(1)));
+    recovery.extend(frame(window, good.clone(), 1));
+
+    let mut window = &recovery[..];
+    let mut frames = Vec::new();
+    while !window.is_empty() {
+        let (frame, remaining) = decoder.feed(window);
+        window = remaining;
+        frames.extend(frame);
+    }
+
+    let last = frames.pop();
+    assert!(
+        matches!(&last, Some(Frame::Command { msg: Some(msg), ack: Some(_) }) if *msg == good),
+        "{last:?}"
+    );
+    assert!(
+        matches!(frames.last(), Some(Frame::Sync { .. })),
+        "{frames:?}"
+    );
+}
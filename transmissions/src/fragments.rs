@@ -0,0 +1,108 @@
+//! Sending messages too big for a frame as a run of [`Fragment`]s
+//!
+//! The fragments of a message have to reach the [`Reassembler`] in order: send
+//! each one once the one before has been delivered, or queue them by
+//! [`crate::priority`], which keeps messages of the same priority in order. The
+//! reassembler puts them back together, throwing away a message that has a
+//! fragment missing or out of order, or that stops arriving part way through.
+
+use embassy_time::{Duration, Instant};
+use shared::fragment::{Fragment, FRAGMENT_LEN};
+
+/// Split `payload` into the fragments of transfer `transfer`, it has to fit in
+/// `u16::MAX` of them
+pub fn fragments(transfer: u8, payload: &[u8]) -> impl Iterator<Item = Fragment> + '_ {
+    // an empty message still needs a fragment to say it's there
+    let count = payload.len().div_ceil(FRAGMENT_LEN).max(1);
+
+    payload
+        .chunks(FRAGMENT_LEN)
+        .chain(payload.is_empty().then_some(&[][..]))
+        .enumerate()
+        .map(move |(index, data)| Fragment {
+            transfer,
+            index: index as u16,
+            count: count as u16,
+            data: heapless::Vec::from_slice(data).unwrap(),
+        })
+}
+
+struct Partial {
+    transfer: u8,
+    next: u16,
+    count: u16,
+    /// Given up on if the next fragment hasn't arrived by then
+    deadline: Instant,
+}
+
+/// Puts messages of up to `N` bytes back together from their fragments
+pub struct Reassembler<const N: usize> {
+    /// How long to wait for each fragment after the one before
+    timeout: Duration,
+    partial: Option<Partial>,
+    data: heapless::Vec<u8, N>,
+}
+
+impl<const N: usize> Reassembler<N> {
+    pub const fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            partial: None,
+            data: heapless::Vec::new(),
+        }
+    }
+
+    /// Add the next fragment, returning the message once it's complete
+    pub fn push(&mut self, fragment: Fragment) -> Option<&[u8]> {
+        let now = Instant::now();
+
+        if self.partial.as_ref().is_some_and(|p| now >= p.deadline) {
+            self.partial = None;
+        }
+
+        if fragment.index >= fragment.count {
+            return None;
+        }
+
+        // a repeat of one that's already been added
+        let repeat = self
+            .partial
+            .as_ref()
+            .is_some_and(|p| p.transfer == fragment.transfer && fragment.index < p.next);
+        if repeat {
+            return None;
+        }
+
+        // a new message, whatever is left of the one before won't be finished
+        if fragment.index == 0 {
+            self.data.clear();
+            self.partial = Some(Partial {
+                transfer: fragment.transfer,
+                next: 0,
+                count: fragment.count,
+                deadline: now,
+            });
+        }
+
+        // the start of its message was missed or given up on
+        let p = self.partial.as_mut()?;
+        if fragment.transfer != p.transfer || fragment.count != p.count {
+            return None;
+        }
+
+        if fragment.index > p.next || self.data.extend_from_slice(&fragment.data).is_err() {
+            self.partial = None;
+            return None;
+        }
+
+        p.next += 1;
+        p.deadline = now + self.timeout;
+
+        if p.next < p.count {
+            return None;
+        }
+
+        self.partial = None;
+        Some(&self.data)
+    }
+}
@@ -0,0 +1,875 @@
+//! Sending messages over a byte stream, such as the usb serial port or the
+//! wire to the other half
+//!
+//! Messages are postcard encoded and cobs framed. Reliable messages are sent
+//! again until the other end acknowledges them, and a checksum over each one
+//! lets the receiver ask for a corrupted message to be sent again. Links with a
+//! [`Window`] wider than one keep several reliable messages in flight at once,
+//! going back to the oldest unacknowledged one when something goes missing.
+//! A message that still isn't acknowledged after [`MAX_ATTEMPTS`] is given up
+//! on and the link is treated as down, failing messages straight away instead
+//! of letting them queue up until the other end comes back. Messages waiting
+//! to be sent are ordered with [`priority`], and messages too big for a frame
+//! can be split up with [`fragments`].
+//! None of this depends on the hardware, so it can be run on the host against
+//! in-memory pipes (see `tests/`).
+
+#![no_std]
+#![allow(async_fn_in_trait)]
+
+use core::future::{pending, Future};
+use core::hash::Hash;
+use core::marker::PhantomData;
+
+use embassy_futures::select::{select, select3, Either, Either3};
+use embassy_sync::{
+    blocking_mutex::raw::{NoopRawMutex, RawMutex},
+    channel::Channel,
+    signal::Signal,
+};
+use embassy_time::{Duration, Instant, Timer};
+use heapless::Deque;
+use portable_atomic::{AtomicU32, Ordering};
+use postcard::accumulator::{CobsAccumulator, FeedResult};
+use serde::{de::DeserializeOwned, Serialize};
+use shared::cmd::{CmdOrAck, Command, CommandSeq};
+use shared::link_stats::{rtt_bucket, LinkStats, RTT_BUCKETS};
+
+pub mod fragments;
+pub mod priority;
+
+pub struct TransmittedMessage<T> {
+    pub msg: T,
+    pub timeout: Option<Duration>,
+    /// Told whether the message got through
+    pub report: Option<&'static dyn Report>,
+}
+
+impl<T> TransmittedMessage<T> {
+    /// Have `report` told whether the message got through. Unreliable messages
+    /// count as delivered once they've been sent
+    pub fn reporting_to(self, report: &'static dyn Report) -> Self {
+        Self {
+            report: Some(report),
+            ..self
+        }
+    }
+}
+
+impl<T: core::fmt::Debug> core::fmt::Debug for TransmittedMessage<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TransmittedMessage")
+            .field("msg", &self.msg)
+            .field("timeout", &self.timeout)
+            .field("report", &self.report.is_some())
+            .finish()
+    }
+}
+
+pub fn low_latency_msg<T>(msg: T) -> TransmittedMessage<T> {
+    TransmittedMessage {
+        msg,
+        timeout: Some(Duration::from_micros(500)),
+        report: None,
+    }
+}
+
+pub fn reliable_msg<T>(msg: T) -> TransmittedMessage<T> {
+    TransmittedMessage {
+        msg,
+        timeout: Some(Duration::from_millis(2)),
+        report: None,
+    }
+}
+
+pub fn unreliable_msg<T>(msg: T) -> TransmittedMessage<T> {
+    TransmittedMessage {
+        msg,
+        timeout: None,
+        report: None,
+    }
+}
+
+/// Why a reliable message didn't get through
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Undelivered {
+    /// Timed out [`MAX_ATTEMPTS`] times waiting to be acknowledged, it might
+    /// still have arrived if only the acks went missing
+    GaveUp,
+    /// Never sent, as nothing has been getting through to the other end
+    LinkDown,
+}
+
+pub type Delivery = Result<(), Undelivered>;
+
+/// Somewhere to be told what happened to a message
+pub trait Report: Sync {
+    fn report(&self, delivery: Delivery);
+}
+
+impl<M: RawMutex + Sync> Report for Signal<M, Delivery> {
+    fn report(&self, delivery: Delivery) {
+        self.signal(delivery);
+    }
+}
+
+/// Running totals for a link, kept up to date by its eventer. See
+/// [`LinkStats`] for what each one counts
+pub struct Counters {
+    pub frames_sent: AtomicU32,
+    pub frames_received: AtomicU32,
+    pub decode_failures: AtomicU32,
+    pub checksum_failures: AtomicU32,
+    pub nacks_received: AtomicU32,
+    pub retries: AtomicU32,
+    pub failures: AtomicU32,
+    pub rtt: [AtomicU32; RTT_BUCKETS],
+}
+
+impl Counters {
+    pub const fn new() -> Self {
+        Self {
+            frames_sent: AtomicU32::new(0),
+            frames_received: AtomicU32::new(0),
+            decode_failures: AtomicU32::new(0),
+            checksum_failures: AtomicU32::new(0),
+            nacks_received: AtomicU32::new(0),
+            retries: AtomicU32::new(0),
+            failures: AtomicU32::new(0),
+            rtt: [const { AtomicU32::new(0) }; RTT_BUCKETS],
+        }
+    }
+
+    pub fn snapshot(&self) -> LinkStats {
+        let load = |c: &AtomicU32| c.load(Ordering::Relaxed);
+
+        LinkStats {
+            frames_sent: load(&self.frames_sent),
+            frames_received: load(&self.frames_received),
+            decode_failures: load(&self.decode_failures),
+            checksum_failures: load(&self.checksum_failures),
+            nacks_received: load(&self.nacks_received),
+            retries: load(&self.retries),
+            failures: load(&self.failures),
+            rtt: core::array::from_fn(|b| load(&self.rtt[b])),
+        }
+    }
+
+    fn count(counter: &AtomicU32) {
+        counter.add(1, Ordering::Relaxed);
+    }
+}
+
+impl Default for Counters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Wraps the receiver's reads, which can wait for any amount of time without
+/// that meaning anything is stuck
+pub trait Idle {
+    async fn idle<F: Future>(&self, fut: F) -> F::Output;
+}
+
+/// Nobody is watching
+impl Idle for () {
+    async fn idle<F: Future>(&self, fut: F) -> F::Output {
+        fut.await
+    }
+}
+
+/// The most bytes a frame can take up on the wire, and the size of each read
+pub const BUF_SIZE: usize = 128;
+
+/// Ids are seven bits and wrap around
+const ID_MASK: u8 = 0b1111111;
+
+/// How long to wait for the other end to answer a [`CmdOrAck::Sync`]
+const SYNC_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How many times a reliable message can time out waiting for its ack before
+/// it's given up on, and how many syncs go unanswered before the link is taken
+/// to be down
+pub const MAX_ATTEMPTS: u8 = 8;
+
+/// Each time a message is sent again it waits twice as long for its ack, up to
+/// this
+const MAX_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How often the other end is synced with while the link is down, to find out
+/// when it comes back
+const PROBE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The widest [`Window`]
+pub const MAX_WINDOW: usize = 8;
+
+/// How many reliable messages can be waiting for an ack at once
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Window(u8);
+
+impl Window {
+    /// One reliable message in flight at a time, with acks that don't say
+    /// which message they're for. This is all the host understands
+    pub const STOP_AND_WAIT: Self = Self(1);
+
+    /// Up to `n` reliable messages in flight at a time, acknowledged by id and
+    /// with the ids covered by the checksums. Both ends have to use the same
+    /// window, and a window of one is [`Window::STOP_AND_WAIT`]
+    pub const fn new(n: u8) -> Self {
+        assert!(n >= 1 && n as usize <= MAX_WINDOW);
+        Self(n)
+    }
+
+    fn is_stop_and_wait(self) -> bool {
+        self == Self::STOP_AND_WAIT
+    }
+
+    fn len(self) -> usize {
+        self.0 as usize
+    }
+
+    fn command<T: Hash>(self, msg: T, id: u8, reliable: bool) -> Command<T> {
+        match (self.is_stop_and_wait(), reliable) {
+            (true, true) => Command::new_reliable(msg, id),
+            (true, false) => Command::new_unreliable(msg, id),
+            (false, _) => {
+                Command::new_sequenced(msg, CommandSeq::new().with_id(id).with_reliable(reliable))
+            }
+        }
+    }
+
+    fn validate<T: Hash>(self, cmd: &Command<T>) -> bool {
+        if self.is_stop_and_wait() {
+            cmd.validate()
+        } else {
+            cmd.validate_sequenced()
+        }
+    }
+}
+
+/// What a reliable command is acknowledged with
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Ack {
+    /// The only reliable command in flight, see [`Window::STOP_AND_WAIT`]
+    Last,
+    /// Every reliable command up to and including the one with this id
+    Upto(u8),
+}
+
+impl Ack {
+    fn frame<T>(self) -> CmdOrAck<T> {
+        match self {
+            Ack::Last => CmdOrAck::Ack,
+            Ack::Upto(id) => CmdOrAck::ack_upto(id),
+        }
+    }
+}
+
+/// A frame pulled out of the received bytes
+#[derive(Debug, PartialEq, Eq)]
+pub enum Frame<T> {
+    /// A command that passed its checksum, `msg` is `None` if it was already
+    /// delivered or arrived after one that went missing. Reliable commands are
+    /// answered with `ack`, whatever happened to them
+    Command {
+        msg: Option<T>,
+        ack: Option<Ack>,
+    },
+    /// The other end is starting over, answered with `ack`
+    Sync {
+        ack: Ack,
+    },
+    Ack(Ack),
+    Nack,
+    /// Couldn't be used, the sender should be asked to send it again
+    Corrupt(Corruption),
+}
+
+/// What was wrong with a [`Frame::Corrupt`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Corruption {
+    /// Not a frame at all
+    Undecodable,
+    /// Decoded, but failed its checksum
+    Checksum,
+}
+
+/// Splits the received bytes into frames and decodes them, keeping track of
+/// which reliable commands have been delivered
+///
+/// Frames longer than [`BUF_SIZE`] are thrown away, whatever is left of one
+/// after that usually decodes as a [`Frame::Corrupt`].
+pub struct Decoder<T> {
+    accumulator: CobsAccumulator<BUF_SIZE>,
+    window: Window,
+    /// Id of the next reliable command to deliver, `None` until the first one
+    next_id: Option<u8>,
+    _received: PhantomData<T>,
+}
+
+impl<T> Decoder<T> {
+    pub fn new(window: Window) -> Self {
+        Self {
+            accumulator: CobsAccumulator::new(),
+            window,
+            next_id: None,
+            _received: PhantomData,
+        }
+    }
+
+    /// Whether the reliable command with `id` should be delivered
+    fn receive(&mut self, id: u8) -> bool {
+        let deliver = match self.next_id {
+            None => true,
+            // the host starts from wherever it likes, so only a repeat of the
+            // last command is dropped
+            Some(next) if self.window.is_stop_and_wait() => id != next.wrapping_sub(1) & ID_MASK,
+            // anything else is either a repeat or arrived after one that went
+            // missing, which the sender will go back and send again
+            Some(next) => id == next,
+        };
+
+        if deliver {
+            self.next_id = Some(id.wrapping_add(1) & ID_MASK);
+        }
+
+        deliver
+    }
+
+    fn ack(&self) -> Ack {
+        match self.next_id {
+            Some(next) if !self.window.is_stop_and_wait() => {
+                Ack::Upto(next.wrapping_sub(1) & ID_MASK)
+            }
+            _ => Ack::Last,
+        }
+    }
+}
+
+impl<T: DeserializeOwned + Hash> Decoder<T> {
+    /// Feed in received bytes, returning the frame they finished, if any, and
+    /// the bytes left over after it
+    pub fn feed<'a>(&mut self, window: &'a [u8]) -> (Option<Frame<T>>, &'a [u8]) {
+        match self.accumulator.feed::<CmdOrAck<T>>(window) {
+            FeedResult::Consumed => (None, &[]),
+            FeedResult::OverFull(remaining) => (None, remaining),
+            FeedResult::DeserError(remaining) => {
+                (Some(Frame::Corrupt(Corruption::Undecodable)), remaining)
+            }
+            FeedResult::Success { data, remaining } => {
+                let frame = match data {
+                    CmdOrAck::Cmd(c) if self.window.validate(&c) => {
+                        if c.command_seq.reliable() {
+                            let deliver = self.receive(c.command_seq.id());
+
+                            Frame::Command {
+                                msg: deliver.then_some(c.cmd),
+                                ack: Some(self.ack()),
+                            }
+                        } else {
+                            Frame::Command {
+                                msg: Some(c.cmd),
+                                ack: None,
+                            }
+                        }
+                    }
+                    CmdOrAck::Cmd(_) => Frame::Corrupt(Corruption::Checksum),
+                    CmdOrAck::Ack if self.window.is_stop_and_wait() => Frame::Ack(Ack::Last),
+                    // nothing checks these, so on a link that doesn't use them
+                    // it's more likely a corrupted nack than anything else
+                    CmdOrAck::Ack => Frame::Corrupt(Corruption::Checksum),
+                    CmdOrAck::Nack => Frame::Nack,
+                    ref data if !data.validate_id() => Frame::Corrupt(Corruption::Checksum),
+                    CmdOrAck::AckUpto { id, .. } => Frame::Ack(Ack::Upto(id)),
+                    CmdOrAck::Sync { id, .. } => {
+                        self.next_id = Some(id);
+
+                        Frame::Sync { ack: self.ack() }
+                    }
+                };
+
+                (Some(frame), remaining)
+            }
+        }
+    }
+}
+
+/// A reliable message waiting to be acknowledged
+struct InFlight<T> {
+    id: u8,
+    msg: T,
+    timeout: Duration,
+    deadline: Instant,
+    /// How many times it's been sent, not counting resends after a nack
+    attempts: u8,
+    /// When it was sent, if it's only been sent once, so its ack gives the
+    /// round trip time
+    sent: Option<Instant>,
+    report: Option<&'static dyn Report>,
+}
+
+enum SenderEvent<T> {
+    Send(TransmittedMessage<T>),
+    /// An ack, or `None` for a nack
+    Acked(Option<Ack>),
+    TimedOut,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LinkState {
+    /// Waiting for the other end to answer a sync before sending anything,
+    /// sending it again at `deadline`
+    Syncing {
+        attempts: u8,
+        deadline: Instant,
+    },
+    Up,
+    /// Nothing has been getting through, so messages are failed instead of
+    /// being left to back up. Links with a window sync with the other end at
+    /// `probe` to find out when it's back, without one the next reliable
+    /// message is sent once to find out
+    Down {
+        probe: Instant,
+    },
+}
+
+fn report(report: Option<&'static dyn Report>, delivery: Delivery) {
+    if let Some(report) = report {
+        report.report(delivery);
+    }
+}
+
+struct EventSender<'e, T> {
+    mix_chan: &'e Channel<NoopRawMutex, CmdOrAck<T>, 16>,
+    ack_signal: &'e Signal<NoopRawMutex, Option<Ack>>,
+    counters: &'e Counters,
+    window: Window,
+    in_flight: Deque<InFlight<T>, MAX_WINDOW>,
+    next_id: u8,
+    state: LinkState,
+    /// Whether a sync was sent to get the other end back in step, and hasn't
+    /// been answered yet
+    resyncing: bool,
+}
+
+impl<'e, T: Hash + Clone> EventSender<'e, T> {
+    async fn task<FnRx, FnRxFut>(&mut self, fn_rx: FnRx)
+    where
+        FnRx: Fn() -> FnRxFut,
+        FnRxFut: Future<Output = TransmittedMessage<T>>,
+    {
+        loop {
+            let deadline = match self.state {
+                LinkState::Syncing { deadline, .. } => Some(deadline),
+                LinkState::Down { probe } if !self.window.is_stop_and_wait() => Some(probe),
+                _ => self.in_flight.front().map(|m| m.deadline),
+            };
+            let timed_out = async {
+                match deadline {
+                    Some(deadline) => Timer::at(deadline).await,
+                    None => pending().await,
+                }
+            };
+
+            // nothing is sent before the other end knows which id comes next
+            let syncing = matches!(self.state, LinkState::Syncing { .. });
+
+            let event = if !syncing && self.in_flight.len() < self.window.len() {
+                match select3(fn_rx(), self.ack_signal.wait(), timed_out).await {
+                    Either3::First(msg) => SenderEvent::Send(msg),
+                    Either3::Second(ack) => SenderEvent::Acked(ack),
+                    Either3::Third(()) => SenderEvent::TimedOut,
+                }
+            } else {
+                match select(self.ack_signal.wait(), timed_out).await {
+                    Either::First(ack) => SenderEvent::Acked(ack),
+                    Either::Second(()) => SenderEvent::TimedOut,
+                }
+            };
+
+            match event {
+                SenderEvent::Send(TransmittedMessage {
+                    msg,
+                    timeout: Some(timeout),
+                    report,
+                }) => self.send_reliable(msg, timeout, report).await,
+                SenderEvent::Send(TransmittedMessage {
+                    msg,
+                    timeout: None,
+                    report: r,
+                }) => {
+                    self.send_unreliable(msg).await;
+                    report(r, Ok(()));
+                }
+                SenderEvent::Acked(Some(ack)) => self.acked(ack).await,
+                // the receiver can't say which message it couldn't decode, so
+                // only the oldest goes again straight away. Resending the lot
+                // for every nack would only cause more of them
+                SenderEvent::Acked(None) if self.state == LinkState::Up => self.resend(1).await,
+                SenderEvent::Acked(None) => {}
+                SenderEvent::TimedOut => self.timed_out().await,
+            }
+        }
+    }
+
+    /// The ack to a sync, once the other end is expecting the next id
+    fn synced(&self) -> Ack {
+        Ack::Upto(self.next_id.wrapping_sub(1) & ID_MASK)
+    }
+
+    async fn timed_out(&mut self) {
+        let now = Instant::now();
+
+        match self.state {
+            LinkState::Syncing { attempts, .. } if attempts >= MAX_ATTEMPTS => {
+                self.state = LinkState::Down { probe: now };
+            }
+            // tell the other end which id comes next, as it might remember
+            // where we were before we restarted or gave up
+            LinkState::Syncing { attempts, .. } => {
+                self.mix_chan.send(CmdOrAck::sync(self.next_id)).await;
+                self.state = LinkState::Syncing {
+                    attempts: attempts + 1,
+                    deadline: now + SYNC_INTERVAL,
+                };
+            }
+            LinkState::Down { .. } if !self.window.is_stop_and_wait() => {
+                self.mix_chan.send(CmdOrAck::sync(self.next_id)).await;
+                self.state = LinkState::Down {
+                    probe: now + PROBE_INTERVAL,
+                };
+            }
+            _ => {
+                self.resyncing = false;
+                self.retry().await;
+            }
+        }
+    }
+
+    async fn send_unreliable(&mut self, msg: T) {
+        let cmd = self.window.command(msg, self.next_id, false);
+        self.mix_chan.send(CmdOrAck::Cmd(cmd)).await;
+    }
+
+    async fn send_reliable(
+        &mut self,
+        msg: T,
+        timeout: Duration,
+        report: Option<&'static dyn Report>,
+    ) {
+        let attempts = match self.state {
+            LinkState::Down { .. } if !self.window.is_stop_and_wait() => {
+                self.failed(report, Undelivered::LinkDown);
+                return;
+            }
+            // the one attempt finds out whether the other end is back
+            LinkState::Down { .. } => MAX_ATTEMPTS,
+            _ => 1,
+        };
+
+        let id = self.next_id;
+        self.next_id = id.wrapping_add(1) & ID_MASK;
+
+        // nothing was waiting for an ack, so any that arrived are repeats
+        if self.in_flight.is_empty() {
+            self.ack_signal.reset();
+        }
+
+        let cmd = self.window.command(msg.clone(), id, true);
+        self.mix_chan.send(CmdOrAck::Cmd(cmd)).await;
+
+        // the window has room, the caller checked
+        let now = Instant::now();
+        let _ = self.in_flight.push_back(InFlight {
+            id,
+            msg,
+            timeout,
+            deadline: now + timeout,
+            attempts,
+            sent: Some(now),
+            report,
+        });
+    }
+
+    async fn acked(&mut self, ack: Ack) {
+        if self.state != LinkState::Up && !self.window.is_stop_and_wait() {
+            if ack == self.synced() {
+                self.state = LinkState::Up;
+            }
+            return;
+        }
+
+        let Some(oldest) = self.in_flight.front().map(|m| m.id) else {
+            return;
+        };
+
+        let upto = match ack {
+            Ack::Last => oldest,
+            Ack::Upto(id) => id,
+        };
+
+        match upto.wrapping_sub(oldest) & ID_MASK {
+            n if (n as usize) < self.in_flight.len() => {
+                for _ in 0..=n {
+                    if let Some(m) = self.in_flight.pop_front() {
+                        if let Some(sent) = m.sent {
+                            let bucket = rtt_bucket(sent.elapsed().as_micros());
+                            Counters::count(&self.counters.rtt[bucket]);
+                        }
+                        report(m.report, Ok(()));
+                    }
+                }
+                self.resyncing = false;
+                self.state = LinkState::Up;
+            }
+            // the oldest went missing, it'll be sent again when it times out
+            ID_MASK => self.resyncing = false,
+            // acks arrive in order, so the other end is waiting for a message
+            // that's been acked already. Only a corrupted frame passing for an
+            // ack does that, so skip it ahead to what's still in flight
+            _ if !self.resyncing => {
+                self.resyncing = true;
+                self.mix_chan.send(CmdOrAck::sync(oldest)).await;
+                self.resend(MAX_WINDOW).await;
+            }
+            // acks sent before the sync arrived
+            _ => {}
+        }
+    }
+
+    /// Back off and send everything waiting for an ack again, giving up on all
+    /// of it if the oldest has timed out too many times already. Only timeouts
+    /// count, a nack means the other end is still there
+    async fn retry(&mut self) {
+        if self
+            .in_flight
+            .front()
+            .is_some_and(|m| m.attempts >= MAX_ATTEMPTS)
+        {
+            self.give_up();
+            return;
+        }
+
+        for m in self.in_flight.iter_mut() {
+            if m.timeout < MAX_TIMEOUT {
+                m.timeout = (m.timeout * 2).min(MAX_TIMEOUT);
+            }
+            m.attempts += 1;
+        }
+
+        self.resend(MAX_WINDOW).await;
+    }
+
+    /// Send the oldest `n` messages waiting for an ack again
+    async fn resend(&mut self, n: usize) {
+        let now = Instant::now();
+
+        for m in self.in_flight.iter_mut().take(n) {
+            m.deadline = now + m.timeout;
+            m.sent = None;
+            Counters::count(&self.counters.retries);
+
+            let cmd = self.window.command(m.msg.clone(), m.id, true);
+            self.mix_chan.send(CmdOrAck::Cmd(cmd)).await;
+        }
+    }
+
+    /// Fail everything in flight, on a link with a window whatever comes after
+    /// the oldest can't be delivered without it anyway
+    fn give_up(&mut self) {
+        while let Some(m) = self.in_flight.pop_front() {
+            self.failed(m.report, Undelivered::GaveUp);
+        }
+
+        self.resyncing = false;
+        self.state = LinkState::Down {
+            probe: Instant::now(),
+        };
+    }
+
+    fn failed(&self, r: Option<&'static dyn Report>, why: Undelivered) {
+        Counters::count(&self.counters.failures);
+        report(r, Err(why));
+    }
+}
+
+struct EventOutProcessor<'e, Sent, TX> {
+    tx: TX,
+    mix_chan: &'e Channel<NoopRawMutex, CmdOrAck<Sent>, 16>,
+    counters: &'e Counters,
+}
+
+struct EventInProcessor<'e, Sent, RX, FnTx, W> {
+    rx: RX,
+    out_cb: FnTx,
+    watched: W,
+    window: Window,
+    mix_chan: &'e Channel<NoopRawMutex, CmdOrAck<Sent>, 16>,
+    ack_signal: &'e Signal<NoopRawMutex, Option<Ack>>,
+    counters: &'e Counters,
+}
+
+impl<'e, Sent, RX, FnTx, W> EventInProcessor<'e, Sent, RX, FnTx, W>
+where
+    RX: embedded_io_async::Read,
+    W: Idle,
+{
+    async fn receive_task_inner<Received, FnTxFut>(
+        &mut self,
+    ) -> Result<(), <RX as embedded_io_async::ErrorType>::Error>
+    where
+        Received: DeserializeOwned + Hash + Clone,
+        FnTxFut: Future,
+        FnTx: Fn(Received) -> FnTxFut,
+    {
+        let mut decoder = Decoder::<Received>::new(self.window);
+
+        loop {
+            let mut buf = [0u8; BUF_SIZE];
+            // waiting for the other end to send something isn't a stall, but
+            // failing to hand on what it sent is
+            let n = self.watched.idle(self.rx.read(&mut buf)).await?;
+            let mut window = &buf[..n];
+
+            while !window.is_empty() {
+                let (frame, remaining) = decoder.feed(window);
+                window = remaining;
+
+                match &frame {
+                    None => {}
+                    Some(Frame::Corrupt(Corruption::Undecodable)) => {
+                        Counters::count(&self.counters.decode_failures)
+                    }
+                    Some(Frame::Corrupt(Corruption::Checksum)) => {
+                        Counters::count(&self.counters.checksum_failures)
+                    }
+                    Some(_) => Counters::count(&self.counters.frames_received),
+                }
+
+                match frame {
+                    None => {}
+                    Some(Frame::Corrupt(_)) => {
+                        self.mix_chan.send(CmdOrAck::Nack).await;
+                    }
+                    Some(Frame::Command { msg, ack }) => {
+                        if let Some(ack) = ack {
+                            self.mix_chan.send(ack.frame()).await;
+                        }
+                        if let Some(msg) = msg {
+                            (self.out_cb)(msg).await;
+                        }
+                    }
+                    Some(Frame::Sync { ack }) => {
+                        self.mix_chan.send(ack.frame()).await;
+                    }
+                    Some(Frame::Ack(ack)) => {
+                        self.ack_signal.signal(Some(ack));
+                    }
+                    Some(Frame::Nack) => {
+                        Counters::count(&self.counters.nacks_received);
+                        self.ack_signal.signal(None);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn task<Received, FnTxFut>(&mut self)
+    where
+        Received: DeserializeOwned + Hash + Clone,
+        FnTxFut: Future,
+        FnTx: Fn(Received) -> FnTxFut,
+    {
+        loop {
+            let _r = self.receive_task_inner().await;
+            // log::debug!("Restarting cobs receiver for reason: {}", _r);
+        }
+    }
+}
+
+impl<'e, T, TX> EventOutProcessor<'e, T, TX>
+where
+    T: Serialize,
+    TX: embedded_io_async::Write,
+{
+    async fn task(&mut self) {
+        loop {
+            let val = self.mix_chan.receive().await;
+
+            let mut buf = [0u8; BUF_SIZE];
+            if let Ok(buf) = postcard::to_slice_cobs(&val, &mut buf) {
+                let _r = self.tx.write_all(buf).await;
+                // a frame that couldn't be written didn't go anywhere
+                if _r.is_ok() {
+                    Counters::count(&self.counters.frames_sent);
+                }
+                // log::debug!("Transmitted {:?} as {:?}, r: {:?}", val, buf, _r);
+            }
+        }
+    }
+}
+
+pub async fn eventer<Sent, Received, TX, RX, FnRx, FnTx, FnRxFut, FnTxFut, W>(
+    tx: TX,
+    rx: RX,
+    window: Window,
+    fn_rx: FnRx,
+    fn_tx: FnTx,
+    watched: W,
+    counters: &Counters,
+) where
+    Sent: Hash + Clone + Serialize,
+    Received: Hash + Clone + DeserializeOwned,
+    TX: embedded_io_async::Write,
+    RX: embedded_io_async::Read,
+    FnRx: Fn() -> FnRxFut,
+    FnTx: Fn(Received) -> FnTxFut,
+    FnRxFut: Future<Output = TransmittedMessage<Sent>>,
+    FnTxFut: Future,
+    W: Idle,
+{
+    let mix_chan = Channel::new();
+    let ack_signal = Signal::new();
+
+    let mut sender = EventSender {
+        mix_chan: &mix_chan,
+        ack_signal: &ack_signal,
+        counters,
+        window,
+        in_flight: Deque::new(),
+        next_id: 0,
+        state: if window.is_stop_and_wait() {
+            LinkState::Up
+        } else {
+            LinkState::Syncing {
+                attempts: 0,
+                deadline: Instant::now(),
+            }
+        },
+        resyncing: false,
+    };
+
+    let mut out_processor = EventOutProcessor::<Sent, TX> {
+        tx,
+        mix_chan: &mix_chan,
+        counters,
+    };
+
+    let mut in_processor = EventInProcessor::<Sent, RX, FnTx, W> {
+        rx,
+        out_cb: fn_tx,
+        watched,
+        window,
+        mix_chan: &mix_chan,
+        ack_signal: &ack_signal,
+        counters,
+    };
+
+    select3(
+        sender.task(fn_rx),
+        out_processor.task(),
+        in_processor.task(),
+    )
+    .await;
+}
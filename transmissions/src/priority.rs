@@ -0,0 +1,54 @@
+//! Ordering the messages waiting to be sent
+//!
+//! Links are fed from an [`embassy_sync::priority_channel::PriorityChannel`],
+//! which hands out the lowest [`PrioritisedMessage`] first. The heap behind it
+//! doesn't keep messages of the same priority in the order they were queued,
+//! so each message is numbered as it's made and ties go to the older one.
+
+use core::cmp::Ordering;
+
+use portable_atomic::AtomicU32;
+
+static NEXT_SEQ: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Debug)]
+pub struct PrioritisedMessage<T> {
+    pub msg: T,
+    /// Lower goes first
+    pub priority: u8,
+    seq: u32,
+}
+
+impl<T> PrioritisedMessage<T> {
+    pub fn new(msg: T, priority: u8) -> Self {
+        Self {
+            msg,
+            priority,
+            seq: NEXT_SEQ.fetch_add(1, portable_atomic::Ordering::Relaxed),
+        }
+    }
+}
+
+impl<T> Eq for PrioritisedMessage<T> {}
+
+impl<T> Ord for PrioritisedMessage<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // compared as a difference so that wrapping around doesn't reorder the
+        // few messages queued at once
+        let age = (self.seq.wrapping_sub(other.seq) as i32).cmp(&0);
+
+        self.priority.cmp(&other.priority).then(age)
+    }
+}
+
+impl<T> PartialEq for PrioritisedMessage<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> PartialOrd for PrioritisedMessage<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
@@ -0,0 +1,247 @@
+//! In-memory wires for running eventers against each other on the host, with
+//! faults injected into the bytes on the way through
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    convert::Infallible,
+    future::{pending, poll_fn, Future},
+    rc::Rc,
+    task::{Poll, Waker},
+};
+
+use dilemma_transmissions::{eventer, Counters, Delivery, TransmittedMessage, Window};
+use embassy_futures::select::{select3, Either3};
+use embassy_sync::{
+    blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex},
+    channel::Channel,
+    signal::Signal,
+};
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Faults {
+    /// Chance of each byte having one of its bits flipped
+    pub bit_flip: f64,
+    /// Chance of each byte going missing
+    pub drop: f64,
+    /// Chance of each byte arriving twice
+    pub duplicate: f64,
+    /// How long bytes take to arrive
+    pub latency: Duration,
+}
+
+/// xorshift, so a failing run can be repeated
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn chance(&mut self, p: f64) -> bool {
+        ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    /// Bytes along with when they arrive
+    bytes: VecDeque<(Instant, u8)>,
+    waker: Option<Waker>,
+}
+
+pub struct WireTx {
+    queue: Rc<RefCell<Queue>>,
+    faults: Rc<Cell<Faults>>,
+    rng: Rng,
+}
+
+pub struct WireRx {
+    queue: Rc<RefCell<Queue>>,
+}
+
+/// One direction of a link, the faults can be changed while it's in use
+pub fn wire(faults: &Rc<Cell<Faults>>, seed: u64) -> (WireTx, WireRx) {
+    let queue = Rc::new(RefCell::new(Queue::default()));
+
+    let tx = WireTx {
+        queue: queue.clone(),
+        faults: faults.clone(),
+        rng: Rng(seed | 1),
+    };
+
+    (tx, WireRx { queue })
+}
+
+impl embedded_io_async::ErrorType for WireTx {
+    type Error = Infallible;
+}
+
+impl embedded_io_async::Write for WireTx {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        let faults = self.faults.get();
+        let arrives = Instant::now() + faults.latency;
+        let mut queue = self.queue.borrow_mut();
+
+        for &byte in buf {
+            if self.rng.chance(faults.drop) {
+                continue;
+            }
+
+            let byte = if self.rng.chance(faults.bit_flip) {
+                byte ^ (1 << (self.rng.next() % 8))
+            } else {
+                byte
+            };
+
+            queue.bytes.push_back((arrives, byte));
+
+            if self.rng.chance(faults.duplicate) {
+                queue.bytes.push_back((arrives, byte));
+            }
+        }
+
+        if let Some(waker) = queue.waker.take() {
+            waker.wake();
+        }
+
+        Ok(buf.len())
+    }
+}
+
+impl embedded_io_async::ErrorType for WireRx {
+    type Error = Infallible;
+}
+
+impl embedded_io_async::Read for WireRx {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        loop {
+            let next = poll_fn(|cx| {
+                let mut queue = self.queue.borrow_mut();
+                match queue.bytes.front() {
+                    Some(&(arrives, _)) => Poll::Ready(arrives),
+                    None => {
+                        queue.waker = Some(cx.waker().clone());
+                        Poll::Pending
+                    }
+                }
+            })
+            .await;
+
+            Timer::at(next).await;
+
+            let now = Instant::now();
+            let mut queue = self.queue.borrow_mut();
+            let mut n = 0;
+
+            while n < buf.len() {
+                match queue.bytes.front() {
+                    Some(&(arrives, byte)) if arrives <= now => {
+                        buf[n] = byte;
+                        queue.bytes.pop_front();
+                        n += 1;
+                    }
+                    _ => break,
+                }
+            }
+
+            if n > 0 {
+                return Ok(n);
+            }
+        }
+    }
+}
+
+/// Two eventers joined by a wire each way, one sending `u32`s to the other
+pub struct Link {
+    pub faults: Rc<Cell<Faults>>,
+    window: Window,
+    outbox: Channel<NoopRawMutex, TransmittedMessage<u32>, 16>,
+    delivered: RefCell<Vec<u32>>,
+    /// The sending end's
+    pub counters: Counters,
+}
+
+impl Link {
+    pub fn new(window: Window, faults: Faults) -> Self {
+        Self {
+            faults: Rc::new(Cell::new(faults)),
+            window,
+            outbox: Channel::new(),
+            delivered: RefCell::new(Vec::new()),
+            counters: Counters::new(),
+        }
+    }
+
+    /// Run the link alongside `test` until it finishes
+    pub fn run<T>(&self, test: impl Future<Output = T>) -> T {
+        let (a_tx, b_rx) = wire(&self.faults, 0x5eed);
+        let (b_tx, a_rx) = wire(&self.faults, 0xfeed);
+        let counters = Counters::new();
+
+        let a = eventer(
+            a_tx,
+            a_rx,
+            self.window,
+            || self.outbox.receive(),
+            |_: u32| async {},
+            (),
+            &self.counters,
+        );
+        let b = eventer(
+            b_tx,
+            b_rx,
+            self.window,
+            pending::<TransmittedMessage<u32>>,
+            |msg: u32| async move { self.delivered.borrow_mut().push(msg) },
+            (),
+            &counters,
+        );
+
+        match futures::executor::block_on(select3(a, b, test)) {
+            Either3::Third(result) => result,
+            _ => unreachable!("the eventers run forever"),
+        }
+    }
+
+    pub async fn send(&self, msg: TransmittedMessage<u32>) {
+        self.outbox.send(msg).await;
+    }
+
+    /// Wait for `n` messages to have been delivered, then a bit longer for
+    /// any that shouldn't have been
+    pub async fn wait_for(&self, n: usize, limit: Duration) {
+        let _ = with_timeout(limit, async {
+            while self.delivered.borrow().len() < n {
+                Timer::after_millis(1).await;
+            }
+        })
+        .await;
+
+        Timer::after_millis(20).await;
+    }
+
+    /// Wait for `n` messages to have been delivered, returning how long that
+    /// took
+    pub async fn time_to(&self, n: usize) -> Duration {
+        let start = Instant::now();
+        while self.delivered.borrow().len() < n {
+            Timer::after_micros(100).await;
+        }
+
+        start.elapsed()
+    }
+
+    pub fn delivered(&self) -> Vec<u32> {
+        self.delivered.borrow().clone()
+    }
+}
+
+/// Somewhere for the eventer to say what happened to a message
+pub fn delivery() -> &'static Signal<CriticalSectionRawMutex, Delivery> {
+    Box::leak(Box::new(Signal::new()))
+}
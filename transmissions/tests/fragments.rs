@@ -0,0 +1,146 @@
+use dilemma_transmissions::{
+    fragments::{fragments, Reassembler},
+    priority::PrioritisedMessage,
+};
+use embassy_sync::{
+    blocking_mutex::raw::NoopRawMutex,
+    priority_channel::{Min, PriorityChannel},
+};
+use embassy_time::Duration;
+use shared::fragment::{Fragment, FRAGMENT_LEN};
+
+const TIMEOUT: Duration = Duration::from_millis(50);
+
+fn payload(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7) as u8).collect()
+}
+
+/// Push each fragment in turn, returning the messages that came out
+fn reassemble(
+    r: &mut Reassembler<1024>,
+    fragments: impl IntoIterator<Item = Fragment>,
+) -> Vec<Vec<u8>> {
+    fragments
+        .into_iter()
+        .filter_map(|f| r.push(f).map(<[u8]>::to_vec))
+        .collect()
+}
+
+#[test]
+fn round_trips() {
+    let mut r = Reassembler::<1024>::new(TIMEOUT);
+
+    for (transfer, len) in [0, 1, FRAGMENT_LEN, FRAGMENT_LEN + 1, 1000, 1024]
+        .into_iter()
+        .enumerate()
+    {
+        let payload = payload(len);
+        let f = fragments(transfer as u8, &payload).collect::<Vec<_>>();
+
+        assert_eq!(f.len(), len.div_ceil(FRAGMENT_LEN).max(1));
+        assert!(f.iter().all(|g| g.count as usize == f.len()));
+        assert_eq!(reassemble(&mut r, f), vec![payload]);
+    }
+}
+
+#[test]
+fn repeats_are_ignored() {
+    let mut r = Reassembler::<1024>::new(TIMEOUT);
+    let payload = payload(200);
+
+    let f = fragments(1, &payload).flat_map(|f| [f.clone(), f]);
+
+    assert_eq!(reassemble(&mut r, f), vec![payload]);
+}
+
+#[test]
+fn a_message_missing_a_fragment_is_thrown_away() {
+    let mut r = Reassembler::<1024>::new(TIMEOUT);
+    let first = payload(200);
+    let second = payload(300);
+
+    let f = fragments(1, &first)
+        .filter(|f| f.index != 1)
+        .chain(fragments(2, &second));
+
+    assert_eq!(reassemble(&mut r, f), vec![second]);
+}
+
+#[test]
+fn a_new_message_replaces_an_unfinished_one() {
+    let mut r = Reassembler::<1024>::new(TIMEOUT);
+    let first = payload(200);
+    let second = payload(300);
+
+    let f = fragments(1, &first)
+        .take(2)
+        .chain(fragments(2, &second))
+        // what's left of the first arriving late
+        .chain(fragments(1, &first).skip(2));
+
+    assert_eq!(reassemble(&mut r, f), vec![second]);
+}
+
+#[test]
+fn a_message_that_stops_arriving_is_given_up_on() {
+    let mut r = Reassembler::<1024>::new(TIMEOUT);
+    let payload = payload(200);
+    let mut f = fragments(1, &payload);
+
+    assert_eq!(
+        reassemble(&mut r, f.by_ref().take(2)),
+        Vec::<Vec<u8>>::new()
+    );
+    std::thread::sleep((TIMEOUT * 2).into());
+    assert_eq!(reassemble(&mut r, f), Vec::<Vec<u8>>::new());
+
+    // and the next one still gets through
+    assert_eq!(reassemble(&mut r, fragments(2, &payload)), vec![payload]);
+}
+
+#[test]
+fn a_message_too_big_is_thrown_away() {
+    let mut r = Reassembler::<1024>::new(TIMEOUT);
+    let big = payload(1025);
+    let small = payload(10);
+
+    let f = fragments(1, &big).chain(fragments(2, &small));
+
+    assert_eq!(reassemble(&mut r, f), vec![small]);
+}
+
+#[test]
+fn survive_a_priority_channel() {
+    let mut r = Reassembler::<1024>::new(TIMEOUT);
+    let channel =
+        PriorityChannel::<NoopRawMutex, PrioritisedMessage<Option<Fragment>>, Min, 16>::new();
+    let payload = payload(1000);
+    let mut f = fragments(1, &payload).peekable();
+    let mut received = Vec::new();
+
+    // fill the queue with fragments and more urgent messages, taking a few out
+    // each time so that the heap gets shuffled
+    loop {
+        for fragment in f.by_ref().take(12) {
+            channel
+                .try_send(PrioritisedMessage::new(Some(fragment), 4))
+                .unwrap();
+        }
+        for _ in 0..3 {
+            channel.try_send(PrioritisedMessage::new(None, 1)).unwrap();
+        }
+        for _ in 0..8 {
+            received.extend(channel.try_receive().ok().and_then(|m| m.msg));
+        }
+
+        if f.peek().is_none() {
+            break;
+        }
+    }
+    while let Ok(m) = channel.try_receive() {
+        received.extend(m.msg);
+    }
+    drop(f);
+
+    assert_eq!(reassemble(&mut r, received), vec![payload]);
+}
@@ -0,0 +1,505 @@
+mod common;
+
+use std::{
+    cell::{Cell, RefCell},
+    future::pending,
+    rc::Rc,
+    sync::atomic::Ordering::Relaxed,
+};
+
+use common::{delivery, wire, Faults, Link, WireRx, WireTx};
+use dilemma_transmissions::{
+    eventer, reliable_msg, Counters, TransmittedMessage, Undelivered, Window, MAX_ATTEMPTS,
+};
+use embassy_futures::select::{select, Either};
+use embassy_time::{with_timeout, Duration, Timer};
+use embedded_io_async::{Read, Write};
+use shared::cmd::{calc_csum, CmdOrAck, Command, CommandSeq};
+
+/// What the host speaks, and what the halves speak to each other
+const WINDOWS: [Window; 2] = [Window::STOP_AND_WAIT, Window::new(8)];
+
+fn send_all(link: &Link, n: u32) -> Vec<u32> {
+    link.run(async {
+        for i in 0..n {
+            link.send(reliable_msg(i)).await;
+        }
+
+        link.wait_for(n as usize, Duration::from_secs(10)).await;
+    });
+
+    link.delivered()
+}
+
+#[test]
+fn delivers_in_order() {
+    for window in WINDOWS {
+        let link = Link::new(window, Faults::default());
+
+        assert_eq!(send_all(&link, 300), (0..300).collect::<Vec<_>>());
+
+        // at least one frame each way for every message, and a round trip timed
+        // for each one that wasn't sent again, which a slow test machine can
+        // cause. Each one that was accounts for at least one retry
+        let stats = link.counters.snapshot();
+        assert!(stats.frames_sent >= 300, "{stats:?}");
+        assert!(stats.frames_received >= 300, "{stats:?}");
+        let timed = stats.rtt.iter().sum::<u32>();
+        assert!(timed <= 300, "{stats:?}");
+        assert!(timed + stats.retries >= 300, "{stats:?}");
+    }
+}
+
+fn lossy() -> Faults {
+    Faults {
+        drop: 0.01,
+        duplicate: 0.01,
+        ..Faults::default()
+    }
+}
+
+#[test]
+fn exactly_once_over_a_lossy_link_between_the_halves() {
+    let link = Link::new(Window::new(8), lossy());
+
+    assert_eq!(send_all(&link, 300), (0..300).collect::<Vec<_>>());
+}
+
+#[test]
+fn in_order_over_a_lossy_link_to_the_host() {
+    let link = Link::new(Window::STOP_AND_WAIT, lossy());
+
+    let delivered = send_all(&link, 300);
+
+    // the host's acks don't say which message they're for, so a repeated ack
+    // arriving after the next message went out passes for its ack and that
+    // message is skipped, but nothing arrives twice or out of order
+    assert!(delivered.windows(2).all(|w| w[0] < w[1]), "{delivered:?}");
+    assert!(delivered.len() > 290, "{delivered:?}");
+}
+
+#[test]
+fn exactly_once_with_acks_arriving_after_the_timeout() {
+    for window in WINDOWS {
+        // a round trip takes longer than a reliable message waits for its ack,
+        // so everything is sent more than once
+        let link = Link::new(
+            window,
+            Faults {
+                latency: Duration::from_millis(3),
+                ..Faults::default()
+            },
+        );
+
+        assert_eq!(send_all(&link, 50), (0..50).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn corrupted_messages_are_never_delivered() {
+    let link = Link::new(
+        Window::STOP_AND_WAIT,
+        Faults {
+            bit_flip: 0.02,
+            ..Faults::default()
+        },
+    );
+
+    let delivered = send_all(&link, 300);
+
+    // the host's checksum doesn't cover the sequence number, so a flip there
+    // can repeat a message or have the next one dropped as a repeat, but
+    // nothing that wasn't sent gets through
+    assert!(delivered.iter().all(|&m| m < 300), "{delivered:?}");
+    assert!(delivered.windows(2).all(|w| w[0] <= w[1]), "{delivered:?}");
+    assert!(delivered.len() > 250, "{delivered:?}");
+}
+
+#[test]
+fn exactly_once_over_a_corrupting_link_between_the_halves() {
+    let link = Link::new(
+        Window::new(8),
+        Faults {
+            bit_flip: 0.02,
+            ..Faults::default()
+        },
+    );
+
+    assert_eq!(send_all(&link, 300), (0..300).collect::<Vec<_>>());
+
+    let stats = link.counters.snapshot();
+    assert!(stats.retries > 0, "{stats:?}");
+    assert!(stats.nacks_received > 0, "{stats:?}");
+    assert!(
+        stats.decode_failures + stats.checksum_failures > 0,
+        "{stats:?}"
+    );
+}
+
+#[test]
+fn recovers_after_sustained_corruption() {
+    for window in WINDOWS {
+        let link = Link::new(
+            window,
+            Faults {
+                bit_flip: 0.5,
+                ..Faults::default()
+            },
+        );
+
+        let during = link.run(async {
+            let send = async {
+                for i in 0..10 {
+                    link.send(reliable_msg(i)).await;
+                }
+                pending::<()>().await;
+            };
+
+            // the sender retries until it gives up and takes the link to be
+            // down, so these may or may not get through
+            let _ = select(send, Timer::after_millis(300)).await;
+            let during = link.delivered();
+
+            // long enough for whatever was left to be sent or dropped, and for
+            // a link with a window to find the other end again
+            link.faults.set(Faults::default());
+            Timer::after_millis(300).await;
+            let before = link.delivered().len();
+
+            for i in 10..20 {
+                link.send(reliable_msg(i)).await;
+            }
+            link.wait_for(before + 10, Duration::from_secs(10)).await;
+
+            (during, before)
+        });
+
+        let (during, before) = during;
+        let delivered = link.delivered();
+        assert!(during.iter().all(|&m| m < 10), "{during:?}");
+        assert_eq!(delivered[before..], (10..20).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn gives_up_when_nothing_gets_through() {
+    for window in WINDOWS {
+        let link = Link::new(
+            window,
+            Faults {
+                drop: 1.0,
+                ..Faults::default()
+            },
+        );
+
+        link.run(async {
+            let delivery = delivery();
+            link.send(reliable_msg(0).reporting_to(delivery)).await;
+
+            let result = with_timeout(Duration::from_secs(1), delivery.wait())
+                .await
+                .expect("never gave up");
+
+            if window == Window::STOP_AND_WAIT {
+                assert_eq!(result, Err(Undelivered::GaveUp));
+                assert_eq!(link.counters.retries.load(Relaxed), MAX_ATTEMPTS as u32 - 1);
+            } else {
+                // the other end never answered the sync, so it wasn't sent
+                assert_eq!(result, Err(Undelivered::LinkDown));
+            }
+
+            // the link is down, so nothing waits around for an ack that isn't
+            // coming and the queue keeps moving
+            with_timeout(Duration::from_secs(1), async {
+                for i in 1..100 {
+                    link.send(reliable_msg(i)).await;
+                }
+                while link.counters.failures.load(Relaxed) < 100 {
+                    Timer::after_millis(1).await;
+                }
+            })
+            .await
+            .expect("backed up");
+        });
+
+        assert!(link.delivered().is_empty());
+    }
+}
+
+#[test]
+fn comes_back_after_the_other_end_does() {
+    for window in WINDOWS {
+        let link = Link::new(
+            window,
+            Faults {
+                drop: 1.0,
+                ..Faults::default()
+            },
+        );
+
+        link.run(async {
+            let delivery = delivery();
+            link.send(reliable_msg(0).reporting_to(delivery)).await;
+            assert!(delivery.wait().await.is_err());
+
+            link.faults.set(Faults::default());
+            Timer::after_millis(200).await;
+
+            link.send(reliable_msg(1).reporting_to(delivery)).await;
+            assert_eq!(delivery.wait().await, Ok(()));
+        });
+
+        assert_eq!(link.delivered(), [1]);
+    }
+}
+
+#[test]
+fn several_messages_in_flight() {
+    let faults = Faults {
+        latency: Duration::from_millis(1),
+        ..Faults::default()
+    };
+
+    let time_to_send = |window| {
+        let link = Link::new(window, faults);
+
+        link.run(async {
+            let send = async {
+                for i in 0..40 {
+                    link.send(TransmittedMessage {
+                        msg: i,
+                        timeout: Some(Duration::from_millis(20)),
+                        report: None,
+                    })
+                    .await;
+                }
+                pending::<()>().await;
+            };
+
+            match select(send, link.time_to(40)).await {
+                Either::Second(elapsed) => elapsed,
+                Either::First(()) => unreachable!(),
+            }
+        })
+    };
+
+    // each message waits for the round trip before the last, so 80ms
+    let one_at_a_time = time_to_send(Window::STOP_AND_WAIT);
+    let windowed = time_to_send(Window::new(8));
+
+    assert!(
+        windowed * 3 < one_at_a_time,
+        "{windowed:?} vs {one_at_a_time:?}"
+    );
+}
+
+/// Frame a command for the eventer under test
+fn frame(cmd: &CmdOrAck<u32>) -> Vec<u8> {
+    let mut buf = [0u8; 32];
+    postcard::to_slice_cobs(cmd, &mut buf).unwrap().to_vec()
+}
+
+/// The other end of the wires to an eventer
+struct Peer {
+    tx: WireTx,
+    rx: WireRx,
+}
+
+impl Peer {
+    /// Send a frame and wait for the reply, skipping the syncs from an
+    /// eventer with a window which is waiting to send its own messages
+    async fn exchange(&mut self, frame: Vec<u8>) -> CmdOrAck<u32> {
+        self.tx.write_all(&frame).await.unwrap();
+
+        loop {
+            match self.receive().await {
+                CmdOrAck::Sync { .. } => {}
+                reply => return reply,
+            }
+        }
+    }
+
+    async fn receive(&mut self) -> CmdOrAck<u32> {
+        let mut buf = Vec::new();
+        with_timeout(Duration::from_secs(1), async {
+            while buf.last() != Some(&0) {
+                let mut byte = [0];
+                self.rx.read_exact(&mut byte).await.unwrap();
+                buf.push(byte[0]);
+            }
+        })
+        .await
+        .expect("no reply");
+
+        postcard::from_bytes_cobs(&mut buf).unwrap()
+    }
+}
+
+/// Start an eventer with the other ends of its wires, delivering into
+/// `delivered`, and run `test` against it
+fn with_peer<F: std::future::Future<Output = ()>>(
+    window: Window,
+    delivered: &RefCell<Vec<u32>>,
+    test: impl FnOnce(Peer) -> F,
+) {
+    let faults = Rc::new(Cell::new(Faults::default()));
+    let (to_eventer, from_test) = wire(&faults, 1);
+    let (to_test, from_eventer) = wire(&faults, 2);
+
+    let counters = Counters::new();
+    let under_test = eventer(
+        to_test,
+        from_test,
+        window,
+        pending::<TransmittedMessage<u32>>,
+        |msg: u32| async move { delivered.borrow_mut().push(msg) },
+        (),
+        &counters,
+    );
+
+    let peer = Peer {
+        tx: to_eventer,
+        rx: from_eventer,
+    };
+
+    match futures::executor::block_on(select(under_test, test(peer))) {
+        Either::Second(()) => {}
+        Either::First(()) => unreachable!("the eventer runs forever"),
+    }
+}
+
+fn command(msg: u32, id: u8, csum: u16) -> CmdOrAck<u32> {
+    CmdOrAck::Cmd(Command {
+        command_seq: CommandSeq::new().with_id(id).with_reliable(true),
+        cmd: msg,
+        csum,
+    })
+}
+
+#[test]
+fn checksums_and_repeats() {
+    let delivered = &RefCell::new(Vec::new());
+
+    with_peer(Window::STOP_AND_WAIT, delivered, |mut peer| async move {
+        let good = calc_csum(7u32);
+
+        assert!(matches!(
+            peer.exchange(frame(&command(7, 1, good ^ 1))).await,
+            CmdOrAck::Nack
+        ));
+        assert!(delivered.borrow().is_empty());
+
+        assert!(matches!(
+            peer.exchange(frame(&command(7, 1, good))).await,
+            CmdOrAck::Ack
+        ));
+        assert_eq!(*delivered.borrow(), [7]);
+
+        // the ack was lost, so the sender tries again
+        assert!(matches!(
+            peer.exchange(frame(&command(7, 1, good))).await,
+            CmdOrAck::Ack
+        ));
+        assert_eq!(*delivered.borrow(), [7]);
+
+        // garbage gets a nack
+        assert!(matches!(peer.exchange(vec![2, 9, 0]).await, CmdOrAck::Nack));
+        assert_eq!(*delivered.borrow(), [7]);
+    });
+}
+
+fn sequenced(msg: u32, id: u8) -> CmdOrAck<u32> {
+    CmdOrAck::Cmd(Command::new_sequenced(
+        msg,
+        CommandSeq::new().with_id(id).with_reliable(true),
+    ))
+}
+
+/// The id acknowledged by an ack from a windowed link
+fn acked(ack: CmdOrAck<u32>) -> u8 {
+    match ack {
+        ack @ CmdOrAck::AckUpto { id, .. } if ack.validate_id() => id,
+        _ => panic!("{ack:?} isn't an ack"),
+    }
+}
+
+#[test]
+fn windowed_acks_and_repeats() {
+    let delivered = &RefCell::new(Vec::new());
+
+    with_peer(Window::new(8), delivered, |mut peer| async move {
+        assert_eq!(acked(peer.exchange(frame(&CmdOrAck::sync(5))).await), 4);
+
+        assert_eq!(acked(peer.exchange(frame(&sequenced(50, 5))).await), 5);
+        assert_eq!(*delivered.borrow(), [50]);
+
+        // 6 went missing, so 7 is dropped until 6 is sent again
+        assert_eq!(acked(peer.exchange(frame(&sequenced(70, 7))).await), 5);
+        assert_eq!(acked(peer.exchange(frame(&sequenced(60, 6))).await), 6);
+        assert_eq!(acked(peer.exchange(frame(&sequenced(70, 7))).await), 7);
+        assert_eq!(*delivered.borrow(), [50, 60, 70]);
+
+        // a late repeat is acked but not delivered again
+        assert_eq!(acked(peer.exchange(frame(&sequenced(60, 6))).await), 7);
+        assert_eq!(*delivered.borrow(), [50, 60, 70]);
+
+        // the checksum covers the id
+        let mut moved = sequenced(80, 8);
+        if let CmdOrAck::Cmd(c) = &mut moved {
+            c.command_seq.set_id(9);
+        }
+        assert!(matches!(peer.exchange(frame(&moved)).await, CmdOrAck::Nack));
+
+        // the sender restarted
+        assert_eq!(acked(peer.exchange(frame(&CmdOrAck::sync(0))).await), 127);
+        assert_eq!(acked(peer.exchange(frame(&sequenced(1, 0))).await), 0);
+        assert_eq!(*delivered.borrow(), [50, 60, 70, 1]);
+    });
+}
+
+#[test]
+fn windowed_sender_syncs_first() {
+    let faults = Rc::new(Cell::new(Faults::default()));
+    let (to_eventer, from_test) = wire(&faults, 1);
+    let (to_test, from_eventer) = wire(&faults, 2);
+    let outbox = embassy_sync::channel::Channel::<
+        embassy_sync::blocking_mutex::raw::NoopRawMutex,
+        TransmittedMessage<u32>,
+        4,
+    >::new();
+
+    let counters = Counters::new();
+    let under_test = eventer(
+        to_test,
+        from_test,
+        Window::new(8),
+        || outbox.receive(),
+        |_: u32| async {},
+        (),
+        &counters,
+    );
+
+    let mut peer = Peer {
+        tx: to_eventer,
+        rx: from_eventer,
+    };
+
+    let test = async {
+        outbox.send(reliable_msg(3)).await;
+
+        // nothing is sent until the sync is answered
+        let CmdOrAck::Sync { id, .. } = peer.receive().await else {
+            panic!("expected a sync");
+        };
+        assert!(matches!(
+            peer.exchange(frame(&CmdOrAck::ack_upto(id.wrapping_sub(1) & 127)))
+                .await,
+            CmdOrAck::Cmd(c) if c.cmd == 3 && c.command_seq.id() == id && c.validate_sequenced()
+        ));
+    };
+
+    match futures::executor::block_on(select(under_test, test)) {
+        Either::Second(()) => {}
+        Either::First(()) => unreachable!("the eventer runs forever"),
+    }
+}